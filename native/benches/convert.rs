@@ -0,0 +1,200 @@
+//! Benchmarks of the conversions, run with `cargo bench`
+//!
+//! Pass `--export-json <path>` to also write the estimates of the benchmarks that ran to a JSON file, for tracking
+//! regressions between runs. For example `cargo bench -- --export-json bench.json`
+
+use atem_connection_image_tools::api::{
+  capabilities, decode_rle, decode_yuva_422_to_rgba, encode_rgba_to_yuva_422, encode_rle,
+  DecodeOptions, EncodeOptions,
+};
+use criterion::{black_box, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// The resolutions the encoder is measured at
+const RESOLUTIONS: [(&str, u32, u32); 4] = [
+  ("720p", 1280, 720),
+  ("1080p", 1920, 1080),
+  ("2160p", 3840, 2160),
+  ("4320p", 7680, 4320),
+];
+
+/// Every benchmark, as the group, the id, and the number of pixels processed per iteration
+const BENCHMARKS: [(&str, &str, u64); 8] = [
+  ("encode", "720p", 1280 * 720),
+  ("encode", "1080p", 1920 * 1080),
+  ("encode", "2160p", 3840 * 2160),
+  ("encode", "4320p", 7680 * 4320),
+  ("decode", "flat", 1920 * 1080),
+  ("decode", "rle_heavy", 1920 * 1080),
+  ("rle", "encode", 1920 * 1080),
+  ("rle", "decode", 1920 * 1080),
+];
+
+/// Deterministic noise, which has no runs for the RLE encoder to find
+fn noise(len: usize) -> Vec<u8> {
+  let mut state = 0x1234_5678u32;
+  (0..len)
+    .map(|_| {
+      state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+      (state >> 24) as u8
+    })
+    .collect()
+}
+
+/// Eight flat bars with a noisy strip along the bottom, like a typical graphic with large areas of solid colour
+fn bars(width: u32, height: u32) -> Vec<u8> {
+  let colours: [[u8; 4]; 8] = [
+    [235, 235, 235, 255],
+    [235, 235, 16, 255],
+    [16, 235, 235, 255],
+    [16, 235, 16, 255],
+    [235, 16, 235, 255],
+    [235, 16, 16, 255],
+    [16, 16, 235, 255],
+    [16, 16, 16, 0],
+  ];
+  let strip = height - height / 16;
+  let mut image = noise((width * height * 4) as usize);
+  for y in 0..strip {
+    for x in 0..width {
+      let offset = ((y * width + x) * 4) as usize;
+      image[offset..offset + 4].copy_from_slice(&colours[(x * 8 / width) as usize]);
+    }
+  }
+  image
+}
+
+fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+  let mut output = vec![0; (width * height * 4) as usize];
+  encode_rgba_to_yuva_422(width, height, rgba, &mut output, &EncodeOptions::default()).unwrap();
+  output
+}
+
+fn bench_encode(c: &mut Criterion) {
+  let mut group = c.benchmark_group("encode");
+  // The largest images take a significant fraction of a second each
+  group.sample_size(10);
+  group.measurement_time(Duration::from_secs(10));
+
+  for (name, width, height) in RESOLUTIONS {
+    let input = noise((width * height * 4) as usize);
+    let mut output = vec![0; (width * height * 4) as usize];
+    let options = EncodeOptions::default();
+
+    group.throughput(Throughput::Elements((width * height) as u64));
+    group.bench_function(BenchmarkId::from_parameter(name), |b| {
+      b.iter(|| encode_rgba_to_yuva_422(width, height, black_box(&input), &mut output, &options))
+    });
+  }
+
+  group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+  let (width, height) = (1920, 1080);
+  let flat = encode(width, height, &noise((width * height * 4) as usize));
+  let rle_heavy = encode_rle(&encode(width, height, &bars(width, height)));
+  let mut output = vec![0; (width * height * 4) as usize];
+  let options = DecodeOptions::default();
+
+  let mut group = c.benchmark_group("decode");
+  group.throughput(Throughput::Elements((width * height) as u64));
+  for (name, input) in [("flat", &flat), ("rle_heavy", &rle_heavy)] {
+    group.bench_function(BenchmarkId::from_parameter(name), |b| {
+      b.iter(|| decode_yuva_422_to_rgba(width, height, black_box(input), &mut output, &options))
+    });
+  }
+  group.finish();
+}
+
+fn bench_rle(c: &mut Criterion) {
+  let (width, height) = (1920, 1080);
+  let flat = encode(width, height, &bars(width, height));
+  let compressed = encode_rle(&flat);
+
+  let mut group = c.benchmark_group("rle");
+  group.throughput(Throughput::Elements((width * height) as u64));
+  group.bench_function("encode", |b| b.iter(|| encode_rle(black_box(&flat))));
+  group.bench_function("decode", |b| b.iter(|| decode_rle(black_box(&compressed))));
+  group.finish();
+}
+
+/// The directory criterion writes its estimates to, which is `target/criterion` unless the target is moved
+fn criterion_directory() -> PathBuf {
+  std::env::var_os("CARGO_TARGET_DIR")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("target"))
+    .join("criterion")
+}
+
+/// Collect the estimates criterion saved for each benchmark that ran since `started`, in nanoseconds per iteration
+///
+/// Benchmarks excluded by the filter keep the estimates of an earlier run, which must not be reported as this one's
+fn export_json(path: &str, started: SystemTime) {
+  let directory = criterion_directory();
+  let benchmarks: Vec<Value> = BENCHMARKS
+    .iter()
+    .filter_map(|&(group, id, pixels)| {
+      let estimates = directory.join(group).join(id).join("new/estimates.json");
+      if std::fs::metadata(&estimates).ok()?.modified().ok()? < started {
+        return None;
+      }
+      let estimates: Value = serde_json::from_slice(&std::fs::read(estimates).ok()?).ok()?;
+      let mean = estimates["mean"]["point_estimate"].as_f64()?;
+
+      Some(json!({
+        "id": format!("{group}/{id}"),
+        "mean_ns": mean,
+        "median_ns": estimates["median"]["point_estimate"],
+        "std_dev_ns": estimates["std_dev"]["point_estimate"],
+        "pixels": pixels,
+        "megapixels_per_second": pixels as f64 * 1000.0 / mean,
+      }))
+    })
+    .collect();
+
+  let exported = benchmarks.len();
+  let capabilities = capabilities();
+  let report = json!({
+    "simd_path": format!("{:?}", capabilities.simd_path).to_lowercase(),
+    "arch": capabilities.arch,
+    "lut_decode": capabilities.lut_decode,
+    "benchmarks": benchmarks,
+  });
+  std::fs::write(path, serde_json::to_string_pretty(&report).unwrap())
+    .unwrap_or_else(|err| panic!("Unable to write {path}: {err}"));
+  println!("Exported {exported} benchmarks to {path}");
+}
+
+fn main() {
+  // criterion rejects arguments it doesn't know, so it is only given the command line when not exporting
+  let mut args = std::env::args().skip(1);
+  let mut export = None;
+  let mut filter = None;
+  while let Some(arg) = args.next() {
+    if arg == "--export-json" {
+      export = Some(args.next().expect("--export-json needs a path"));
+    } else if let Some(path) = arg.strip_prefix("--export-json=") {
+      export = Some(path.to_string());
+    } else if !arg.starts_with('-') {
+      filter = Some(arg);
+    }
+  }
+
+  let mut criterion = match (&export, filter) {
+    (None, _) => Criterion::default().configure_from_args(),
+    (Some(_), Some(filter)) => Criterion::default().with_filter(filter),
+    (Some(_), None) => Criterion::default(),
+  };
+  let started = SystemTime::now();
+  bench_encode(&mut criterion);
+  bench_decode(&mut criterion);
+  bench_rle(&mut criterion);
+  criterion.final_summary();
+
+  if let Some(path) = export {
+    export_json(&path, started);
+  }
+}
@@ -0,0 +1,6 @@
+#![no_main]
+
+use atem_connection_image_tools::fuzz;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| fuzz::rle_decode(data));
@@ -0,0 +1,583 @@
+use crate::convert::{check_arithmetic, encode_row, RowLayout};
+use crate::error::{Error, Result};
+use crate::legalise::R103Limits;
+use crate::options::{EncodeFormat, VideoRange};
+use crate::rle::RLEDecoder;
+use crate::yuv_constants::YuvConstantsSimd;
+use napi::bindgen_prelude::Uint32Array;
+
+/// The number of bins in each histogram, one for each 10-bit code
+const HISTOGRAM_BINS: usize = 1024;
+
+/// The bounding box of a connected region of pixels outside of the EBU R103 limits
+#[napi(object)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct IllegalRegion {
+  pub x: u32,
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+  /// The number of illegal pixels in the region
+  pub pixel_count: u32,
+}
+
+/// Statistics about the alpha of an image
+#[napi(object)]
+#[derive(Debug, PartialEq)]
+pub struct AlphaStatistics {
+  /// The smallest alpha code
+  pub min: u32,
+  /// The largest alpha code
+  pub max: u32,
+  /// The mean alpha, as a fraction of the nominal range from transparent to opaque
+  pub mean: f64,
+  /// The number of pixels at or above nominal opaque
+  pub opaque_pixels: u32,
+  /// The number of pixels at or below nominal transparent
+  pub transparent_pixels: u32,
+  /// Whether every pixel is opaque
+  pub fully_opaque: bool,
+  /// Whether every pixel is transparent
+  pub fully_transparent: bool,
+}
+
+/// A quality control report of the levels of a still image, as it would be sent to the ATEM
+#[napi(object)]
+pub struct StillAnalysis {
+  /// The number of pixels with each 10-bit luma code
+  pub luma_histogram: Uint32Array,
+  /// The number of pixel pairs with each 10-bit Cb code
+  pub cb_histogram: Uint32Array,
+  /// The number of pixel pairs with each 10-bit Cr code
+  pub cr_histogram: Uint32Array,
+  /// The number of pixels outside of the EBU R103 limits
+  pub illegal_pixels: u32,
+  /// The percentage of pixels outside of the EBU R103 limits
+  pub illegal_percentage: f64,
+  /// The bounding boxes of the largest connected regions of illegal pixels, largest first
+  pub illegal_regions: Vec<IllegalRegion>,
+  /// The total number of connected regions of illegal pixels, which may be more than are listed
+  pub illegal_region_count: u32,
+  pub alpha: AlphaStatistics,
+}
+
+/// The results of analysing an image, before conversion to the JS types
+pub(crate) struct Analysis {
+  pub luma_histogram: Vec<u32>,
+  pub cb_histogram: Vec<u32>,
+  pub cr_histogram: Vec<u32>,
+  pub illegal_pixels: u32,
+  pub illegal_percentage: f64,
+  pub illegal_regions: Vec<IllegalRegion>,
+  pub illegal_region_count: u32,
+  pub alpha: AlphaStatistics,
+}
+impl From<Analysis> for StillAnalysis {
+  fn from(analysis: Analysis) -> StillAnalysis {
+    StillAnalysis {
+      luma_histogram: analysis.luma_histogram.into(),
+      cb_histogram: analysis.cb_histogram.into(),
+      cr_histogram: analysis.cr_histogram.into(),
+      illegal_pixels: analysis.illegal_pixels,
+      illegal_percentage: analysis.illegal_percentage,
+      illegal_regions: analysis.illegal_regions,
+      illegal_region_count: analysis.illegal_region_count,
+      alpha: analysis.alpha,
+    }
+  }
+}
+
+/// Analyse an image, which is either flat or RLE compressed YUVA422, or RGBA to be encoded with `input`
+///
+/// RGBA is encoded exactly as it would be for sending to the ATEM, so the report describes the codes the ATEM receives
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn analyse_still(
+  constants: &YuvConstantsSimd,
+  range: VideoRange,
+  alpha_range: VideoRange,
+  width: u32,
+  height: u32,
+  data: &[u8],
+  input: Option<(EncodeFormat, RowLayout)>,
+  max_regions: usize,
+) -> Result<Analysis> {
+  let [kr, kb] = [constants.kr[0], constants.kb[0]];
+  let mut analyser = Analyser::new(kr, kb, range, alpha_range);
+  let mut row = vec![0; width as usize * 4];
+
+  match input {
+    Some((input_format, input_layout)) => {
+      if !input_layout.check_len(height, data.len()) {
+        return Err(Error::from_reason("Input buffer has incorrect length"));
+      }
+      check_arithmetic(constants, input_format.format, input_format.arithmetic)?;
+
+      for y in 0..height as usize {
+        let input_start = y * input_layout.stride;
+        let input_row = &data[input_start..input_start + input_layout.row_bytes];
+        encode_row(constants, input_format, 0, y, input_row, &mut row);
+        analyser.add_row(y as u32, &row);
+      }
+    }
+    None => {
+      let mut decoder = RLEDecoder::new(data);
+      for y in 0..height {
+        for sample in row.chunks_exact_mut(8) {
+          let Some(value) = decoder.read_sample() else {
+            return Err(Error::from_reason(
+              "Input buffer has less data than expected",
+            ));
+          };
+          sample.copy_from_slice(value);
+        }
+        analyser.add_row(y, &row);
+      }
+    }
+  }
+
+  Ok(analyser.finish(max_regions))
+}
+
+/// Accumulates the statistics of an image, one row at a time
+struct Analyser {
+  limits: R103Limits,
+  /// The alpha codes of nominal transparent and opaque
+  alpha_levels: [u32; 2],
+  luma_histogram: Vec<u32>,
+  cb_histogram: Vec<u32>,
+  cr_histogram: Vec<u32>,
+  pixel_count: u64,
+  illegal_pixels: u32,
+  regions: RegionTracker,
+  alpha_min: u32,
+  alpha_max: u32,
+  alpha_sum: u64,
+  opaque_pixels: u32,
+  transparent_pixels: u32,
+}
+impl Analyser {
+  fn new(kr: f32, kb: f32, range: VideoRange, alpha_range: VideoRange) -> Analyser {
+    Analyser {
+      limits: R103Limits::new(kr, kb, range),
+      alpha_levels: match alpha_range {
+        VideoRange::Limited => [64, 940],
+        VideoRange::Full => [0, 1023],
+      },
+      luma_histogram: vec![0; HISTOGRAM_BINS],
+      cb_histogram: vec![0; HISTOGRAM_BINS],
+      cr_histogram: vec![0; HISTOGRAM_BINS],
+      pixel_count: 0,
+      illegal_pixels: 0,
+      regions: RegionTracker::default(),
+      alpha_min: 1023,
+      alpha_max: 0,
+      alpha_sum: 0,
+      opaque_pixels: 0,
+      transparent_pixels: 0,
+    }
+  }
+
+  /// Add a flat YUVA422 row of the image
+  fn add_row(&mut self, y: u32, row: &[u8]) {
+    let mut run_start = None;
+
+    for (pair, sample) in row.chunks_exact(8).enumerate() {
+      let word1 = u32::from_be_bytes(sample[0..4].try_into().unwrap());
+      let word2 = u32::from_be_bytes(sample[4..8].try_into().unwrap());
+      let cb = (word1 >> 10) & 0x3ff;
+      let cr = (word2 >> 10) & 0x3ff;
+      self.cb_histogram[cb as usize] += 1;
+      self.cr_histogram[cr as usize] += 1;
+
+      for (i, word) in [word1, word2].into_iter().enumerate() {
+        let x = (pair * 2 + i) as u32;
+        let luma = word & 0x3ff;
+        let alpha = word >> 20;
+        self.luma_histogram[luma as usize] += 1;
+        self.add_alpha(alpha);
+
+        let legal = self.limits.is_legal(luma, cb, cr);
+        if !legal {
+          self.illegal_pixels += 1;
+        }
+        match (legal, run_start) {
+          (false, None) => run_start = Some(x),
+          (true, Some(start)) => {
+            self.regions.add_run(y, start, x);
+            run_start = None;
+          }
+          _ => {}
+        }
+      }
+    }
+
+    let width = (row.len() / 4) as u32;
+    if let Some(start) = run_start {
+      self.regions.add_run(y, start, width);
+    }
+    self.regions.end_row();
+    self.pixel_count += u64::from(width);
+  }
+
+  fn add_alpha(&mut self, alpha: u32) {
+    let [transparent, opaque] = self.alpha_levels;
+    self.alpha_min = self.alpha_min.min(alpha);
+    self.alpha_max = self.alpha_max.max(alpha);
+    self.alpha_sum += u64::from(alpha);
+    if alpha >= opaque {
+      self.opaque_pixels += 1;
+    }
+    if alpha <= transparent {
+      self.transparent_pixels += 1;
+    }
+  }
+
+  fn finish(self, max_regions: usize) -> Analysis {
+    let [transparent, opaque] = self.alpha_levels;
+    let (illegal_percentage, alpha_mean) = if self.pixel_count == 0 {
+      (0.0, 0.0)
+    } else {
+      let count = self.pixel_count as f64;
+      let mean_code = self.alpha_sum as f64 / count;
+      (
+        f64::from(self.illegal_pixels) * 100.0 / count,
+        (mean_code - f64::from(transparent)) / f64::from(opaque - transparent),
+      )
+    };
+
+    let mut illegal_regions = self.regions.into_regions();
+    let illegal_region_count = illegal_regions.len() as u32;
+    illegal_regions.truncate(max_regions);
+
+    let pixel_count = self.pixel_count as u32;
+    Analysis {
+      luma_histogram: self.luma_histogram,
+      cb_histogram: self.cb_histogram,
+      cr_histogram: self.cr_histogram,
+      illegal_pixels: self.illegal_pixels,
+      illegal_percentage,
+      illegal_regions,
+      illegal_region_count,
+      alpha: AlphaStatistics {
+        min: self.alpha_min.min(self.alpha_max),
+        max: self.alpha_max,
+        mean: alpha_mean,
+        opaque_pixels: self.opaque_pixels,
+        transparent_pixels: self.transparent_pixels,
+        fully_opaque: self.opaque_pixels == pixel_count,
+        fully_transparent: self.transparent_pixels == pixel_count,
+      },
+    }
+  }
+}
+
+/// A set of pixels connected to each other, including diagonally
+struct Component {
+  /// The component this was merged into, or itself if it is the root of a set
+  parent: usize,
+  left: u32,
+  top: u32,
+  /// One past the rightmost pixel
+  right: u32,
+  /// One past the bottom pixel
+  bottom: u32,
+  pixel_count: u32,
+}
+
+/// A run of pixels within a row, from `start` up to but excluding `end`
+struct Run {
+  start: u32,
+  end: u32,
+  component: usize,
+}
+
+/// Finds the connected regions of an image from runs of pixels, with a union-find over the runs
+#[derive(Default)]
+struct RegionTracker {
+  components: Vec<Component>,
+  previous_row: Vec<Run>,
+  current_row: Vec<Run>,
+  /// The first run of the previous row which could touch the next run of the current row
+  previous_index: usize,
+}
+impl RegionTracker {
+  /// Add a run to the current row. Runs must be added from left to right
+  fn add_run(&mut self, y: u32, start: u32, end: u32) {
+    let component = self.components.len();
+    self.components.push(Component {
+      parent: component,
+      left: start,
+      top: y,
+      right: end,
+      bottom: y + 1,
+      pixel_count: end - start,
+    });
+
+    // Runs touch if they overlap, or meet at a corner
+    while self
+      .previous_row
+      .get(self.previous_index)
+      .is_some_and(|run| run.end < start)
+    {
+      self.previous_index += 1;
+    }
+    let mut index = self.previous_index;
+    while let Some(run) = self.previous_row.get(index) {
+      if run.start > end {
+        break;
+      }
+      let other = run.component;
+      self.union(component, other);
+      index += 1;
+    }
+
+    self.current_row.push(Run {
+      start,
+      end,
+      component,
+    });
+  }
+
+  fn end_row(&mut self) {
+    std::mem::swap(&mut self.previous_row, &mut self.current_row);
+    self.current_row.clear();
+    self.previous_index = 0;
+  }
+
+  fn find(&mut self, mut component: usize) -> usize {
+    while self.components[component].parent != component {
+      // Halve the path, to keep later lookups short
+      let grandparent = self.components[self.components[component].parent].parent;
+      self.components[component].parent = grandparent;
+      component = grandparent;
+    }
+    component
+  }
+
+  fn union(&mut self, a: usize, b: usize) {
+    let (a, b) = (self.find(a), self.find(b));
+    if a == b {
+      return;
+    }
+
+    let (root, child) = (a.min(b), a.max(b));
+    let Component {
+      left,
+      top,
+      right,
+      bottom,
+      pixel_count,
+      ..
+    } = self.components[child];
+    self.components[child].parent = root;
+
+    let root = &mut self.components[root];
+    root.left = root.left.min(left);
+    root.top = root.top.min(top);
+    root.right = root.right.max(right);
+    root.bottom = root.bottom.max(bottom);
+    root.pixel_count += pixel_count;
+  }
+
+  /// The bounding box of every region, largest first
+  fn into_regions(self) -> Vec<IllegalRegion> {
+    let mut regions: Vec<IllegalRegion> = self
+      .components
+      .iter()
+      .enumerate()
+      .filter(|(index, component)| component.parent == *index)
+      .map(|(_, component)| IllegalRegion {
+        x: component.left,
+        y: component.top,
+        width: component.right - component.left,
+        height: component.bottom - component.top,
+        pixel_count: component.pixel_count,
+      })
+      .collect();
+
+    // Roots are in the order their first pixel was found, so ties stay in reading order
+    regions.sort_by(|a, b| b.pixel_count.cmp(&a.pixel_count));
+    regions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::options::{EncodeOptions, InputFormat};
+
+  const KR: f32 = 0.2126;
+  const KB: f32 = 0.0722;
+
+  fn sample(a: u32, y1: u32, y2: u32, cb: u32, cr: u32) -> [u8; 8] {
+    let word1 = a << 20 | cb << 10 | y1;
+    let word2 = a << 20 | cr << 10 | y2;
+    let mut sample = [0; 8];
+    sample[0..4].copy_from_slice(&word1.to_be_bytes());
+    sample[4..8].copy_from_slice(&word2.to_be_bytes());
+    sample
+  }
+
+  fn analyse_yuva(width: u32, height: u32, data: &[u8]) -> Analysis {
+    let constants = YuvConstantsSimd::create(KR, KB);
+    analyse_still(
+      &constants,
+      VideoRange::Limited,
+      VideoRange::Limited,
+      width,
+      height,
+      data,
+      None,
+      16,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn test_histograms_and_alpha() {
+    let grey = sample(940, 500, 502, 512, 512);
+    let clear = sample(64, 64, 64, 500, 520);
+    let data = [grey, grey, grey, clear].concat();
+
+    let analysis = analyse_yuva(8, 1, &data);
+    assert_eq!(analysis.luma_histogram[500], 3);
+    assert_eq!(analysis.luma_histogram[502], 3);
+    assert_eq!(analysis.luma_histogram[64], 2);
+    assert_eq!(analysis.cb_histogram[512], 3);
+    assert_eq!(analysis.cr_histogram[520], 1);
+    assert_eq!(analysis.illegal_pixels, 0);
+    assert!(analysis.illegal_regions.is_empty());
+
+    assert_eq!(
+      analysis.alpha,
+      AlphaStatistics {
+        min: 64,
+        max: 940,
+        mean: 0.75,
+        opaque_pixels: 6,
+        transparent_pixels: 2,
+        fully_opaque: false,
+        fully_transparent: false,
+      }
+    );
+  }
+
+  #[test]
+  fn test_illegal_regions() {
+    let legal = sample(940, 500, 500, 512, 512);
+    let superwhite = sample(940, 1000, 1000, 512, 512);
+    let left_half = sample(940, 1000, 500, 512, 512);
+    let right_half = sample(940, 500, 1000, 512, 512);
+
+    #[rustfmt::skip]
+    let rows = [
+      [superwhite, legal, legal, legal, legal, legal, legal, legal],
+      [legal, left_half, legal, legal, legal, legal, legal, legal],
+      [legal, legal, legal, legal, legal, right_half, superwhite, legal],
+      [legal, legal, legal, legal, legal, legal, legal, superwhite],
+    ];
+    let data = rows.concat().concat();
+
+    let analysis = analyse_yuva(16, 4, &data);
+    assert_eq!(analysis.illegal_pixels, 8);
+    assert_eq!(analysis.illegal_percentage, 12.5);
+    assert_eq!(analysis.illegal_region_count, 2);
+    assert_eq!(
+      analysis.illegal_regions,
+      [
+        // Connected diagonally across three rows
+        IllegalRegion {
+          x: 11,
+          y: 2,
+          width: 5,
+          height: 2,
+          pixel_count: 5,
+        },
+        IllegalRegion {
+          x: 0,
+          y: 0,
+          width: 3,
+          height: 2,
+          pixel_count: 3,
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_rgba_matches_encoded() {
+    let constants = YuvConstantsSimd::create(KR, KB);
+    let rgba: Vec<u8> = (0..16 * 2 * 4).map(|i| (i * 53 % 256) as u8).collect();
+    let input_format = EncodeOptions {
+      format: Some(InputFormat::Rgba),
+      ..Default::default()
+    }
+    .encode_format();
+    let layout = RowLayout::new(16, 4, None).unwrap();
+
+    let mut encoded = vec![0; 16 * 2 * 4];
+    crate::convert::rgba_to_yuva_422(&constants, 16, 2, &rgba, input_format, layout, &mut encoded)
+      .unwrap();
+
+    let from_rgba = analyse_still(
+      &constants,
+      VideoRange::Limited,
+      VideoRange::Limited,
+      16,
+      2,
+      &rgba,
+      Some((input_format, layout)),
+      16,
+    )
+    .unwrap();
+    let from_encoded = analyse_yuva(16, 2, &encoded);
+    assert_eq!(from_rgba.luma_histogram, from_encoded.luma_histogram);
+    assert_eq!(from_rgba.cb_histogram, from_encoded.cb_histogram);
+    assert_eq!(from_rgba.illegal_pixels, from_encoded.illegal_pixels);
+    assert_eq!(from_rgba.alpha, from_encoded.alpha);
+
+    // Opaque RGBA is reported as fully opaque
+    let opaque: Vec<u8> = (0..16 * 4)
+      .map(|i| if i % 4 == 3 { 255 } else { 0 })
+      .collect();
+    let layout = RowLayout::new(16, 4, None).unwrap();
+    let analysis = analyse_still(
+      &constants,
+      VideoRange::Limited,
+      VideoRange::Limited,
+      16,
+      1,
+      &opaque,
+      Some((input_format, layout)),
+      16,
+    )
+    .unwrap();
+    assert!(analysis.alpha.fully_opaque);
+    assert!(!analysis.alpha.fully_transparent);
+  }
+
+  #[test]
+  fn test_rle_input() {
+    let grey = sample(940, 500, 500, 512, 512);
+    let mut data = vec![0xfe; 8];
+    data.extend_from_slice(&8u64.to_be_bytes());
+    data.extend_from_slice(&grey);
+
+    let analysis = analyse_yuva(8, 2, &data);
+    assert_eq!(analysis.luma_histogram[500], 16);
+    assert!(analysis.alpha.fully_opaque);
+
+    let constants = YuvConstantsSimd::create(KR, KB);
+    let result = analyse_still(
+      &constants,
+      VideoRange::Limited,
+      VideoRange::Limited,
+      8,
+      3,
+      &data,
+      None,
+      16,
+    );
+    assert!(result.is_err());
+  }
+}
@@ -0,0 +1,636 @@
+//! The conversions, callable from Rust without Node.js, such as from benchmarks and tests
+//!
+//! These behave the same as the exported functions of the same purpose, but take plain slices, and fail with an
+//! [`Error`] carrying the message the JavaScript error would have
+
+use crate::convert::{
+  fill_key_to_yuva_422, rgba_to_yuva_422, yuva_422_to_fill_key, yuva_422_to_rgba, RowLayout,
+};
+use crate::fixed_point::default_arithmetic;
+use crate::rle::RLEDecoder;
+
+pub use crate::dispatch::{Capabilities, SimdPath};
+pub use crate::error::{Error, Result};
+pub use crate::options::{
+  Arithmetic, DecodeOptions, Dither, EncodeOptions, FillKeyEncodeOptions, FillKeyOptions,
+  InputFormat, KeyFormat, OutputFormat, TransferFunction, VideoRange,
+};
+
+/// Convert a RGBA buffer to ATEM YUV422 packing in the correct colorspace
+///
+/// The output must be `width * height * 4` bytes long
+pub fn encode_rgba_to_yuva_422(
+  width: u32,
+  height: u32,
+  input: &[u8],
+  output: &mut [u8],
+  options: &EncodeOptions,
+) -> Result<()> {
+  let constants = crate::constants_for_height(width, height, options.range, options.alpha_range)?;
+  let input_format = options.encode_format();
+  let input_layout = RowLayout::new(width, input_format.format.bytes_per_pixel(), options.stride)?;
+
+  rgba_to_yuva_422(
+    constants,
+    width,
+    height,
+    input,
+    input_format,
+    input_layout,
+    output,
+  )
+}
+
+/// Convert separate RGB fill and key images to ATEM YUV422 packing in the correct colorspace, with the key as the alpha
+///
+/// The fill must be `width * height * 3` bytes long, the key `width * height` multiplied by the bytes per pixel of its
+/// format, and the output `width * height * 4` bytes long
+pub fn encode_fill_key_to_yuva_422(
+  width: u32,
+  height: u32,
+  fill: &[u8],
+  key: &[u8],
+  output: &mut [u8],
+  options: &FillKeyEncodeOptions,
+) -> Result<()> {
+  let constants = crate::constants_for_height(width, height, options.range, options.alpha_range)?;
+
+  fill_key_to_yuva_422(
+    constants,
+    width,
+    height,
+    fill,
+    key,
+    options.key_format.unwrap_or(KeyFormat::Luma),
+    options.key_transform()?,
+    options.arithmetic.unwrap_or_else(default_arithmetic),
+    output,
+  )
+}
+
+/// Convert an ATEM YUVA422 buffer, which may be RLE compressed, to RGBA from the correct colorspace
+pub fn decode_yuva_422_to_rgba(
+  width: u32,
+  height: u32,
+  input: &[u8],
+  output: &mut [u8],
+  options: &DecodeOptions,
+) -> Result<()> {
+  let constants = crate::constants_for_height(width, height, options.range, options.alpha_range)?;
+  let output_format = options.decode_format();
+  let output_layout = RowLayout::new(
+    width,
+    output_format.format.bytes_per_pixel(),
+    options.stride,
+  )?;
+
+  yuva_422_to_rgba(
+    constants,
+    width,
+    height,
+    input,
+    output,
+    output_format,
+    output_layout,
+  )
+}
+
+/// Convert an ATEM YUVA422 buffer, which may be RLE compressed, to separate RGB fill and key images
+///
+/// The fill must be `width * height * 3` bytes long, and the key `width * height` bytes long
+pub fn decode_yuva_422_to_fill_key(
+  width: u32,
+  height: u32,
+  input: &[u8],
+  fill: &mut [u8],
+  key: &mut [u8],
+  options: &FillKeyOptions,
+) -> Result<()> {
+  let constants = crate::constants_for_height(width, height, options.range, options.alpha_range)?;
+
+  yuva_422_to_fill_key(
+    constants,
+    width,
+    height,
+    input,
+    fill,
+    key,
+    options.premultiply.unwrap_or(false),
+    options.arithmetic.unwrap_or_else(default_arithmetic),
+  )
+}
+
+/// Compress a flat YUVA422 image, replacing runs of identical samples with an RLE header followed by the sample
+pub fn encode_rle(input: &[u8]) -> Vec<u8> {
+  crate::rle::encode_rle(input)
+}
+
+/// Expand a RLE compressed YUVA422 image back to flat samples
+pub fn decode_rle(input: &[u8]) -> Vec<u8> {
+  let mut decoder = RLEDecoder::new(input);
+  let mut output = Vec::with_capacity(input.len());
+  while let Some(sample) = decoder.read_sample() {
+    output.extend_from_slice(sample);
+  }
+  output
+}
+
+/// The SIMD support of the CPU, and the kernels in use
+pub fn capabilities() -> Capabilities {
+  crate::dispatch::capabilities()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::transfer::{gamma_to_linear, linear_to_gamma};
+  use proptest::prelude::*;
+
+  /// The height at which images switch from BT.601 to BT.709
+  const BT709_HEIGHT: u32 = 720;
+
+  fn ranges() -> impl Strategy<Value = VideoRange> {
+    prop_oneof![Just(VideoRange::Limited), Just(VideoRange::Full)]
+  }
+
+  fn arithmetics() -> impl Strategy<Value = Arithmetic> {
+    prop_oneof![
+      Just(Arithmetic::Float),
+      Just(Arithmetic::Fixed),
+      Just(Arithmetic::Javascript)
+    ]
+  }
+
+  fn input_formats() -> impl Strategy<Value = InputFormat> {
+    prop_oneof![
+      Just(InputFormat::Rgba),
+      Just(InputFormat::Rgba16le),
+      Just(InputFormat::Rgba16be),
+      Just(InputFormat::Rgbaf32)
+    ]
+  }
+
+  fn output_formats() -> impl Strategy<Value = OutputFormat> {
+    prop_oneof![
+      Just(OutputFormat::Rgba),
+      Just(OutputFormat::Rgba16le),
+      Just(OutputFormat::Rgba16be),
+      Just(OutputFormat::Rgbaf32),
+      Just(OutputFormat::R210),
+      Just(OutputFormat::A2r10g10b10)
+    ]
+  }
+
+  fn transfers() -> impl Strategy<Value = TransferFunction> {
+    prop_oneof![
+      Just(TransferFunction::Gamma),
+      Just(TransferFunction::Linear)
+    ]
+  }
+
+  fn dithers() -> impl Strategy<Value = Option<Dither>> {
+    prop_oneof![
+      Just(None),
+      Just(Some(Dither::Bayer)),
+      Just(Some(Dither::BlueNoise))
+    ]
+  }
+
+  /// Write 8-bit RGBA pixels in another format, with `padding` bytes of junk after each row
+  fn to_input_format(
+    width: u32,
+    rgba: &[u8],
+    format: InputFormat,
+    transfer: TransferFunction,
+    padding: usize,
+  ) -> Vec<u8> {
+    let mut output = Vec::new();
+    for row in rgba.chunks_exact(width as usize * 4) {
+      for (i, &value) in row.iter().enumerate() {
+        match format {
+          InputFormat::Rgba => output.push(value),
+          InputFormat::Rgba16le => output.extend((value as u16 * 257).to_le_bytes()),
+          InputFormat::Rgba16be => output.extend((value as u16 * 257).to_be_bytes()),
+          InputFormat::Rgbaf32 => {
+            let value = value as f32 / 255.0;
+            let linear = transfer == TransferFunction::Linear && i % 4 != 3;
+            let value = if linear {
+              gamma_to_linear(value)
+            } else {
+              value
+            };
+            output.extend(value.to_ne_bytes());
+          }
+        }
+      }
+      output.extend(std::iter::repeat_n(0xa5, padding));
+    }
+    output
+  }
+
+  /// Read the pixels of any format as RGBA in the range 0-1, skipping `padding` bytes after each row. Formats without
+  /// alpha give `None` for it
+  fn from_output_format(
+    width: u32,
+    data: &[u8],
+    format: OutputFormat,
+    transfer: TransferFunction,
+    padding: usize,
+  ) -> Vec<[Option<f32>; 4]> {
+    let word = |bytes: &[u8]| -> [u8; 4] { bytes.try_into().unwrap() };
+    let row_bytes = width as usize * format.bytes_per_pixel();
+    data
+      .chunks(row_bytes + padding)
+      .flat_map(|row| row[..row_bytes].chunks_exact(format.bytes_per_pixel()))
+      .map(|pixel| match format {
+        OutputFormat::Rgba => word(pixel).map(|value| Some(value as f32 / 255.0)),
+        OutputFormat::Rgba16le | OutputFormat::Rgba16be => std::array::from_fn(|i| {
+          let bytes = [pixel[i * 2], pixel[i * 2 + 1]];
+          let value = if format == OutputFormat::Rgba16le {
+            u16::from_le_bytes(bytes)
+          } else {
+            u16::from_be_bytes(bytes)
+          };
+          Some(value as f32 / 65535.0)
+        }),
+        OutputFormat::Rgbaf32 => std::array::from_fn(|i| {
+          let value = f32::from_ne_bytes(word(&pixel[i * 4..i * 4 + 4]));
+          let linear = transfer == TransferFunction::Linear && i != 3;
+          Some(if linear {
+            linear_to_gamma(value)
+          } else {
+            value
+          })
+        }),
+        OutputFormat::R210 | OutputFormat::A2r10g10b10 => {
+          let word = if format == OutputFormat::R210 {
+            u32::from_be_bytes(word(pixel))
+          } else {
+            u32::from_le_bytes(word(pixel))
+          };
+          let channel = |shift: u32| Some((word >> shift & 0x3ff) as f32 / 1023.0);
+          let alpha = (format == OutputFormat::A2r10g10b10).then(|| (word >> 30) as f32 / 3.0);
+          [channel(20), channel(10), channel(0), alpha]
+        }
+      })
+      .collect()
+  }
+
+  /// An image of 8 to 32 pixels wide, where each pair of pixels has the same colour but its own alpha, so that there
+  /// is no error from sharing chroma. BT.709 images repeat their rows to reach 720 rows
+  fn paired_images() -> impl Strategy<Value = (u32, u32, Vec<u8>)> {
+    (1..=4u32, 1..=4u32, any::<bool>()).prop_flat_map(|(width, rows, bt709)| {
+      let width = width * 8;
+      prop::collection::vec(any::<[u8; 5]>(), (width * rows / 2) as usize).prop_map(move |pairs| {
+        let tile: Vec<u8> = pairs
+          .iter()
+          .flat_map(|&[r, g, b, a1, a2]| [r, g, b, a1, r, g, b, a2])
+          .collect();
+        let height = if bt709 { BT709_HEIGHT } else { rows };
+        let image = tile.repeat(height.div_ceil(rows) as usize);
+        (
+          width,
+          height,
+          image[..(width * height * 4) as usize].to_vec(),
+        )
+      })
+    })
+  }
+
+  /// An image of 8 to 64 pixels wide made from a few colours, so that it has runs for RLE to compress
+  fn images() -> impl Strategy<Value = (u32, u32, Vec<u8>)> {
+    (1..=8u32, 1..=16u32).prop_flat_map(|(width, height)| {
+      let width = width * 8;
+      (
+        prop::collection::vec(any::<[u8; 4]>(), 1..4),
+        prop::collection::vec((any::<prop::sample::Index>(), 1..32usize), 1..64),
+      )
+        .prop_map(move |(palette, runs)| {
+          let mut image = Vec::new();
+          for (colour, len) in runs.iter().cycle() {
+            image.extend(palette[colour.index(palette.len())].repeat(*len));
+            if image.len() >= (width * height * 4) as usize {
+              break;
+            }
+          }
+          image.truncate((width * height * 4) as usize);
+          (width, height, image)
+        })
+    })
+  }
+
+  fn encode(width: u32, height: u32, rgba: &[u8], options: &EncodeOptions) -> Vec<u8> {
+    let mut output = vec![0; (width * height * 4) as usize];
+    encode_rgba_to_yuva_422(width, height, rgba, &mut output, options).unwrap();
+    output
+  }
+
+  fn decode(width: u32, height: u32, data: &[u8], options: &DecodeOptions) -> Vec<u8> {
+    let mut output = vec![0; (width * height * 4) as usize];
+    decode_yuva_422_to_rgba(width, height, data, &mut output, options).unwrap();
+    output
+  }
+
+  #[test]
+  fn test_errors_keep_their_message() {
+    let mut output = vec![0; 8 * 4];
+    let error = encode_rgba_to_yuva_422(7, 1, &[0; 7 * 4], &mut output, &EncodeOptions::default())
+      .unwrap_err();
+    assert_eq!(error.reason(), "Width must be a multiple of 8");
+    assert_eq!(error.to_string(), "Width must be a multiple of 8");
+    assert_eq!(
+      napi::Error::from(error).reason,
+      "Width must be a multiple of 8"
+    );
+  }
+
+  #[test]
+  fn test_fill_key_rejects_invalid_key_transform() {
+    let (fill, key, mut output) = (vec![0; 8 * 3], vec![0; 8], vec![0; 8 * 4]);
+    for options in [
+      FillKeyEncodeOptions {
+        key_clip: Some(1.5),
+        ..Default::default()
+      },
+      FillKeyEncodeOptions {
+        key_gain: Some(-1.0),
+        ..Default::default()
+      },
+      FillKeyEncodeOptions {
+        key_gain: Some(f64::NAN),
+        ..Default::default()
+      },
+    ] {
+      assert!(encode_fill_key_to_yuva_422(8, 1, &fill, &key, &mut output, &options).is_err());
+    }
+
+    let options = FillKeyEncodeOptions {
+      key_format: Some(KeyFormat::Rgba),
+      ..Default::default()
+    };
+    assert!(encode_fill_key_to_yuva_422(8, 1, &fill, &key, &mut output, &options).is_err());
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_round_trip_error_is_bounded(
+      (width, height, rgba) in paired_images(),
+      range in ranges(),
+      alpha_range in ranges(),
+      arithmetic in arithmetics(),
+    ) {
+      // The javascript arithmetic rejects full range
+      prop_assume!(arithmetic != Arithmetic::Javascript || range == VideoRange::Limited);
+      let encoded = encode(width, height, &rgba, &EncodeOptions {
+        range: Some(range),
+        alpha_range: Some(alpha_range),
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      });
+      let decoded = decode(width, height, &encoded, &DecodeOptions {
+        range: Some(range),
+        alpha_range: Some(alpha_range),
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      });
+
+      for (i, (&input, &output)) in rgba.iter().zip(&decoded).enumerate() {
+        prop_assert!(input.abs_diff(output) <= 1, "byte {} was {} but decoded as {}", i, input, output);
+      }
+    }
+
+    #[test]
+    fn test_alpha_range_only_changes_alpha(
+      (width, height, rgba) in images(),
+      arithmetic in arithmetics(),
+    ) {
+      let encode_words = |range, alpha_range| {
+        let encoded = encode(width, height, &rgba, &EncodeOptions {
+          range: Some(range),
+          alpha_range: Some(alpha_range),
+          arithmetic: Some(arithmetic),
+          ..Default::default()
+        });
+        encoded
+          .chunks_exact(4)
+          .map(|word| u32::from_be_bytes(word.try_into().unwrap()))
+          .collect::<Vec<_>>()
+      };
+      let limited = encode_words(VideoRange::Limited, VideoRange::Limited);
+      // The javascript arithmetic rejects full range
+      let full = (arithmetic != Arithmetic::Javascript).then(|| encode_words(VideoRange::Full, VideoRange::Full));
+      let full_alpha = encode_words(VideoRange::Limited, VideoRange::Full);
+
+      for i in 0..limited.len() {
+        prop_assert_eq!(full_alpha[i] & 0xfffff, limited[i] & 0xfffff, "luma or chroma of word {} differs", i);
+        if let Some(full) = &full {
+          prop_assert_eq!(full_alpha[i] >> 20, full[i] >> 20, "alpha of word {} differs", i);
+        }
+      }
+    }
+
+    #[test]
+    fn test_formats_round_trip_error_is_bounded(
+      (width, height, rgba) in paired_images(),
+      input_format in input_formats(),
+      output_format in output_formats(),
+      transfer in transfers(),
+      dither in dithers(),
+      (range, alpha_range) in (ranges(), ranges()),
+      arithmetic in arithmetics(),
+      (input_padding, output_padding) in (0..16usize, 0..16usize),
+      threads in 1..=3u32,
+    ) {
+      let input = to_input_format(width, &rgba, input_format, transfer, input_padding);
+      let mut encoded = vec![0; rgba.len()];
+      let encode_options = EncodeOptions {
+        format: Some(input_format),
+        stride: Some((width as usize * input_format.bytes_per_pixel() + input_padding) as u32),
+        transfer: Some(transfer),
+        dither,
+        range: Some(range),
+        alpha_range: Some(alpha_range),
+        arithmetic: Some(arithmetic),
+        threads: Some(threads),
+        ..Default::default()
+      };
+      // The junk after the last row is not part of the image
+      let input = &input[..input.len() - input_padding];
+      let result = encode_rgba_to_yuva_422(width, height, input, &mut encoded, &encode_options);
+      if arithmetic == Arithmetic::Javascript && (input_format != InputFormat::Rgba || range == VideoRange::Full) {
+        prop_assert!(result.is_err(), "javascript arithmetic was not rejected");
+        return Ok(());
+      }
+      result.unwrap();
+
+      let output_stride = width as usize * output_format.bytes_per_pixel() + output_padding;
+      let mut decoded = vec![0; output_stride * height as usize - output_padding];
+      let decode_options = DecodeOptions {
+        format: Some(output_format),
+        stride: Some(output_stride as u32),
+        transfer: Some(transfer),
+        dither,
+        range: Some(range),
+        alpha_range: Some(alpha_range),
+        arithmetic: Some(arithmetic),
+        threads: Some(threads),
+        ..Default::default()
+      };
+      decode_yuva_422_to_rgba(width, height, &encoded, &mut decoded, &decode_options).unwrap();
+
+      // Dithering adds up to half a code to each rounding, which the colour matrix can carry to more than one channel.
+      // Deep output is scaled exactly, while 8-bit output keeps the scales of earlier versions, which are a little off
+      let deep_output = matches!(
+        output_format,
+        OutputFormat::Rgba16le
+          | OutputFormat::Rgba16be
+          | OutputFormat::Rgbaf32
+          | OutputFormat::R210
+          | OutputFormat::A2r10g10b10
+      );
+      let mut tolerance = match (deep_output, dither.is_some()) {
+        (true, false) => 0.6,
+        (true, true) => 1.0,
+        (false, false) => 1.5,
+        (false, true) => 2.5,
+      };
+      // 8-bit input is encoded with those scales too, and truncated in limited range, which deep output does not undo
+      if deep_output && input_format == InputFormat::Rgba {
+        tolerance += 1.5;
+      }
+      let tolerance = tolerance / 255.0;
+      let decoded = from_output_format(width, &decoded, output_format, transfer, output_padding);
+      for (i, (input, output)) in rgba.chunks_exact(4).zip(&decoded).enumerate() {
+        for channel in 0..4 {
+          let Some(output) = output[channel] else { continue };
+          // 2-bit alpha can only be within half a step
+          let tolerance = if channel == 3 && output_format == OutputFormat::A2r10g10b10 { 0.5 / 3.0 } else { 0.0 } + tolerance;
+          let input = input[channel] as f32 / 255.0;
+          prop_assert!((input - output).abs() <= tolerance, "channel {} of pixel {} was {} but decoded as {}", channel, i, input, output);
+        }
+      }
+    }
+
+    #[test]
+    fn test_rle_is_lossless((width, height, rgba) in images()) {
+      let flat = encode(width, height, &rgba, &EncodeOptions::default());
+      let compressed = encode_rle(&flat);
+      prop_assert!(compressed.len() <= flat.len());
+      prop_assert_eq!(decode_rle(&compressed), flat.clone());
+
+      let options = DecodeOptions::default();
+      prop_assert_eq!(
+        decode(width, height, &compressed, &options),
+        decode(width, height, &flat, &options)
+      );
+    }
+
+    #[test]
+    fn test_fill_key_matches_rgba((width, height, rgba) in images(), arithmetic in arithmetics()) {
+      let compressed = encode_rle(&encode(width, height, &rgba, &EncodeOptions::default()));
+      let decoded = decode(width, height, &compressed, &DecodeOptions {
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      });
+
+      let pixels = (width * height) as usize;
+      let (mut fill, mut key) = (vec![0; pixels * 3], vec![0; pixels]);
+      let options = FillKeyOptions {
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      };
+      decode_yuva_422_to_fill_key(width, height, &compressed, &mut fill, &mut key, &options).unwrap();
+      for (i, pixel) in decoded.chunks_exact(4).enumerate() {
+        prop_assert_eq!(&fill[i * 3..i * 3 + 3], &pixel[0..3]);
+        prop_assert_eq!(key[i], pixel[3]);
+      }
+
+      let premultiply = FillKeyOptions {
+        premultiply: Some(true),
+        ..options
+      };
+      decode_yuva_422_to_fill_key(width, height, &compressed, &mut fill, &mut key, &premultiply).unwrap();
+      // The fill is multiplied before either is rounded, so each rounding of the decoded pixel can carry half a code
+      for (i, pixel) in decoded.chunks_exact(4).enumerate() {
+        for c in 0..3 {
+          let expected = pixel[c] as f32 * pixel[3] as f32 / 255.0;
+          prop_assert!((fill[i * 3 + c] as f32 - expected).abs() <= 1.5, "pixel {} was {:?} but premultiplied as {:?}", i, pixel, &fill[i * 3..i * 3 + 3]);
+        }
+      }
+    }
+
+    #[test]
+    fn test_fill_key_encodes_as_rgba(
+      (width, height, rgba) in images(),
+      range in ranges(),
+      arithmetic in arithmetics(),
+    ) {
+      let fill: Vec<u8> = rgba.chunks_exact(4).flat_map(|pixel| &pixel[0..3]).copied().collect();
+      let key: Vec<u8> = rgba.chunks_exact(4).map(|pixel| pixel[3]).collect();
+      let mut output = vec![0; rgba.len()];
+      let options = FillKeyEncodeOptions {
+        range: Some(range),
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      };
+      let result = encode_fill_key_to_yuva_422(width, height, &fill, &key, &mut output, &options);
+      if arithmetic == Arithmetic::Javascript && range == VideoRange::Full {
+        prop_assert!(result.is_err(), "javascript arithmetic was not rejected");
+        return Ok(());
+      }
+      result.unwrap();
+
+      let expected = encode(width, height, &rgba, &EncodeOptions {
+        range: Some(range),
+        arithmetic: Some(arithmetic),
+        ..Default::default()
+      });
+      prop_assert!(output == expected);
+
+      let inverted: Vec<u8> = key.iter().map(|key| 255 - key).collect();
+      let options = FillKeyEncodeOptions {
+        invert_key: Some(true),
+        ..options
+      };
+      encode_fill_key_to_yuva_422(width, height, &fill, &inverted, &mut output, &options).unwrap();
+      prop_assert!(output == expected);
+    }
+
+    #[test]
+    fn test_paths_are_identical((width, height, rgba) in images(), arithmetic in arithmetics()) {
+      let reference = |path| EncodeOptions {
+        arithmetic: Some(arithmetic),
+        simd_path: Some(path),
+        ..Default::default()
+      };
+      let expected = encode(width, height, &rgba, &reference(SimdPath::Scalar));
+      let compressed = encode_rle(&expected);
+      let expected_rgba = decode(width, height, &expected, &DecodeOptions {
+        arithmetic: Some(arithmetic),
+        simd_path: Some(SimdPath::Scalar),
+        ..Default::default()
+      });
+
+      for path in SimdPath::available() {
+        for threads in [1, 3] {
+          let encoded = encode(width, height, &rgba, &EncodeOptions {
+            threads: Some(threads),
+            ..reference(path)
+          });
+          prop_assert!(encoded == expected, "encoding with {:?} on {} threads differs", path, threads);
+
+          let options = DecodeOptions {
+            arithmetic: Some(arithmetic),
+            simd_path: Some(path),
+            threads: Some(threads),
+            ..Default::default()
+          };
+          prop_assert!(decode(width, height, &expected, &options) == expected_rgba, "decoding with {:?} on {} threads differs", path, threads);
+          prop_assert!(decode(width, height, &compressed, &options) == expected_rgba, "decoding RLE with {:?} on {} threads differs", path, threads);
+        }
+      }
+    }
+  }
+}
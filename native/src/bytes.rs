@@ -0,0 +1,198 @@
+use napi::bindgen_prelude::{FromNapiValue, ToNapiValue};
+use napi::{check_status, sys, Env, Error, JsBuffer, NapiRaw, Status, TypedArrayType};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// The raw bytes of any ArrayBuffer-backed value: a Buffer, any TypedArray, a DataView, or an ArrayBuffer.
+///
+/// Views only expose the range of their backing store they cover, so their byte offset and length are respected.
+/// Node-API can't read a SharedArrayBuffer directly, but a view of one works.
+///
+/// Other threads may write shared memory at any time, so it is never borrowed as a slice. Its bytes are copied with
+/// atomic loads into a private buffer instead, and if that gets modified, every byte is copied back with atomic stores
+/// once the conversion finishes. Bytes of a shared output which the conversion doesn't write, such as row padding, are
+/// stored again with the values they had when it started.
+pub struct JsBytes {
+  value: sys::napi_value,
+  data: *mut u8,
+  len: usize,
+  /// The private copy of shared memory, and whether it has been borrowed mutably
+  shared: Option<(Vec<u8>, bool)>,
+}
+
+impl JsBytes {
+  fn element_size(typedarray_type: TypedArrayType) -> Option<usize> {
+    match typedarray_type {
+      TypedArrayType::Int8 | TypedArrayType::Uint8 | TypedArrayType::Uint8Clamped => Some(1),
+      TypedArrayType::Int16 | TypedArrayType::Uint16 => Some(2),
+      TypedArrayType::Int32 | TypedArrayType::Uint32 | TypedArrayType::Float32 => Some(4),
+      TypedArrayType::Float64 | TypedArrayType::BigInt64 | TypedArrayType::BigUint64 => Some(8),
+      _ => None,
+    }
+  }
+
+  /// Allocate a new Buffer, without zero-filling it
+  pub fn create_buffer(env: Env, len: usize) -> napi::Result<JsBytes> {
+    JsBytes::from_buffer(env.create_buffer(len)?.into_raw())
+  }
+
+  pub fn from_buffer(buffer: JsBuffer) -> napi::Result<JsBytes> {
+    let value = unsafe { buffer.raw() };
+    let mut buffer = buffer.into_value()?;
+
+    Ok(JsBytes {
+      value,
+      data: buffer.as_mut_ptr(),
+      len: buffer.len(),
+      shared: None,
+    })
+  }
+
+  /// Whether a backing store is a SharedArrayBuffer, which Node-API doesn't report as an ArrayBuffer
+  unsafe fn is_shared(env: sys::napi_env, arraybuffer: sys::napi_value) -> napi::Result<bool> {
+    let mut is_arraybuffer = false;
+    check_status!(unsafe { sys::napi_is_arraybuffer(env, arraybuffer, &mut is_arraybuffer) })?;
+    Ok(!is_arraybuffer)
+  }
+
+  fn atomic_bytes(&self) -> &[AtomicU8] {
+    if self.len == 0 {
+      return &[];
+    }
+    // AtomicU8 has the same layout as u8, and atomic accesses may race with other threads
+    unsafe { slice::from_raw_parts(self.data as *const AtomicU8, self.len) }
+  }
+
+  /// Fail if the bytes of the two values share any memory
+  pub fn check_no_overlap(&self, other: &JsBytes) -> napi::Result<()> {
+    let self_start = self.data as usize;
+    let other_start = other.data as usize;
+
+    if self.len > 0
+      && other.len > 0
+      && self_start < other_start + other.len
+      && other_start < self_start + self.len
+    {
+      return Err(Error::from_reason(
+        "Input and output buffers must not overlap",
+      ));
+    }
+
+    Ok(())
+  }
+}
+
+impl FromNapiValue for JsBytes {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> napi::Result<Self> {
+    let mut data = ptr::null_mut();
+    let mut len = 0;
+    let mut arraybuffer = ptr::null_mut();
+
+    let mut is_typedarray = false;
+    check_status!(unsafe { sys::napi_is_typedarray(env, napi_val, &mut is_typedarray) })?;
+    let mut is_dataview = false;
+    check_status!(unsafe { sys::napi_is_dataview(env, napi_val, &mut is_dataview) })?;
+    let mut is_arraybuffer = false;
+    check_status!(unsafe { sys::napi_is_arraybuffer(env, napi_val, &mut is_arraybuffer) })?;
+
+    if is_typedarray {
+      let mut typedarray_type = 0;
+      let mut element_count = 0;
+      check_status!(unsafe {
+        sys::napi_get_typedarray_info(
+          env,
+          napi_val,
+          &mut typedarray_type,
+          &mut element_count,
+          &mut data,
+          &mut arraybuffer,
+          ptr::null_mut(),
+        )
+      })?;
+
+      let element_size = JsBytes::element_size(typedarray_type.into())
+        .ok_or_else(|| Error::new(Status::InvalidArg, "Unsupported TypedArray type"))?;
+      len = element_count * element_size;
+    } else if is_dataview {
+      check_status!(unsafe {
+        sys::napi_get_dataview_info(
+          env,
+          napi_val,
+          &mut len,
+          &mut data,
+          &mut arraybuffer,
+          ptr::null_mut(),
+        )
+      })?;
+    } else if is_arraybuffer {
+      check_status!(unsafe { sys::napi_get_arraybuffer_info(env, napi_val, &mut data, &mut len) })?;
+    } else {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Expected a Buffer, TypedArray, DataView or ArrayBuffer",
+      ));
+    }
+
+    let mut bytes = JsBytes {
+      value: napi_val,
+      data: data as *mut u8,
+      len,
+      shared: None,
+    };
+    if !is_arraybuffer && unsafe { JsBytes::is_shared(env, arraybuffer)? } {
+      let copy = bytes
+        .atomic_bytes()
+        .iter()
+        .map(|byte| byte.load(Ordering::Relaxed))
+        .collect();
+      bytes.shared = Some((copy, false));
+    }
+
+    Ok(bytes)
+  }
+}
+
+impl ToNapiValue for JsBytes {
+  unsafe fn to_napi_value(_env: sys::napi_env, val: Self) -> napi::Result<sys::napi_value> {
+    Ok(val.value)
+  }
+}
+
+impl Deref for JsBytes {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    if let Some((copy, _)) = &self.shared {
+      return copy;
+    }
+    if self.len == 0 {
+      return &[];
+    }
+    unsafe { slice::from_raw_parts(self.data, self.len) }
+  }
+}
+
+impl DerefMut for JsBytes {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    if let Some((copy, modified)) = &mut self.shared {
+      *modified = true;
+      return copy;
+    }
+    if self.len == 0 {
+      return &mut [];
+    }
+    unsafe { slice::from_raw_parts_mut(self.data, self.len) }
+  }
+}
+
+impl Drop for JsBytes {
+  fn drop(&mut self) {
+    if let Some((copy, true)) = &self.shared {
+      for (byte, &value) in self.atomic_bytes().iter().zip(copy) {
+        byte.store(value, Ordering::Relaxed);
+      }
+    }
+  }
+}
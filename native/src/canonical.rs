@@ -0,0 +1,151 @@
+use crate::error::{Error, Result};
+use crate::rle::RLEDecoder;
+
+/// The number of samples hashed at a time
+const HASH_BATCH_SAMPLES: usize = 512;
+/// The most samples in a still, which is 8K at two pixels per sample
+const MAX_STILL_SAMPLES: usize = 7680 * 4320 / 2;
+
+/// Reject a flat or RLE compressed YUVA422 image which expands to more samples than the largest still, without
+/// expanding it, as a RLE header can claim up to 2^64 repeats
+fn check_expanded_len(data: &[u8]) -> Result<()> {
+  let mut decoder = RLEDecoder::new(data);
+  if decoder.skip_samples(MAX_STILL_SAMPLES) && decoder.read_sample().is_some() {
+    return Err(Error::from_reason(
+      "Image expands to more samples than the largest still",
+    ));
+  }
+  Ok(())
+}
+
+/// The MD5 digest of the expanded sample stream of a flat or RLE compressed YUVA422 image
+///
+/// The digest only depends on the samples, so it is the same however the image was compressed, and matches the MD5 of
+/// the flat image
+pub(crate) fn canonical_hash(data: &[u8]) -> Result<[u8; 16]> {
+  check_expanded_len(data)?;
+
+  let mut context = md5::Context::new();
+  let mut decoder = RLEDecoder::new(data);
+  let mut batch = [0; HASH_BATCH_SAMPLES * 8];
+
+  loop {
+    let mut len = 0;
+    while len < batch.len() {
+      let Some(sample) = decoder.read_sample() else {
+        break;
+      };
+      batch[len..len + 8].copy_from_slice(sample);
+      len += 8;
+    }
+
+    context.consume(&batch[..len]);
+    if len < batch.len() {
+      return Ok(context.compute().0);
+    }
+  }
+}
+
+/// Whether two flat or RLE compressed YUVA422 images expand to the same samples, without expanding either of them
+pub(crate) fn stills_equal(a: &[u8], b: &[u8]) -> Result<bool> {
+  check_expanded_len(a)?;
+  check_expanded_len(b)?;
+  if a == b {
+    return Ok(true);
+  }
+
+  let mut decoder_a = RLEDecoder::new(a);
+  let mut decoder_b = RLEDecoder::new(b);
+  loop {
+    match (decoder_a.read_sample(), decoder_b.read_sample()) {
+      (Some(sample_a), Some(sample_b)) if sample_a == sample_b => {}
+      (None, None) => return Ok(true),
+      _ => return Ok(false),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::rle::encode_rle;
+
+  fn image() -> Vec<u8> {
+    let flat = [0x3a, 0xc8, 0x00, 0x40, 0x3a, 0xc8, 0x00, 0x40];
+    let mut data = flat.repeat(20);
+    data.extend((0..64).map(|i| i as u8));
+    data.extend(flat.repeat(7));
+    data
+  }
+
+  #[test]
+  fn test_hash_ignores_rle() {
+    let flat = image();
+    let compressed = encode_rle(&flat);
+    assert!(compressed.len() < flat.len());
+
+    // The hash of a flat image is the plain MD5 of its bytes
+    assert_eq!(canonical_hash(&flat).unwrap(), md5::compute(&flat).0);
+    assert_eq!(
+      canonical_hash(&compressed).unwrap(),
+      canonical_hash(&flat).unwrap()
+    );
+
+    // The same run split differently
+    let mut split = Vec::new();
+    for count in [12u64, 8] {
+      split.extend([0xfe; 8]);
+      split.extend(count.to_be_bytes());
+      split.extend(&flat[0..8]);
+    }
+    split.extend(&flat[160..]);
+    assert_eq!(
+      canonical_hash(&split).unwrap(),
+      canonical_hash(&flat).unwrap()
+    );
+    assert!(stills_equal(&split, &compressed).unwrap());
+
+    let mut changed = flat.clone();
+    changed[170] ^= 1;
+    assert_ne!(
+      canonical_hash(&changed).unwrap(),
+      canonical_hash(&flat).unwrap()
+    );
+  }
+
+  #[test]
+  fn test_stills_equal() {
+    let flat = image();
+    let compressed = encode_rle(&flat);
+    assert!(stills_equal(&flat, &compressed).unwrap());
+    assert!(stills_equal(&compressed, &flat).unwrap());
+
+    let mut changed = flat.clone();
+    changed[170] ^= 1;
+    assert!(!stills_equal(&compressed, &changed).unwrap());
+
+    // A prefix is not equal
+    assert!(!stills_equal(&flat, &flat[..flat.len() - 8]).unwrap());
+  }
+
+  #[test]
+  fn test_endless_run_is_rejected() {
+    let sample = [0x3a, 0xc8, 0x00, 0x40, 0x3a, 0xc8, 0x00, 0x40];
+    let mut endless = vec![0xfe; 8];
+    endless.extend((1u64 << 63).to_be_bytes());
+    endless.extend(sample);
+    assert!(canonical_hash(&endless).is_err());
+    assert!(stills_equal(&endless, &endless).is_err());
+    assert!(stills_equal(&sample, &endless).is_err());
+
+    // The largest still is accepted
+    let mut largest = vec![0xfe; 8];
+    largest.extend((MAX_STILL_SAMPLES as u64).to_be_bytes());
+    largest.extend(sample);
+    assert!(stills_equal(&largest, &largest).unwrap());
+
+    let mut larger = largest.clone();
+    larger.extend(sample);
+    assert!(canonical_hash(&larger).is_err());
+  }
+}
@@ -0,0 +1,333 @@
+use crate::bytes::JsBytes;
+use crate::convert::{rgba_to_yuva_422, validate_dimensions, yuva_422_to_rgba, RowLayout};
+use crate::fixed_point::default_arithmetic;
+use crate::legalise::{legalise_with_report, LegaliseReport};
+use crate::options::{
+  Arithmetic, Colorspace, DecodeFormat, Dither, EncodeFormat, InputFormat, OutputFormat,
+  TransferFunction, VideoRange,
+};
+use crate::region::rgba_to_yuva_422_region;
+use crate::yuv_constants::YuvConstantsSimd;
+use napi::bindgen_prelude::ObjectFinalize;
+use napi::{Env, Ref};
+
+#[napi(object)]
+pub struct AtemImageCodecOptions {
+  /// The width of the images
+  pub width: u32,
+  /// The height of the images
+  pub height: u32,
+  /// The colorspace to convert in. Defaults to the one the ATEM uses for the resolution
+  #[napi(ts_type = "'bt601' | 'bt709'")]
+  pub colorspace: Option<Colorspace>,
+  /// The pixel layout of the input of `encode` and `encodeRegion`. Defaults to `rgba`
+  #[napi(ts_type = "'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32'")]
+  pub input_format: Option<InputFormat>,
+  /// The pixel layout of the output of `decode`. Defaults to `rgba`
+  #[napi(ts_type = "'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32' | 'r210' | 'a2r10g10b10'")]
+  pub output_format: Option<OutputFormat>,
+  /// How the colour values of `rgbaf32` input and output are encoded. Defaults to `gamma`
+  #[napi(ts_type = "'gamma' | 'linear'")]
+  pub transfer: Option<TransferFunction>,
+  /// Dither deep input of `encode` and `encodeRegion` when rounding it to 10-bit, and `rgba` output of `decode` when
+  /// rounding it to 8-bit. Defaults to no dithering
+  #[napi(ts_type = "'bayer' | 'blueNoise'")]
+  pub dither: Option<Dither>,
+  /// The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic` when the
+  /// codec is created
+  #[napi(ts_type = "'float' | 'fixed' | 'javascript'")]
+  pub arithmetic: Option<Arithmetic>,
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// The number of bytes from the start of one row of the input of `encode` and `encodeRegion` to the start of the
+  /// next, for when the rows are padded. Defaults to the width multiplied by the bytes per pixel of the input format
+  pub input_stride: Option<u32>,
+  /// The number of bytes from the start of one row of the output of `decode` to the start of the next, for when the
+  /// rows are padded. Defaults to `width` multiplied by the bytes per pixel of the output format
+  pub output_stride: Option<u32>,
+  /// Write every result into the same buffer, instead of allocating a new one each call.
+  /// The data returned by a call is only valid until the next call of the same method
+  pub reuse_buffers: Option<bool>,
+}
+
+/// A codec for converting a sequence of images of the same size, such as a clip
+///
+/// The colour constants are computed once upon construction, and reused for each frame, as are the decoding lookup
+/// tables when they are faster on this CPU.
+///
+/// An instance can't be shared between threads, as it holds references to JavaScript buffers of the thread it was
+/// created on. Each worker thread creates its own instead, and can convert into shared memory owned by another thread.
+#[napi(custom_finalize)]
+pub struct AtemImageCodec {
+  width: u32,
+  height: u32,
+  colorspace: Colorspace,
+  constants: &'static YuvConstantsSimd,
+  input_format: EncodeFormat,
+  output_format: DecodeFormat,
+  range: VideoRange,
+  input_stride: Option<u32>,
+  input_layout: RowLayout,
+  output_layout: RowLayout,
+
+  reuse_buffers: bool,
+  encode_buffer: Option<Ref<()>>,
+  decode_buffer: Option<Ref<()>>,
+}
+
+#[napi]
+impl AtemImageCodec {
+  #[napi(constructor)]
+  pub fn new(options: AtemImageCodecOptions) -> napi::Result<Self> {
+    validate_dimensions(options.width, options.height)?;
+    let transfer = options.transfer.unwrap_or(TransferFunction::Gamma);
+    let arithmetic = options.arithmetic.unwrap_or_else(default_arithmetic);
+    let input_format = EncodeFormat {
+      format: options.input_format.unwrap_or(InputFormat::Rgba),
+      transfer,
+      dither: options.dither,
+      arithmetic,
+      path: None,
+      threads: 1,
+    };
+    let input_layout = RowLayout::new(
+      options.width,
+      input_format.format.bytes_per_pixel(),
+      options.input_stride,
+    )?;
+    let output_format = DecodeFormat {
+      format: options.output_format.unwrap_or(OutputFormat::Rgba),
+      transfer,
+      dither: options.dither,
+      arithmetic,
+      path: None,
+      threads: 1,
+    };
+    let output_layout = RowLayout::new(
+      options.width,
+      output_format.format.bytes_per_pixel(),
+      options.output_stride,
+    )?;
+
+    let range = options.range.unwrap_or(VideoRange::Limited);
+    let colorspace = options
+      .colorspace
+      .unwrap_or_else(|| Colorspace::for_height(options.height));
+
+    Ok(AtemImageCodec {
+      width: options.width,
+      height: options.height,
+      colorspace,
+      constants: YuvConstantsSimd::shared(
+        colorspace,
+        range,
+        options.alpha_range.unwrap_or(VideoRange::Limited),
+      ),
+      input_format,
+      output_format,
+      range,
+      input_stride: options.input_stride,
+      input_layout,
+      output_layout,
+
+      reuse_buffers: options.reuse_buffers.unwrap_or(false),
+      encode_buffer: None,
+      decode_buffer: None,
+    })
+  }
+
+  #[napi(getter)]
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  #[napi(getter)]
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  #[napi(getter, ts_return_type = "'bt601' | 'bt709'")]
+  pub fn colorspace(&self) -> Colorspace {
+    self.colorspace
+  }
+
+  /// Convert a RGBA image to ATEM YUVA422 packing
+  ///
+  /// @param input - The input RGBA pixel data, in any ArrayBuffer-backed value
+  /// @param output - Optional buffer to write the YUVA422 pixel data into
+  /// @returns The output YUVA422 pixel data
+  #[napi(
+    ts_generic_types = "T extends ArrayBufferView | ArrayBuffer = Buffer",
+    ts_return_type = "T"
+  )]
+  pub fn encode(
+    &mut self,
+    env: Env,
+    #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+    #[napi(ts_arg_type = "T")] output: Option<JsBytes>,
+  ) -> napi::Result<JsBytes> {
+    let mut output = match output {
+      Some(output) => output,
+      None => self.output_buffer(env, false)?,
+    };
+    input.check_no_overlap(&output)?;
+
+    rgba_to_yuva_422(
+      self.constants,
+      self.width,
+      self.height,
+      &input,
+      self.input_format,
+      self.input_layout,
+      &mut output,
+    )?;
+
+    Ok(output)
+  }
+
+  /// Convert an ATEM YUVA422 image to RGBA
+  ///
+  /// @param input - The input YUVA422 pixel data, in any ArrayBuffer-backed value
+  /// @param output - Optional buffer to write the RGBA pixel data into
+  /// @returns The output RGBA pixel data
+  #[napi(
+    ts_generic_types = "T extends ArrayBufferView | ArrayBuffer = Buffer",
+    ts_return_type = "T"
+  )]
+  pub fn decode(
+    &mut self,
+    env: Env,
+    #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+    #[napi(ts_arg_type = "T")] output: Option<JsBytes>,
+  ) -> napi::Result<JsBytes> {
+    let mut output = match output {
+      Some(output) => output,
+      None => self.output_buffer(env, true)?,
+    };
+    input.check_no_overlap(&output)?;
+
+    yuva_422_to_rgba(
+      self.constants,
+      self.width,
+      self.height,
+      &input,
+      &mut output,
+      self.output_format,
+      self.output_layout,
+    )?;
+
+    Ok(output)
+  }
+
+  /// Convert a RGBA image, and write it over a rectangle of an existing YUVA422 image of the codec size
+  ///
+  /// The existing image must not be RLE compressed. The input is read using the `inputStride`, if it was specified
+  ///
+  /// @param target - The YUVA422 pixel data to write the region into, in any ArrayBuffer-backed value
+  /// @param x - The x position of the region in the target image
+  /// @param y - The y position of the region in the target image
+  /// @param input - The input RGBA pixel data of the region, in any ArrayBuffer-backed value
+  /// @param width - The width of the region
+  /// @param height - The height of the region
+  #[napi]
+  pub fn encode_region(
+    &self,
+    #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut target: JsBytes,
+    x: u32,
+    y: u32,
+    #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+    width: u32,
+    height: u32,
+  ) -> napi::Result<()> {
+    let input_layout = RowLayout::new(
+      width,
+      self.input_format.format.bytes_per_pixel(),
+      self.input_stride,
+    )?;
+    input.check_no_overlap(&target)?;
+
+    rgba_to_yuva_422_region(
+      self.constants,
+      self.width,
+      self.height,
+      &mut target,
+      x,
+      y,
+      width,
+      height,
+      &input,
+      self.input_format,
+      input_layout,
+    )?;
+    Ok(())
+  }
+
+  /// Clip the luma, and reduce the chroma, of a YUVA422 image of the codec size, so that it lies within the EBU R103
+  /// broadcast-safe limits. This modifies the image in place
+  ///
+  /// The image must not be RLE compressed
+  ///
+  /// @param data - The YUVA422 pixel data to legalise, in any ArrayBuffer-backed value
+  /// @param map - Whether to return a map of the modified pixels
+  /// @returns The number of modified pixels, and the optional map
+  #[napi]
+  pub fn legalise(
+    &self,
+    #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut data: JsBytes,
+    map: Option<bool>,
+  ) -> napi::Result<LegaliseReport> {
+    let [kr, kb] = self.colorspace.coefficients();
+    Ok(legalise_with_report(
+      kr,
+      kb,
+      self.range,
+      self.width,
+      self.height,
+      &mut data,
+      map.unwrap_or(false),
+    )?)
+  }
+
+  fn output_buffer(&mut self, env: Env, decode: bool) -> napi::Result<JsBytes> {
+    let byte_count = if decode {
+      self.output_layout.byte_len(self.height)
+    } else {
+      self.width as usize * self.height as usize * 4
+    };
+    if !self.reuse_buffers {
+      let mut buffer = JsBytes::create_buffer(env, byte_count)?;
+      if decode && self.output_layout.padded {
+        buffer.fill(0);
+      }
+      return Ok(buffer);
+    }
+
+    let slot = if decode {
+      &mut self.decode_buffer
+    } else {
+      &mut self.encode_buffer
+    };
+    if slot.is_none() {
+      let buffer = env.create_buffer_with_data(vec![0; byte_count])?.into_raw();
+      *slot = Some(env.create_reference(buffer)?);
+    }
+    JsBytes::from_buffer(env.get_reference_value(slot.as_ref().unwrap())?)
+  }
+}
+
+impl ObjectFinalize for AtemImageCodec {
+  fn finalize(mut self, env: Env) -> napi::Result<()> {
+    if let Some(mut buffer) = self.encode_buffer.take() {
+      buffer.unref(env)?;
+    }
+    if let Some(mut buffer) = self.decode_buffer.take() {
+      buffer.unref(env)?;
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,449 @@
+use crate::convert::{yuva_422_to_rgba, RowLayout};
+use crate::error::{Error, Result};
+use crate::options::{CompareLimits, DecodeOptions, StillFormat};
+use crate::transfer::srgb_to_linear;
+use crate::yuv_constants::YuvConstantsSimd;
+use napi::bindgen_prelude::Buffer;
+use std::borrow::Cow;
+
+/// The size of the square windows SSIM is measured over
+const SSIM_WINDOW: usize = 8;
+/// The constants which stabilise SSIM for dark and flat windows, for 8-bit values
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// The result of comparing two images
+#[napi(object)]
+pub struct StillComparison {
+  /// The peak signal to noise ratio of the RGB components in decibels, or `Infinity` if they are identical
+  pub psnr: f64,
+  /// The mean structural similarity of the luma, from 8x8 windows. 1 means identical
+  pub ssim: f64,
+  /// The largest CIEDE2000 colour difference of any pixel
+  pub max_delta_e: f64,
+  /// The mean CIEDE2000 colour difference
+  pub mean_delta_e: f64,
+  /// The largest difference in 8-bit alpha of any pixel
+  pub max_alpha_difference: u32,
+  /// An RGBA image of the colour difference of each pixel, from black for none through blue, green and yellow to red
+  /// for twice `maxDeltaE` or more. Only present if it was requested
+  pub heat_map: Option<Buffer>,
+  /// Whether every measurement is within the thresholds
+  pub pass: bool,
+}
+
+/// The results of comparing two images, before conversion to the JS types
+pub(crate) struct Comparison {
+  pub psnr: f64,
+  pub ssim: f64,
+  pub max_delta_e: f64,
+  pub mean_delta_e: f64,
+  pub max_alpha_difference: u32,
+  pub heat_map: Option<Vec<u8>>,
+  pub pass: bool,
+}
+impl From<Comparison> for StillComparison {
+  fn from(comparison: Comparison) -> StillComparison {
+    StillComparison {
+      psnr: comparison.psnr,
+      ssim: comparison.ssim,
+      max_delta_e: comparison.max_delta_e,
+      mean_delta_e: comparison.mean_delta_e,
+      max_alpha_difference: comparison.max_alpha_difference,
+      heat_map: comparison.heat_map.map(Buffer::from),
+      pass: comparison.pass,
+    }
+  }
+}
+
+/// Get an image as 8-bit RGBA, decoding it with the normal decode path if it is YUVA422
+pub(crate) fn decode_still<'a>(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  data: &'a [u8],
+  format: StillFormat,
+) -> Result<Cow<'a, [u8]>> {
+  let byte_len = width as usize * height as usize * 4;
+  match format {
+    StillFormat::Rgba => {
+      if data.len() != byte_len {
+        return Err(Error::from_reason("Input buffer has incorrect length"));
+      }
+      Ok(Cow::Borrowed(data))
+    }
+    StillFormat::Yuva422 => {
+      let mut output = vec![0; byte_len];
+      yuva_422_to_rgba(
+        constants,
+        width,
+        height,
+        data,
+        &mut output,
+        DecodeOptions::default().decode_format(),
+        RowLayout::new(width, 4, None)?,
+      )?;
+      Ok(Cow::Owned(output))
+    }
+  }
+}
+
+/// Compare two 8-bit RGBA images of the same size
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compare_stills(
+  kr: f32,
+  kb: f32,
+  width: u32,
+  height: u32,
+  a: &[u8],
+  b: &[u8],
+  limits: CompareLimits,
+  with_heat_map: bool,
+) -> Comparison {
+  let pixel_count = width as usize * height as usize;
+  let mut heat_map = with_heat_map.then(|| vec![0; pixel_count * 4]);
+
+  let mut squared_error: u64 = 0;
+  let mut max_delta_e: f64 = 0.0;
+  let mut total_delta_e: f64 = 0.0;
+  let mut max_alpha_difference = 0;
+
+  for (index, (pixel_a, pixel_b)) in a.chunks_exact(4).zip(b.chunks_exact(4)).enumerate() {
+    for channel in 0..3 {
+      let difference = pixel_a[channel].abs_diff(pixel_b[channel]) as u64;
+      squared_error += difference * difference;
+    }
+    max_alpha_difference = max_alpha_difference.max(pixel_a[3].abs_diff(pixel_b[3]) as u32);
+
+    let delta_e = if pixel_a[0..3] == pixel_b[0..3] {
+      0.0
+    } else {
+      delta_e_2000(to_lab(&pixel_a[0..3]), to_lab(&pixel_b[0..3]))
+    };
+    max_delta_e = max_delta_e.max(delta_e);
+    total_delta_e += delta_e;
+
+    if let Some(heat_map) = &mut heat_map {
+      let colour = heat_colour(delta_e / (limits.max_delta_e * 2.0));
+      heat_map[index * 4..index * 4 + 4].copy_from_slice(&[colour[0], colour[1], colour[2], 255]);
+    }
+  }
+
+  let psnr = if squared_error == 0 {
+    f64::INFINITY
+  } else {
+    let mean_squared_error = squared_error as f64 / (pixel_count * 3) as f64;
+    10.0 * (255.0 * 255.0 / mean_squared_error).log10()
+  };
+  let ssim = mean_ssim(kr, kb, width as usize, height as usize, a, b);
+  let mean_delta_e = if pixel_count == 0 {
+    0.0
+  } else {
+    total_delta_e / pixel_count as f64
+  };
+
+  Comparison {
+    psnr,
+    ssim,
+    max_delta_e,
+    mean_delta_e,
+    max_alpha_difference,
+    heat_map,
+    pass: psnr >= limits.min_psnr
+      && ssim >= limits.min_ssim
+      && max_delta_e <= limits.max_delta_e
+      && max_alpha_difference <= limits.max_alpha_difference,
+  }
+}
+
+/// The mean SSIM of the luma of two images, over non-overlapping windows
+fn mean_ssim(kr: f32, kb: f32, width: usize, height: usize, a: &[u8], b: &[u8]) -> f64 {
+  let weights = [kr as f64, 1.0 - kr as f64 - kb as f64, kb as f64];
+  let luma = |image: &[u8], x: usize, y: usize| {
+    let offset = (y * width + x) * 4;
+    (0..3)
+      .map(|channel| weights[channel] * image[offset + channel] as f64)
+      .sum::<f64>()
+  };
+
+  let mut total = 0.0;
+  let mut window_count = 0;
+  for top in (0..height).step_by(SSIM_WINDOW) {
+    for left in (0..width).step_by(SSIM_WINDOW) {
+      let bottom = (top + SSIM_WINDOW).min(height);
+      let right = (left + SSIM_WINDOW).min(width);
+      let count = ((bottom - top) * (right - left)) as f64;
+
+      let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+      for y in top..bottom {
+        for x in left..right {
+          let (value_a, value_b) = (luma(a, x, y), luma(b, x, y));
+          sum_a += value_a;
+          sum_b += value_b;
+          sum_aa += value_a * value_a;
+          sum_bb += value_b * value_b;
+          sum_ab += value_a * value_b;
+        }
+      }
+
+      let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+      let variance_a = sum_aa / count - mean_a * mean_a;
+      let variance_b = sum_bb / count - mean_b * mean_b;
+      let covariance = sum_ab / count - mean_a * mean_b;
+
+      total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (variance_a + variance_b + SSIM_C2));
+      window_count += 1;
+    }
+  }
+
+  if window_count == 0 {
+    1.0
+  } else {
+    total / window_count as f64
+  }
+}
+
+/// Convert an 8-bit RGB pixel with BT.709 primaries to CIELAB, relative to D65 white
+///
+/// The pixel is linearised with the sRGB display transfer function, as CIELAB describes the light that is seen rather
+/// than the light the camera captured
+fn to_lab(rgb: &[u8]) -> [f64; 3] {
+  let [r, g, b] = [0, 1, 2].map(|channel| srgb_to_linear(rgb[channel] as f64 / 255.0));
+
+  let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+  let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+  let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+  const DELTA: f64 = 6.0 / 29.0;
+  let f = |t: f64| {
+    if t > DELTA * DELTA * DELTA {
+      t.cbrt()
+    } else {
+      t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+    }
+  };
+  let (fx, fy, fz) = (f(x), f(y), f(z));
+
+  [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// The CIEDE2000 difference between two CIELAB colours
+fn delta_e_2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+  let pow25_7 = 25.0_f64.powi(7);
+  let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+  let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+
+  let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+  let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+  let hue = |b: f64, a: f64| {
+    if a == 0.0 && b == 0.0 {
+      0.0
+    } else {
+      b.atan2(a).to_degrees().rem_euclid(360.0)
+    }
+  };
+  let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+  let delta_l = l2 - l1;
+  let delta_c = c2 - c1;
+  let delta_h = if c1 * c2 == 0.0 {
+    0.0
+  } else {
+    let difference = h2 - h1;
+    if difference > 180.0 {
+      difference - 360.0
+    } else if difference < -180.0 {
+      difference + 360.0
+    } else {
+      difference
+    }
+  };
+  let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+  let l_bar = (l1 + l2) / 2.0;
+  let c_bar = (c1 + c2) / 2.0;
+  let h_bar = if c1 * c2 == 0.0 {
+    h1 + h2
+  } else if (h1 - h2).abs() <= 180.0 {
+    (h1 + h2) / 2.0
+  } else if h1 + h2 < 360.0 {
+    (h1 + h2 + 360.0) / 2.0
+  } else {
+    (h1 + h2 - 360.0) / 2.0
+  };
+
+  let cos = |degrees: f64| degrees.to_radians().cos();
+  let t = 1.0 - 0.17 * cos(h_bar - 30.0) + 0.24 * cos(2.0 * h_bar) + 0.32 * cos(3.0 * h_bar + 6.0)
+    - 0.20 * cos(4.0 * h_bar - 63.0);
+  let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+  let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt();
+  let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+  let s_c = 1.0 + 0.045 * c_bar;
+  let s_h = 1.0 + 0.015 * c_bar * t;
+  let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+  let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+  (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+/// A colour for a heat map, for a value from 0 to 1
+fn heat_colour(value: f64) -> [u8; 3] {
+  const STOPS: [[f64; 3]; 5] = [
+    [0.0, 0.0, 0.0],
+    [0.0, 0.0, 255.0],
+    [0.0, 255.0, 0.0],
+    [255.0, 255.0, 0.0],
+    [255.0, 0.0, 0.0],
+  ];
+
+  let position = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+  let index = (position as usize).min(STOPS.len() - 2);
+  let fraction = position - index as f64;
+  let [from, to] = [STOPS[index], STOPS[index + 1]];
+  [0, 1, 2].map(|channel| (from[channel] + (to[channel] - from[channel]) * fraction).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KR: f32 = 0.2126;
+  const KB: f32 = 0.0722;
+
+  fn limits() -> CompareLimits {
+    CompareLimits {
+      min_psnr: 40.0,
+      min_ssim: 0.98,
+      max_delta_e: 3.0,
+      max_alpha_difference: 2,
+    }
+  }
+
+  fn gradient(width: usize, height: usize) -> Vec<u8> {
+    (0..width * height)
+      .flat_map(|i| {
+        [
+          (i * 7 % 256) as u8,
+          (i * 3 % 256) as u8,
+          (i % 256) as u8,
+          255,
+        ]
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_delta_e_2000() {
+    // Pairs from the test data of Sharma, Wu and Dalal
+    let pairs = [
+      ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+      ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+      ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+      (
+        [2.0776, 0.0795, -1.1350],
+        [0.9033, -0.0636, -0.5514],
+        0.9082,
+      ),
+    ];
+    for (lab1, lab2, expected) in pairs {
+      let delta_e = delta_e_2000(lab1, lab2);
+      assert!((delta_e - expected).abs() < 0.0001, "{delta_e} {expected}");
+    }
+  }
+
+  #[test]
+  fn test_to_lab() {
+    // Reference values for sRGB colours, which share the BT.709 primaries
+    let colours = [
+      ([0, 0, 0], [0.0, 0.0, 0.0]),
+      ([255, 255, 255], [100.0, 0.0, 0.0]),
+      ([119, 119, 119], [50.03, 0.0, 0.0]),
+      ([255, 0, 0], [53.24, 80.09, 67.20]),
+      ([0, 0, 255], [32.30, 79.19, -107.86]),
+      // Dark tones are where the display and camera transfer functions differ most
+      ([20, 20, 20], [6.32, 0.0, 0.0]),
+    ];
+    for (rgb, expected) in colours {
+      let lab = to_lab(&rgb);
+      for (value, expected) in lab.into_iter().zip(expected) {
+        assert!(
+          (value - expected).abs() < 0.05,
+          "{rgb:?} {lab:?} {expected}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_identical() {
+    let image = gradient(16, 16);
+    let comparison = compare_stills(KR, KB, 16, 16, &image, &image, limits(), true);
+    assert_eq!(comparison.psnr, f64::INFINITY);
+    assert!((comparison.ssim - 1.0).abs() < 1e-9);
+    assert_eq!(comparison.max_delta_e, 0.0);
+    assert_eq!(comparison.max_alpha_difference, 0);
+    assert!(comparison.pass);
+    assert!(comparison
+      .heat_map
+      .unwrap()
+      .chunks_exact(4)
+      .all(|pixel| pixel == [0, 0, 0, 255]));
+  }
+
+  #[test]
+  fn test_rounding_passes() {
+    let a = gradient(16, 16);
+    let b: Vec<u8> = a
+      .iter()
+      .enumerate()
+      .map(|(i, &value)| {
+        if i % 4 != 3 && i % 3 == 0 {
+          value.saturating_add(1)
+        } else {
+          value
+        }
+      })
+      .collect();
+
+    let comparison = compare_stills(KR, KB, 16, 16, &a, &b, limits(), false);
+    assert!(comparison.psnr > 50.0, "{}", comparison.psnr);
+    assert!(comparison.max_delta_e < 2.0, "{}", comparison.max_delta_e);
+    assert!(comparison.heat_map.is_none());
+    assert!(comparison.pass);
+  }
+
+  #[test]
+  fn test_changed_region_fails() {
+    let a = gradient(16, 16);
+    let mut b = a.clone();
+    // A red square in the corner
+    for y in 0..4 {
+      for x in 0..4 {
+        let offset = (y * 16 + x) * 4;
+        b[offset..offset + 4].copy_from_slice(&[255, 0, 0, 255]);
+      }
+    }
+
+    let comparison = compare_stills(KR, KB, 16, 16, &a, &b, limits(), true);
+    assert!(comparison.max_delta_e > 10.0);
+    assert!(comparison.ssim < 1.0);
+    assert!(!comparison.pass);
+
+    let heat_map = comparison.heat_map.unwrap();
+    assert_eq!(heat_map[0..4], [255, 0, 0, 255]);
+    assert_eq!(heat_map[16 * 4 * 8..16 * 4 * 8 + 4], [0, 0, 0, 255]);
+  }
+
+  #[test]
+  fn test_alpha_difference() {
+    let a = gradient(8, 1);
+    let mut b = a.clone();
+    b[7] = 200;
+
+    let comparison = compare_stills(KR, KB, 8, 1, &a, &b, limits(), false);
+    assert_eq!(comparison.max_alpha_difference, 55);
+    assert_eq!(comparison.psnr, f64::INFINITY);
+    assert!(!comparison.pass);
+  }
+}
@@ -0,0 +1,232 @@
+//! The conformance corpus in `fixtures/conformance`, of the hashes of stills encoded at each resolution the ATEM
+//! supports, with the RLE compressed stills and the decoded images of the smaller ones
+//!
+//! There are no captures of stills from a real switcher, so the corpus is synthetic, generated by this library from a
+//! test pattern. It pins the current output, so that changes to the colour constants or the RLE framing get noticed.
+//! The stills are converted with fixed-point arithmetic, which gives the same output on every architecture. After an
+//! intended change, the corpus can be regenerated by running the tests with `ATEM_UPDATE_CONFORMANCE=1`
+
+use crate::api::{
+  decode_yuva_422_to_rgba, encode_rgba_to_yuva_422, encode_rle, Arithmetic, DecodeOptions,
+  EncodeOptions, VideoRange,
+};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The environment variable which regenerates the corpus, instead of checking against it
+const UPDATE_VARIABLE: &str = "ATEM_UPDATE_CONFORMANCE";
+
+/// The stills in the corpus, as the name, the dimensions, the range, and whether its files are checked in rather than
+/// only its hashes, which keeps the corpus small
+const STILLS: [(&str, u32, u32, VideoRange, bool); 7] = [
+  ("525i", 720, 486, VideoRange::Limited, true),
+  ("625i", 720, 576, VideoRange::Limited, true),
+  ("720p", 1280, 720, VideoRange::Limited, true),
+  ("1080p", 1920, 1080, VideoRange::Limited, true),
+  ("1080p-full", 1920, 1080, VideoRange::Full, true),
+  ("2160p", 3840, 2160, VideoRange::Limited, false),
+  ("4320p", 7680, 4320, VideoRange::Limited, false),
+];
+
+fn corpus_directory() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("../fixtures/conformance")
+}
+
+/// Colour bars over the top half, then luma and alpha ramps, then blocks of pseudo-random colour and alpha
+fn test_pattern(width: u32, height: u32) -> Vec<u8> {
+  const BARS: [[u8; 3]; 8] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+    [0, 0, 0],
+  ];
+
+  let mut image = Vec::with_capacity((width * height * 4) as usize);
+  for y in 0..height {
+    for x in 0..width {
+      let ramp = (x * 255 / (width - 1)) as u8;
+      let pixel = if y < height / 2 {
+        let [r, g, b] = BARS[(x * 8 / width) as usize];
+        [r, g, b, 255]
+      } else if y < height * 5 / 8 {
+        [ramp, ramp, ramp, 255]
+      } else if y < height * 3 / 4 {
+        [255, 128, 0, ramp]
+      } else {
+        let block = (x / 16).wrapping_mul(0x9e37_79b9) ^ (y / 16).wrapping_mul(0x85eb_ca6b);
+        block.wrapping_mul(0xc2b2_ae35).to_le_bytes()
+      };
+      image.extend_from_slice(&pixel);
+    }
+  }
+  image
+}
+
+fn md5_hex(data: &[u8]) -> String {
+  format!("{:x}", md5::compute(data))
+}
+
+fn read_png(path: &Path) -> Vec<u8> {
+  let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  let mut pixels = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut pixels).unwrap();
+  assert_eq!(info.color_type, png::ColorType::Rgba, "{path:?}");
+  pixels.truncate(info.buffer_size());
+  pixels
+}
+
+/// Write an unfiltered RGBA PNG, which is simple for the Jest tests to read
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+  let mut encoder = png::Encoder::new(std::fs::File::create(path).unwrap(), width, height);
+  encoder.set_color(png::ColorType::Rgba);
+  encoder.set_depth(png::BitDepth::Eight);
+  encoder.set_filter(png::FilterType::NoFilter);
+  encoder.set_adaptive_filter(png::AdaptiveFilterType::NonAdaptive);
+  encoder.set_compression(png::Compression::Best);
+  encoder
+    .write_header()
+    .unwrap()
+    .write_image_data(pixels)
+    .unwrap();
+}
+
+fn read_gz(path: &Path) -> Vec<u8> {
+  let mut data = Vec::new();
+  GzDecoder::new(std::fs::File::open(path).unwrap())
+    .read_to_end(&mut data)
+    .unwrap();
+  data
+}
+
+fn write_gz(path: &Path, data: &[u8]) {
+  let mut encoder = GzEncoder::new(
+    std::fs::File::create(path).unwrap(),
+    flate2::Compression::best(),
+  );
+  encoder.write_all(data).unwrap();
+  encoder.finish().unwrap();
+}
+
+fn convert(width: u32, height: u32, range: VideoRange, rgba: &[u8]) -> (Vec<u8>, Vec<u8>) {
+  let mut encoded = vec![0; rgba.len()];
+  encode_rgba_to_yuva_422(
+    width,
+    height,
+    rgba,
+    &mut encoded,
+    &EncodeOptions {
+      range: Some(range),
+      arithmetic: Some(Arithmetic::Fixed),
+      ..Default::default()
+    },
+  )
+  .unwrap();
+
+  let decoded = decode(width, height, range, &encoded);
+  (encoded, decoded)
+}
+
+fn decode(width: u32, height: u32, range: VideoRange, data: &[u8]) -> Vec<u8> {
+  let mut decoded = vec![0; (width * height * 4) as usize];
+  decode_yuva_422_to_rgba(
+    width,
+    height,
+    data,
+    &mut decoded,
+    &DecodeOptions {
+      range: Some(range),
+      arithmetic: Some(Arithmetic::Fixed),
+      ..Default::default()
+    },
+  )
+  .unwrap();
+  decoded
+}
+
+fn update_corpus(directory: &Path) {
+  std::fs::create_dir_all(directory).unwrap();
+
+  let mut stills = Vec::new();
+  for (name, width, height, range, files) in STILLS {
+    let source = test_pattern(width, height);
+    let (encoded, decoded) = convert(width, height, range, &source);
+    let rle = encode_rle(&encoded);
+
+    let mut still = json!({
+      "name": name,
+      "width": width,
+      "height": height,
+      "range": if range == VideoRange::Full { "full" } else { "limited" },
+      "arithmetic": "fixed",
+      "sourceMd5": md5_hex(&source),
+      "yuvaMd5": md5_hex(&encoded),
+      "rleMd5": md5_hex(&rle),
+      "decodedMd5": md5_hex(&decoded),
+    });
+    if files {
+      let (rle_file, decoded_file) = (format!("{name}.rle.yuva.gz"), format!("{name}.decoded.png"));
+      write_gz(&directory.join(&rle_file), &rle);
+      write_png(&directory.join(&decoded_file), width, height, &decoded);
+      still["rle"] = json!(rle_file);
+      still["decoded"] = json!(decoded_file);
+    }
+    stills.push(still);
+  }
+
+  let manifest = serde_json::to_string_pretty(&json!({ "stills": stills })).unwrap();
+  std::fs::write(directory.join("manifest.json"), manifest + "\n").unwrap();
+}
+
+#[test]
+fn test_conformance_corpus() {
+  let directory = corpus_directory();
+  if std::env::var_os(UPDATE_VARIABLE).is_some() {
+    update_corpus(&directory);
+  }
+
+  let manifest: Value =
+    serde_json::from_slice(&std::fs::read(directory.join("manifest.json")).unwrap()).unwrap();
+  let stills = manifest["stills"].as_array().unwrap();
+  assert_eq!(stills.len(), STILLS.len());
+
+  for (still, (name, width, height, range, files)) in stills.iter().zip(STILLS) {
+    assert_eq!(still["name"], name);
+    assert_eq!(still["width"], width);
+    assert_eq!(still["height"], height);
+    let file = |key: &str| directory.join(still[key].as_str().unwrap());
+    let md5 = |key: &str| still[key].as_str().unwrap().to_string();
+
+    // The source is the test pattern, so that a change to the corpus is deliberate
+    let source = test_pattern(width, height);
+    assert_eq!(md5_hex(&source), md5("sourceMd5"), "{name} source");
+
+    // Colour regressions change the encoded or decoded images
+    let (encoded, decoded) = convert(width, height, range, &source);
+    assert_eq!(md5_hex(&encoded), md5("yuvaMd5"), "{name} encode");
+    assert_eq!(md5_hex(&decoded), md5("decodedMd5"), "{name} decode");
+
+    // Framing regressions change the compressed image, or how it expands
+    let rle = encode_rle(&encoded);
+    assert!(rle.len() < encoded.len(), "{name} is not compressed");
+    assert_eq!(md5_hex(&rle), md5("rleMd5"), "{name} RLE encode");
+    assert_eq!(
+      md5_hex(&decode(width, height, range, &rle)),
+      md5("decodedMd5"),
+      "{name} RLE decode"
+    );
+
+    assert_eq!(still.get("rle").is_some(), files, "{name} files");
+    if files {
+      assert!(read_gz(&file("rle")) == rle, "{name} RLE file");
+      assert!(read_png(&file("decoded")) == decoded, "{name} decoded file");
+    }
+  }
+}
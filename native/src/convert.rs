@@ -0,0 +1,757 @@
+use crate::decode_lut::{decode_rgba_row_lut, prefer_lut};
+use crate::dispatch::{active_path, decode_rgba_row, encode_rgba_row, SimdPath};
+use crate::dither::dither_offsets;
+use crate::error::{Error, Result};
+use crate::fixed_point::{rgb_to_yuva422_fixed, yuva422_to_fill_key_fixed, yuva422_to_rgb_fixed};
+use crate::javascript::rgb_to_yuva422_javascript;
+use crate::options::{
+  Arithmetic, DecodeFormat, EncodeFormat, InputFormat, KeyFormat, KeyTransform, OutputFormat,
+  TransferFunction,
+};
+use crate::rgba_to_yuva422_simd::{
+  fill_key_to_rgba, fill_key_to_yuva422_simd, rgb16_to_yuva422_simd, rgbf32_to_yuva422_simd,
+};
+use crate::rle::RLEDecoder;
+use crate::yuv_constants::YuvConstantsSimd;
+use crate::yuva422_to_rgba_simd::{
+  yuva422_to_fill_key_simd, yuva422_to_rgb10_simd, yuva422_to_rgb16_simd, yuva422_to_rgb_simd,
+  yuva422_to_rgbf32_simd,
+};
+
+pub(crate) fn validate_dimensions(width: u32, _height: u32) -> Result<()> {
+  if width % 8 != 0 {
+    return Err(Error::from_reason("Width must be a multiple of 8"));
+  }
+
+  Ok(())
+}
+
+/// Reject the `javascript` arithmetic where it can't be used, as atem-connection only encodes limited range 8-bit RGBA
+pub(crate) fn check_arithmetic(
+  constants: &YuvConstantsSimd,
+  format: InputFormat,
+  arithmetic: Arithmetic,
+) -> Result<()> {
+  if arithmetic == Arithmetic::Javascript
+    && (constants.javascript.is_none() || format != InputFormat::Rgba)
+  {
+    return Err(Error::from_reason(
+      "The javascript arithmetic only encodes limited range rgba input",
+    ));
+  }
+  Ok(())
+}
+
+/// The largest number of bytes used by a pixel of any supported format
+pub(crate) const MAX_BYTES_PER_PIXEL: usize = 16;
+
+/// The layout of the rows of an image in a buffer
+#[derive(Clone, Copy)]
+pub(crate) struct RowLayout {
+  /// The number of bytes of pixel data in each row
+  pub row_bytes: usize,
+  /// The number of bytes from the start of one row to the start of the next
+  pub stride: usize,
+  /// Whether the stride was specified, rather than the rows being tightly packed
+  pub padded: bool,
+}
+impl RowLayout {
+  pub fn new(width: u32, bytes_per_pixel: usize, stride: Option<u32>) -> Result<RowLayout> {
+    let row_bytes = width as usize * bytes_per_pixel;
+    let Some(stride) = stride else {
+      return Ok(RowLayout {
+        row_bytes,
+        stride: row_bytes,
+        padded: false,
+      });
+    };
+
+    let stride = stride as usize;
+    if stride < row_bytes {
+      return Err(Error::from_reason(format!(
+        "Stride must be at least {row_bytes} bytes"
+      )));
+    }
+
+    Ok(RowLayout {
+      row_bytes,
+      stride,
+      padded: true,
+    })
+  }
+
+  /// The number of bytes needed to hold an image of this layout. The final row does not need any padding
+  pub fn byte_len(&self, height: u32) -> usize {
+    if height == 0 {
+      0
+    } else {
+      self.stride * (height as usize - 1) + self.row_bytes
+    }
+  }
+
+  pub fn check_len(&self, height: u32, len: usize) -> bool {
+    if self.padded {
+      len >= self.byte_len(height)
+    } else {
+      len == self.byte_len(height)
+    }
+  }
+}
+
+pub(crate) fn rgba_to_yuva_422(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  input_format: EncodeFormat,
+  input_layout: RowLayout,
+  output: &mut [u8],
+) -> Result<()> {
+  let output_row_bytes = width as usize * 4;
+  if !input_layout.check_len(height, input.len()) {
+    return Err(Error::from_reason("Input buffer has incorrect length"));
+  }
+  if output.len() != output_row_bytes * height as usize {
+    return Err(Error::from_reason("Output buffer has incorrect length"));
+  }
+  check_arithmetic(constants, input_format.format, input_format.arithmetic)?;
+
+  let threads = input_format.threads.min(height as usize);
+  if threads <= 1 || output_row_bytes == 0 {
+    encode_rows(constants, input_format, input_layout, input, 0, output);
+    return Ok(());
+  }
+
+  // Split the rows into a band for each thread, converting the first band on this thread
+  let band_rows = (height as usize).div_ceil(threads);
+  std::thread::scope(|scope| {
+    let mut bands = output.chunks_mut(band_rows * output_row_bytes).enumerate();
+    let (_, first_band) = bands.next().unwrap();
+    for (band, output_band) in bands {
+      scope.spawn(move || {
+        encode_rows(
+          constants,
+          input_format,
+          input_layout,
+          input,
+          band * band_rows,
+          output_band,
+        )
+      });
+    }
+    encode_rows(constants, input_format, input_layout, input, 0, first_band);
+  });
+
+  Ok(())
+}
+
+/// Encode separate images of 8-bit RGB fill and a key, as though they were combined into RGBA first
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fill_key_to_yuva_422(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  fill: &[u8],
+  key: &[u8],
+  key_format: KeyFormat,
+  key_transform: KeyTransform,
+  arithmetic: Arithmetic,
+  output: &mut [u8],
+) -> Result<()> {
+  let pixels = width as usize * height as usize;
+  if fill.len() != pixels * 3 {
+    return Err(Error::from_reason("Fill buffer has incorrect length"));
+  }
+  if key.len() != pixels * key_format.bytes_per_pixel() {
+    return Err(Error::from_reason("Key buffer has incorrect length"));
+  }
+  if output.len() != pixels * 4 {
+    return Err(Error::from_reason("Output buffer has incorrect length"));
+  }
+  // The fill and key are combined into 8-bit RGBA
+  check_arithmetic(constants, InputFormat::Rgba, arithmetic)?;
+
+  let key_batches = key.chunks_exact(key_format.bytes_per_pixel() * 8);
+  let batches = fill
+    .chunks_exact(24)
+    .zip(key_batches)
+    .zip(output.chunks_exact_mut(32));
+  let rgba = |fill_batch, key_batch| {
+    fill_key_to_rgba(constants, fill_batch, key_batch, key_format, key_transform)
+  };
+
+  match arithmetic {
+    Arithmetic::Fixed => {
+      for ((fill_batch, key_batch), output_batch) in batches {
+        rgb_to_yuva422_fixed(&constants.fixed, &rgba(fill_batch, key_batch), output_batch);
+      }
+    }
+    Arithmetic::Javascript => {
+      let javascript = (constants.javascript.as_ref())
+        .expect("The javascript arithmetic is only used for limited range");
+      for ((fill_batch, key_batch), output_batch) in batches {
+        rgb_to_yuva422_javascript(javascript, &rgba(fill_batch, key_batch), output_batch);
+      }
+    }
+    Arithmetic::Float => {
+      for ((fill_batch, key_batch), output_batch) in batches {
+        fill_key_to_yuva422_simd(
+          constants,
+          fill_batch,
+          key_batch,
+          key_format,
+          key_transform,
+          output_batch,
+        );
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Encode the rows of the image starting at `first_row`, filling the output
+fn encode_rows(
+  constants: &YuvConstantsSimd,
+  input_format: EncodeFormat,
+  input_layout: RowLayout,
+  input: &[u8],
+  first_row: usize,
+  output: &mut [u8],
+) {
+  let output_row_bytes = input_layout.row_bytes / input_format.format.bytes_per_pixel() * 4;
+  for (i, output_row) in output.chunks_exact_mut(output_row_bytes).enumerate() {
+    let row = first_row + i;
+    let input_start = row * input_layout.stride;
+    let input_row = &input[input_start..input_start + input_layout.row_bytes];
+
+    encode_row(constants, input_format, 0, row, input_row, output_row);
+  }
+}
+
+/// Encode a row of pixels, which must be a multiple of 8 pixels long
+///
+/// The `x` and `y` position of the first pixel in the image is used for dithering. The arithmetic must have been
+/// checked with `check_arithmetic`
+pub(crate) fn encode_row(
+  constants: &YuvConstantsSimd,
+  input_format: EncodeFormat,
+  x: usize,
+  y: usize,
+  input: &[u8],
+  output: &mut [u8],
+) {
+  let input_batch_bytes = input_format.format.bytes_per_pixel() * 8;
+  let batches = input
+    .chunks_exact(input_batch_bytes)
+    .zip(output.chunks_exact_mut(32))
+    .enumerate()
+    .map(|(i, (input_batch, output_batch))| {
+      let dither = dither_offsets(input_format.dither, x + i * 8, y);
+      (input_batch, output_batch, dither)
+    });
+
+  match input_format.format {
+    // 8-bit input has no extra precision to dither
+    InputFormat::Rgba if input_format.arithmetic == Arithmetic::Fixed => {
+      for (input_batch, output_batch, _) in batches {
+        rgb_to_yuva422_fixed(&constants.fixed, input_batch, output_batch);
+      }
+    }
+    InputFormat::Rgba if input_format.arithmetic == Arithmetic::Javascript => {
+      let javascript = (constants.javascript.as_ref())
+        .expect("The javascript arithmetic is only used for limited range");
+      for (input_batch, output_batch, _) in batches {
+        rgb_to_yuva422_javascript(javascript, input_batch, output_batch);
+      }
+    }
+    InputFormat::Rgba => {
+      let path = input_format.path.unwrap_or_else(active_path);
+      encode_rgba_row(constants, path, input, output)
+    }
+    InputFormat::Rgba16le | InputFormat::Rgba16be => {
+      let big_endian = input_format.format == InputFormat::Rgba16be;
+      for (input_batch, output_batch, dither) in batches {
+        rgb16_to_yuva422_simd(
+          constants,
+          input_batch,
+          big_endian,
+          dither.as_ref(),
+          output_batch,
+        );
+      }
+    }
+    InputFormat::Rgbaf32 => {
+      let linear = input_format.transfer == TransferFunction::Linear;
+      for (input_batch, output_batch, dither) in batches {
+        rgbf32_to_yuva422_simd(
+          constants,
+          input_batch,
+          linear,
+          dither.as_ref(),
+          output_batch,
+        );
+      }
+    }
+  }
+}
+
+pub(crate) fn yuva_422_to_rgba(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  output: &mut [u8],
+  output_format: DecodeFormat,
+  output_layout: RowLayout,
+) -> Result<()> {
+  let row_bytes = width as usize * output_format.format.bytes_per_pixel();
+  // RLE means the input data could have any length, so it can't be checked here
+  if !output_layout.check_len(height, output.len()) {
+    return Err(Error::from_reason("Output buffer has incorrect length"));
+  }
+
+  // A padded output may be longer than the image, which must not be split into more bands
+  let output = &mut output[..output_layout.byte_len(height)];
+  let mut decoder = RLEDecoder::new(input);
+  let use_lut = output_format.format == OutputFormat::Rgba
+    && output_format.arithmetic != Arithmetic::Fixed
+    && output_format.path.is_none()
+    && prefer_lut(width as usize * height as usize);
+
+  let threads = output_format.threads.min(height as usize);
+  let decoded = if threads <= 1 || row_bytes == 0 {
+    decode_rows(
+      constants,
+      decoder,
+      output_format,
+      use_lut,
+      0,
+      row_bytes,
+      output_layout.stride,
+      output,
+    )
+  } else {
+    // Each band starts where the previous one ends in the input, which is found by skipping over the samples, so
+    // that RLE compressed input can be split too
+    let band_rows = (height as usize).div_ceil(threads);
+    let band_samples = band_rows * width as usize / 2;
+    let bands = output.chunks_mut(band_rows * output_layout.stride);
+    let mut band_decoders = Vec::with_capacity(threads);
+    for _ in 0..bands.len() {
+      band_decoders.push(decoder.clone());
+      decoder.skip_samples(band_samples);
+    }
+
+    std::thread::scope(|scope| {
+      let mut bands = bands.zip(band_decoders).enumerate();
+      let (_, (first_band, first_decoder)) = bands.next().unwrap();
+      let handles: Vec<_> = bands
+        .map(|(band, (output_band, band_decoder))| {
+          scope.spawn(move || {
+            decode_rows(
+              constants,
+              band_decoder,
+              output_format,
+              use_lut,
+              band * band_rows,
+              row_bytes,
+              output_layout.stride,
+              output_band,
+            )
+          })
+        })
+        .collect();
+
+      let first_decoded = decode_rows(
+        constants,
+        first_decoder,
+        output_format,
+        use_lut,
+        0,
+        row_bytes,
+        output_layout.stride,
+        first_band,
+      );
+      handles.into_iter().fold(first_decoded, |decoded, handle| {
+        handle.join().unwrap() && decoded
+      })
+    })
+  };
+
+  if !decoded {
+    // Throw if not enough data was decoded
+    return Err(Error::from_reason(
+      "Input buffer has less data than expected",
+    ));
+  }
+
+  Ok(())
+}
+
+/// Decode an image into separate buffers of 8-bit RGB fill, and 8-bit key from the alpha
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn yuva_422_to_fill_key(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  fill: &mut [u8],
+  key: &mut [u8],
+  premultiply: bool,
+  arithmetic: Arithmetic,
+) -> Result<()> {
+  let pixels = width as usize * height as usize;
+  // RLE means the input data could have any length, so it can't be checked here
+  if fill.len() != pixels * 3 {
+    return Err(Error::from_reason("Fill buffer has incorrect length"));
+  }
+  if key.len() != pixels {
+    return Err(Error::from_reason("Key buffer has incorrect length"));
+  }
+
+  let mut decoder = RLEDecoder::new(input);
+  for (fill_batch, key_batch) in fill.chunks_exact_mut(24).zip(key.chunks_exact_mut(8)) {
+    let (Some(sample1), Some(sample2), Some(sample3), Some(sample4)) = (
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+    ) else {
+      return Err(Error::from_reason(
+        "Input buffer has less data than expected",
+      ));
+    };
+
+    if arithmetic == Arithmetic::Fixed {
+      yuva422_to_fill_key_fixed(
+        &constants.fixed,
+        sample1,
+        sample2,
+        sample3,
+        sample4,
+        premultiply,
+        fill_batch,
+        key_batch,
+      );
+    } else {
+      yuva422_to_fill_key_simd(
+        constants,
+        sample1,
+        sample2,
+        sample3,
+        sample4,
+        premultiply,
+        fill_batch,
+        key_batch,
+      );
+    }
+  }
+
+  Ok(())
+}
+
+/// Decode the rows of the image starting at `first_row` into the output, returning false if the input ran out of data
+#[allow(clippy::too_many_arguments)]
+fn decode_rows(
+  constants: &YuvConstantsSimd,
+  mut decoder: RLEDecoder,
+  output_format: DecodeFormat,
+  use_lut: bool,
+  first_row: usize,
+  row_bytes: usize,
+  stride: usize,
+  output: &mut [u8],
+) -> bool {
+  let rows = output.len().div_ceil(stride);
+  (0..rows).all(|i| {
+    let row_start = i * stride;
+    decode_row(
+      constants,
+      &mut decoder,
+      output_format,
+      use_lut,
+      first_row + i,
+      &mut output[row_start..row_start + row_bytes],
+    )
+  })
+}
+
+/// Decode a row of pixels, returning false if the input ran out of data
+///
+/// When `use_lut` is set, `rgba` output with float arithmetic is decoded with the lookup tables
+fn decode_row(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  output_format: DecodeFormat,
+  use_lut: bool,
+  y: usize,
+  output: &mut [u8],
+) -> bool {
+  if use_lut {
+    return decode_rgba_row_lut(constants, decoder, output_format.dither, y, output);
+  }
+
+  let path = output_format.path.unwrap_or_else(active_path);
+  if output_format.format == OutputFormat::Rgba
+    && output_format.dither.is_none()
+    && output_format.arithmetic != Arithmetic::Fixed
+    && path != SimdPath::Portable
+  {
+    return decode_rgba_row(constants, path, decoder, output);
+  }
+
+  let output_batch_bytes = output_format.format.bytes_per_pixel() * 8;
+  let linear = output_format.transfer == TransferFunction::Linear;
+  let mut write_offset = 0;
+
+  while write_offset < output.len() {
+    let sample1 = decoder.read_sample();
+    let sample2 = decoder.read_sample();
+    let sample3 = decoder.read_sample();
+    let sample4 = decoder.read_sample();
+
+    // TODO - this could be handled better if needed, but that is difficult to do sanely
+    let (Some(sample1), Some(sample2), Some(sample3), Some(sample4)) =
+      (sample1, sample2, sample3, sample4)
+    else {
+      return false; // Not enough data
+    };
+
+    let old_write_offset = write_offset;
+    write_offset += output_batch_bytes;
+
+    let output_batch = &mut output[old_write_offset..write_offset];
+    match output_format.format {
+      OutputFormat::Rgba if output_format.arithmetic == Arithmetic::Fixed => {
+        let x = old_write_offset / output_batch_bytes * 8;
+        let dither = dither_offsets(output_format.dither, x, y);
+        yuva422_to_rgb_fixed(
+          &constants.fixed,
+          sample1,
+          sample2,
+          sample3,
+          sample4,
+          dither.as_ref(),
+          output_batch,
+        )
+      }
+      OutputFormat::Rgba => {
+        let x = old_write_offset / output_batch_bytes * 8;
+        let dither = dither_offsets(output_format.dither, x, y);
+        yuva422_to_rgb_simd(
+          constants,
+          sample1,
+          sample2,
+          sample3,
+          sample4,
+          dither.as_ref(),
+          output_batch,
+        )
+      }
+      OutputFormat::Rgba16le | OutputFormat::Rgba16be => yuva422_to_rgb16_simd(
+        constants,
+        sample1,
+        sample2,
+        sample3,
+        sample4,
+        output_format.format == OutputFormat::Rgba16be,
+        output_batch,
+      ),
+      OutputFormat::R210 | OutputFormat::A2r10g10b10 => yuva422_to_rgb10_simd(
+        constants,
+        sample1,
+        sample2,
+        sample3,
+        sample4,
+        output_format.format == OutputFormat::A2r10g10b10,
+        output_batch,
+      ),
+      OutputFormat::Rgbaf32 => yuva422_to_rgbf32_simd(
+        constants,
+        sample1,
+        sample2,
+        sample3,
+        sample4,
+        linear,
+        output_batch,
+      ),
+    }
+  }
+
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::options::{DecodeOptions, Dither, EncodeOptions};
+
+  fn test_image(width: u32, height: u32) -> Vec<u8> {
+    (0..width * height * 4)
+      .map(|i| (i * 37 % 256) as u8)
+      .collect()
+  }
+
+  #[test]
+  fn test_encode_stride() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (width, height) = (16, 3);
+    let input = test_image(width, height);
+
+    let mut expected = vec![0; input.len()];
+    let layout = RowLayout::new(width, 4, None).unwrap();
+    rgba_to_yuva_422(
+      &constants,
+      width,
+      height,
+      &input,
+      EncodeOptions::default().encode_format(),
+      layout,
+      &mut expected,
+    )
+    .unwrap();
+
+    // Pad each row with 8 bytes of garbage, and omit the padding from the final row
+    let padded_layout = RowLayout::new(width, 4, Some(72)).unwrap();
+    let mut padded_input = Vec::new();
+    for row in input.chunks_exact(64) {
+      padded_input.extend_from_slice(row);
+      padded_input.extend_from_slice(&[0xff; 8]);
+    }
+    padded_input.truncate(padded_layout.byte_len(height));
+
+    let mut output = vec![0; input.len()];
+    rgba_to_yuva_422(
+      &constants,
+      width,
+      height,
+      &padded_input,
+      EncodeOptions::default().encode_format(),
+      padded_layout,
+      &mut output,
+    )
+    .unwrap();
+    assert_eq!(output, expected);
+  }
+
+  #[test]
+  fn test_decode_stride() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (width, height) = (8, 2);
+    let input = [[4, 8, 0, 64, 4, 8, 0, 64]; 8].concat();
+
+    let layout = RowLayout::new(width, 4, Some(40)).unwrap();
+    let mut output = vec![0xaa; layout.byte_len(height)];
+    yuva_422_to_rgba(
+      &constants,
+      width,
+      height,
+      &input,
+      &mut output,
+      DecodeOptions::default().decode_format(),
+      layout,
+    )
+    .unwrap();
+
+    assert_eq!(output[0..32], [0; 32]);
+    assert_eq!(output[32..40], [0xaa; 8]);
+    assert_eq!(output[40..72], [0; 32]);
+  }
+
+  #[test]
+  fn test_dithered_decode() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (width, height) = (32, 32);
+
+    // Grey luma code 498 is 126.83 in 8-bit, which always rounds to 127 without dithering
+    let word = (940u32 << 20 | 512 << 10 | 498).to_be_bytes();
+    let input = [word, word].concat().repeat((width * height / 2) as usize);
+
+    for dither in [Dither::Bayer, Dither::BlueNoise] {
+      let output_format = DecodeOptions {
+        dither: Some(dither),
+        ..Default::default()
+      }
+      .decode_format();
+      let layout = RowLayout::new(width, 4, None).unwrap();
+
+      let mut output = vec![0; (width * height * 4) as usize];
+      yuva_422_to_rgba(
+        &constants,
+        width,
+        height,
+        &input,
+        &mut output,
+        output_format,
+        layout,
+      )
+      .unwrap();
+
+      let reds: Vec<u8> = output.iter().step_by(4).copied().collect();
+      assert!(reds.iter().all(|&r| r == 126 || r == 127), "{dither:?}");
+      let mean = reds.iter().map(|&r| r as f32).sum::<f32>() / reds.len() as f32;
+      assert!((mean - 126.83).abs() < 0.02, "{dither:?} {mean}");
+
+      let mut repeated = vec![0; output.len()];
+      yuva_422_to_rgba(
+        &constants,
+        width,
+        height,
+        &input,
+        &mut repeated,
+        output_format,
+        layout,
+      )
+      .unwrap();
+      assert_eq!(output, repeated);
+    }
+  }
+
+  #[test]
+  fn test_dithered_encode() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (width, height) = (32, 32);
+
+    // Grey 0x7f40 falls between two 10-bit luma codes
+    let exact = 64.0 + 876.0 * (0x7f40 as f32 / 65535.0);
+    let pixel = [0x7f40u16; 4].map(u16::to_le_bytes).concat();
+    let input = pixel.repeat((width * height) as usize);
+
+    let input_format = EncodeOptions {
+      format: Some(InputFormat::Rgba16le),
+      dither: Some(Dither::BlueNoise),
+      ..Default::default()
+    }
+    .encode_format();
+    let layout = RowLayout::new(width, 8, None).unwrap();
+
+    let mut output = vec![0; (width * height * 4) as usize];
+    rgba_to_yuva_422(
+      &constants,
+      width,
+      height,
+      &input,
+      input_format,
+      layout,
+      &mut output,
+    )
+    .unwrap();
+
+    let lumas: Vec<u32> = output
+      .chunks_exact(4)
+      .map(|word| u32::from_be_bytes(word.try_into().unwrap()) & 0x3ff)
+      .collect();
+    let low = exact.floor() as u32;
+    assert!(lumas.iter().all(|&y| y == low || y == low + 1));
+    let mean = lumas.iter().sum::<u32>() as f32 / lumas.len() as f32;
+    assert!((mean - exact).abs() < 0.02, "{mean} {exact}");
+  }
+
+  #[test]
+  fn test_stride_too_small() {
+    assert!(RowLayout::new(8, 4, Some(31)).is_err());
+    assert!(RowLayout::new(8, 4, Some(32)).is_ok());
+    assert!(RowLayout::new(8, 8, Some(63)).is_err());
+  }
+}
@@ -0,0 +1,266 @@
+use crate::dispatch::{active_path, decode_rgba_row, requested_path};
+use crate::dither::dither_offsets;
+use crate::options::{Colorspace, Dither, VideoRange};
+use crate::rle::RLEDecoder;
+use crate::yuv_constants::YuvConstantsSimd;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The smallest image worth considering the tables for. Below this the cost of building them isn't recovered
+const LUT_MIN_PIXELS: usize = 256 * 256;
+
+/// The contribution of each 10-bit code to the 8-bit RGBA output, as computed by the float kernels
+///
+/// Each colour is the sum of the luma entry and the entries of its chroma codes, in the same order as the float kernels,
+/// so the output is identical to theirs
+pub struct DecodeLut {
+  luma: [f32; 1024],
+  cr_to_r: [f32; 1024],
+  cr_to_g: [f32; 1024],
+  cb_to_g: [f32; 1024],
+  cb_to_b: [f32; 1024],
+  alpha: [f32; 1024],
+}
+impl DecodeLut {
+  pub fn new(constants: &YuvConstantsSimd) -> Box<DecodeLut> {
+    let table = |convert: &dyn Fn(f32) -> f32| std::array::from_fn(|code| convert(code as f32));
+    let chroma = |code: f32| (code - constants.cb_cr_offset[0]) / constants.half_cb_cr_scale[0];
+
+    Box::new(DecodeLut {
+      luma: table(&|code| (code - constants.luma_offset[0]) / constants.luma_scale[0]),
+      cr_to_r: table(&|code| constants.kr_i[0] * chroma(code)),
+      cr_to_g: table(&|code| constants.cr_to_g[0] * chroma(code)),
+      cb_to_g: table(&|code| constants.cb_to_g[0] * chroma(code)),
+      cb_to_b: table(&|code| constants.kb_i[0] * chroma(code)),
+      alpha: table(&|code| (code - constants.alpha_offset[0]) / constants.alpha_scale[0]),
+    })
+  }
+}
+
+/// Convert a sample to 2 pixels of 8-bit RGBA by looking up the contribution of each code, giving the same output as
+/// `yuva422_to_rgb_simd`
+///
+/// This is scalar code, as the lookups are faster than gathering them into vectors
+#[inline(always)]
+fn yuva422_to_rgb_lut(lut: &DecodeLut, sample: &[u8], dither: [f32; 2], target: &mut [u8]) {
+  let ycba = u32::from_be_bytes(sample[0..4].try_into().unwrap());
+  let ycra = u32::from_be_bytes(sample[4..8].try_into().unwrap());
+  // Masking the codes to 10 bits lets the compiler drop the bounds checks
+  let code = |word: u32, shift: u32| (word >> shift) as usize & 0x3ff;
+
+  let (cb, cr) = (code(ycba, 10), code(ycra, 10));
+  let cr_to_r = lut.cr_to_r[cr];
+  let cr_to_g = lut.cr_to_g[cr];
+  let cb_to_g = lut.cb_to_g[cb];
+  let cb_to_b = lut.cb_to_b[cb];
+
+  // The colour is clipped before dithering, as in the float kernel, but alpha is not
+  let to_8bit = |value: f32, dither: f32| round_to_u8(value.clamp(0.0, 255.0) + dither);
+  for (i, word) in [ycba, ycra].into_iter().enumerate() {
+    let y = lut.luma[code(word, 0)];
+    let d = dither[i];
+    target[i * 4] = to_8bit(y + cr_to_r, d);
+    target[i * 4 + 1] = to_8bit(y - cr_to_g - cb_to_g, d);
+    target[i * 4 + 2] = to_8bit(y + cb_to_b, d);
+    target[i * 4 + 3] = round_to_u8(lut.alpha[code(word, 20)] + d);
+  }
+}
+
+/// Round half away from zero and saturate, as `value.round() as u8` does
+///
+/// `f32::round` is a library call on CPUs without SSE4.1, which would take most of the time
+#[inline(always)]
+fn round_to_u8(value: f32) -> u8 {
+  // Negative values truncate to 0, and their fraction is below a half
+  let truncated = value as u32;
+  let rounded = truncated + (value - truncated as f32 >= 0.5) as u32;
+  rounded.min(255) as u8
+}
+
+/// Decode a row of 8-bit RGBA pixels with the lookup tables, returning false if the input ran out of data
+pub(crate) fn decode_rgba_row_lut(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  dither: Option<Dither>,
+  y: usize,
+  output: &mut [u8],
+) -> bool {
+  let lut = constants.decode_lut();
+  for (i, output_batch) in output.chunks_exact_mut(32).enumerate() {
+    let (Some(sample1), Some(sample2), Some(sample3), Some(sample4)) = (
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+    ) else {
+      return false; // Not enough data
+    };
+
+    let [first, second] = dither_offsets(dither, i * 8, y).unwrap_or_default();
+    for (j, (sample, target)) in [sample1, sample2, sample3, sample4]
+      .into_iter()
+      .zip(output_batch.chunks_exact_mut(8))
+      .enumerate()
+    {
+      yuva422_to_rgb_lut(lut, sample, [first[j], second[j]], target);
+    }
+  }
+
+  true
+}
+
+/// Whether an image of this many pixels should be decoded with the lookup tables rather than the SIMD float kernels
+///
+/// Both give identical output, so this only affects the speed. The AVX2 kernels are usually the faster, while the
+/// tables take around half the time of the portable kernels
+pub(crate) fn prefer_lut(pixels: usize) -> bool {
+  pixels >= LUT_MIN_PIXELS && lut_is_faster()
+}
+
+static LUT_IS_FASTER: OnceLock<bool> = OnceLock::new();
+
+/// Whether the tables were faster than the SIMD float kernels on this CPU
+///
+/// A path forced by the environment is always used, as it is when given in the options. Otherwise the two are timed
+/// against each other once, the first time a large image is decoded or the capabilities are reported, which takes a
+/// few milliseconds
+pub(crate) fn lut_is_faster() -> bool {
+  *LUT_IS_FASTER.get_or_init(|| {
+    requested_path().is_none() && {
+      let (lut, simd) = benchmark();
+      lut < simd
+    }
+  })
+}
+
+/// Time decoding a block of varied samples with the tables and with the SIMD float kernels, taking the fastest of a few
+/// runs of each after one to warm up
+fn benchmark() -> (Duration, Duration) {
+  const WIDTH: usize = 1920;
+  const ROWS: usize = 16;
+  const RUNS: usize = 5;
+
+  // The tables of the most common constants, which are kept for decoding
+  let constants =
+    YuvConstantsSimd::shared(Colorspace::Bt709, VideoRange::Limited, VideoRange::Limited);
+  let mut state = 1u32;
+  let input: Vec<u8> = (0..WIDTH * ROWS * 4)
+    .map(|_| {
+      state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+      (state >> 24) as u8
+    })
+    .collect();
+  let mut output = vec![0; input.len()];
+
+  let time = |decode_row: &dyn Fn(&mut RLEDecoder, &mut [u8]) -> bool, output: &mut [u8]| {
+    (0..=RUNS)
+      .map(|_| {
+        let start = Instant::now();
+        let mut decoder = RLEDecoder::new(&input);
+        for row in output.chunks_exact_mut(WIDTH * 4) {
+          decode_row(&mut decoder, row);
+        }
+        start.elapsed()
+      })
+      .skip(1)
+      .min()
+      .unwrap()
+  };
+
+  let path = active_path();
+  let lut = time(
+    &|decoder, row| decode_rgba_row_lut(constants, decoder, None, 0, row),
+    &mut output,
+  );
+  let simd = time(
+    &|decoder, row| decode_rgba_row(constants, path, decoder, row),
+    &mut output,
+  );
+  (lut, simd)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::options::VideoRange;
+  use crate::yuva422_to_rgba_simd::yuva422_to_rgb_simd;
+
+  #[test]
+  fn test_matches_float() {
+    // Every code appears in each position, with a spread of the other codes alongside it
+    let input: Vec<u8> = (0..1024u32 * 64)
+      .flat_map(|i| {
+        let (y, cb, cr, a) = (i % 1024, i * 7 % 1024, i * 13 % 1024, i * 3 % 1024);
+        [
+          a << 20 | cb << 10 | y,
+          (1023 - a) << 20 | cr << 10 | (1023 - y),
+        ]
+      })
+      .flat_map(u32::to_be_bytes)
+      .collect();
+
+    for range in [VideoRange::Limited, VideoRange::Full] {
+      let constants = YuvConstantsSimd::create_with_range(0.2126, 0.0722, range, range);
+      for dither in [None, Some(Dither::BlueNoise)] {
+        let mut expected = vec![0; input.len()];
+        let mut output = vec![0; input.len()];
+        let mut lut_decoder = RLEDecoder::new(&input);
+        let mut decoder = RLEDecoder::new(&input);
+        for (y, (expected_row, row)) in expected
+          .chunks_exact_mut(256)
+          .zip(output.chunks_exact_mut(256))
+          .enumerate()
+        {
+          assert!(decode_rgba_row_lut(
+            &constants,
+            &mut lut_decoder,
+            dither,
+            y,
+            row
+          ));
+          for (i, batch) in expected_row.chunks_exact_mut(32).enumerate() {
+            let samples: Vec<&[u8]> = (0..4).map(|_| decoder.read_sample().unwrap()).collect();
+            let dither = dither_offsets(dither, i * 8, y);
+            yuva422_to_rgb_simd(
+              &constants,
+              samples[0],
+              samples[1],
+              samples[2],
+              samples[3],
+              dither.as_ref(),
+              batch,
+            );
+          }
+        }
+        assert_eq!(output, expected, "{range:?} {dither:?}");
+      }
+    }
+  }
+
+  #[test]
+  fn test_short_input() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let mut output = [0; 64];
+    assert!(!decode_rgba_row_lut(
+      &constants,
+      &mut RLEDecoder::new(&[0; 48]),
+      None,
+      0,
+      &mut output
+    ));
+    assert!(!prefer_lut(LUT_MIN_PIXELS - 1));
+  }
+
+  #[test]
+  fn test_tables_are_shared() {
+    let shared =
+      || YuvConstantsSimd::shared(Colorspace::Bt601, VideoRange::Full, VideoRange::Limited);
+    assert!(std::ptr::eq(shared(), shared()));
+    assert!(std::ptr::eq(shared().decode_lut(), shared().decode_lut()));
+
+    let other = YuvConstantsSimd::shared(Colorspace::Bt601, VideoRange::Full, VideoRange::Full);
+    assert!(!std::ptr::eq(shared(), other));
+    assert_eq!(other.alpha_offset[0], 0.0);
+    assert_eq!(shared().alpha_offset[0], 64.0);
+  }
+}
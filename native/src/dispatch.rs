@@ -0,0 +1,386 @@
+use crate::decode_lut::lut_is_faster;
+use crate::rgba_to_yuva422_simd::{rgb_to_yuva422_lanes, rgb_to_yuva422_simd};
+use crate::rle::RLEDecoder;
+use crate::yuv_constants::YuvConstantsSimd;
+use crate::yuva422_to_rgba_simd::{yuva422_to_rgb_lanes, yuva422_to_rgb_simd};
+use std::simd::{LaneCount, SupportedLaneCount};
+use std::sync::OnceLock;
+
+/// The environment variable which can force a narrower path than the CPU supports, such as for comparing them
+const SIMD_PATH_VARIABLE: &str = "ATEM_IMAGE_TOOLS_SIMD";
+
+/// The set of kernels used for converting 8-bit RGBA
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum SimdPath {
+  /// 1 pixel pair at a time, without SIMD, as a reference for the other paths
+  #[napi(value = "scalar")]
+  Scalar,
+  /// 4 pixel pairs at a time, with portable SIMD that works on any CPU
+  #[napi(value = "portable")]
+  Portable,
+  /// 8 pixel pairs at a time, using AVX2
+  #[napi(value = "avx2")]
+  Avx2,
+  /// 16 pixel pairs at a time, using AVX-512
+  #[napi(value = "avx512")]
+  Avx512,
+}
+impl SimdPath {
+  /// The number of pixel pairs converted by each vector operation
+  pub fn lanes(self) -> u32 {
+    match self {
+      SimdPath::Scalar => 1,
+      SimdPath::Portable => 4,
+      SimdPath::Avx2 => 8,
+      SimdPath::Avx512 => 16,
+    }
+  }
+
+  fn parse(name: &str) -> Option<SimdPath> {
+    match name {
+      "scalar" => Some(SimdPath::Scalar),
+      "portable" => Some(SimdPath::Portable),
+      "avx2" => Some(SimdPath::Avx2),
+      "avx512" => Some(SimdPath::Avx512),
+      _ => None,
+    }
+  }
+
+  /// The widest path the CPU supports
+  fn detect() -> SimdPath {
+    #[cfg(target_arch = "x86_64")]
+    {
+      if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512bw") {
+        return SimdPath::Avx512;
+      }
+      if is_x86_feature_detected!("avx2") {
+        return SimdPath::Avx2;
+      }
+    }
+
+    SimdPath::Portable
+  }
+
+  /// Whether the CPU supports this path
+  pub fn is_available(self) -> bool {
+    self.lanes() <= SimdPath::detect().lanes()
+  }
+
+  /// The paths the CPU supports, from narrowest to widest
+  pub fn available() -> Vec<SimdPath> {
+    [
+      SimdPath::Scalar,
+      SimdPath::Portable,
+      SimdPath::Avx2,
+      SimdPath::Avx512,
+    ]
+    .into_iter()
+    .filter(|path| path.is_available())
+    .collect()
+  }
+}
+
+static REQUESTED_PATH: OnceLock<Option<SimdPath>> = OnceLock::new();
+
+/// The path forced by the environment, read once
+pub(crate) fn requested_path() -> Option<SimdPath> {
+  *REQUESTED_PATH.get_or_init(|| {
+    // A path wider than the CPU supports can't be used
+    std::env::var(SIMD_PATH_VARIABLE)
+      .ok()
+      .and_then(|name| SimdPath::parse(&name))
+      .filter(|path| path.is_available())
+  })
+}
+
+/// The path used for conversions which don't request one, chosen once from the CPU features and the environment
+pub(crate) fn active_path() -> SimdPath {
+  requested_path().unwrap_or_else(SimdPath::detect)
+}
+
+/// The SIMD support of the CPU, and the kernels in use
+#[napi(object)]
+pub struct Capabilities {
+  /// The kernels used for converting 8-bit RGBA
+  #[napi(ts_type = "'scalar' | 'portable' | 'avx2' | 'avx512'")]
+  pub simd_path: SimdPath,
+  /// The number of pixel pairs converted by each vector operation
+  pub lanes: u32,
+  /// The CPU architecture the library was built for
+  pub arch: String,
+  /// The relevant CPU features detected at runtime
+  pub features: Vec<String>,
+  /// Whether large `rgba` images are decoded with lookup tables, because they were faster than the SIMD kernels on
+  /// this CPU. The two are timed the first time either is needed, unless a path is forced by the environment
+  pub lut_decode: bool,
+}
+
+pub(crate) fn capabilities() -> Capabilities {
+  let simd_path = active_path();
+
+  #[allow(unused_mut)]
+  let mut features = Vec::new();
+  #[cfg(target_arch = "x86_64")]
+  {
+    macro_rules! detect {
+      ($($feature:tt),*) => {
+        $(if is_x86_feature_detected!($feature) {
+          features.push($feature.to_string());
+        })*
+      };
+    }
+    detect!("sse4.1", "avx", "avx2", "fma", "avx512f", "avx512bw");
+  }
+
+  Capabilities {
+    simd_path,
+    lanes: simd_path.lanes(),
+    arch: std::env::consts::ARCH.to_string(),
+    features,
+    lut_decode: lut_is_faster(),
+  }
+}
+
+/// Encode a row of 8-bit RGBA pixels, which must be a multiple of 8 pixels long
+///
+/// Every path gives identical output
+pub(crate) fn encode_rgba_row(
+  constants: &YuvConstantsSimd,
+  path: SimdPath,
+  input: &[u8],
+  output: &mut [u8],
+) {
+  match path {
+    // SAFETY: these paths are only available when the CPU supports them
+    #[cfg(target_arch = "x86_64")]
+    SimdPath::Avx512 => unsafe { encode_rgba_row_avx512(constants, input, output) },
+    #[cfg(target_arch = "x86_64")]
+    SimdPath::Avx2 => unsafe { encode_rgba_row_avx2(constants, input, output) },
+    SimdPath::Scalar => encode_rgba_row_lanes::<1>(constants, input, output),
+    _ => encode_rgba_row_portable(constants, input, output),
+  }
+}
+
+/// Decode a row of 8-bit RGBA pixels without dithering, returning false if the input ran out of data
+///
+/// Every path gives identical output
+pub(crate) fn decode_rgba_row(
+  constants: &YuvConstantsSimd,
+  path: SimdPath,
+  decoder: &mut RLEDecoder,
+  output: &mut [u8],
+) -> bool {
+  match path {
+    // SAFETY: these paths are only available when the CPU supports them
+    #[cfg(target_arch = "x86_64")]
+    SimdPath::Avx512 => unsafe { decode_rgba_row_avx512(constants, decoder, output) },
+    #[cfg(target_arch = "x86_64")]
+    SimdPath::Avx2 => unsafe { decode_rgba_row_avx2(constants, decoder, output) },
+    SimdPath::Scalar => decode_rgba_row_lanes::<1>(constants, decoder, output),
+    _ => decode_rgba_row_portable(constants, decoder, output),
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn encode_rgba_row_avx2(constants: &YuvConstantsSimd, input: &[u8], output: &mut [u8]) {
+  encode_rgba_row_lanes::<8>(constants, input, output)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn encode_rgba_row_avx512(constants: &YuvConstantsSimd, input: &[u8], output: &mut [u8]) {
+  encode_rgba_row_lanes::<16>(constants, input, output)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn decode_rgba_row_avx2(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  output: &mut [u8],
+) -> bool {
+  decode_rgba_row_lanes::<8>(constants, decoder, output)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx512f,avx512bw")]
+unsafe fn decode_rgba_row_avx512(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  output: &mut [u8],
+) -> bool {
+  decode_rgba_row_lanes::<16>(constants, decoder, output)
+}
+
+/// Encode `2 * N` pixels at a time, finishing any remainder with the portable path
+#[inline(always)]
+fn encode_rgba_row_lanes<const N: usize>(
+  constants: &YuvConstantsSimd,
+  input: &[u8],
+  output: &mut [u8],
+) where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let mut input_batches = input.chunks_exact(N * 8);
+  let mut output_batches = output.chunks_exact_mut(N * 8);
+  for (input_batch, output_batch) in (&mut input_batches).zip(&mut output_batches) {
+    rgb_to_yuva422_lanes::<N>(constants, input_batch, output_batch);
+  }
+
+  encode_rgba_row_portable(
+    constants,
+    input_batches.remainder(),
+    output_batches.into_remainder(),
+  );
+}
+
+fn encode_rgba_row_portable(constants: &YuvConstantsSimd, input: &[u8], output: &mut [u8]) {
+  for (input_batch, output_batch) in input.chunks_exact(32).zip(output.chunks_exact_mut(32)) {
+    rgb_to_yuva422_simd(constants, input_batch, output_batch);
+  }
+}
+
+/// Decode `2 * N` pixels at a time, finishing any remainder with the portable path
+#[inline(always)]
+fn decode_rgba_row_lanes<const N: usize>(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  output: &mut [u8],
+) -> bool
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let mut samples = [0; 128];
+  let mut output_batches = output.chunks_exact_mut(N * 8);
+  for output_batch in &mut output_batches {
+    for sample in samples[..N * 8].chunks_exact_mut(8) {
+      let Some(read) = decoder.read_sample() else {
+        return false; // Not enough data
+      };
+      sample.copy_from_slice(read);
+    }
+
+    yuva422_to_rgb_lanes::<N>(constants, &samples, output_batch);
+  }
+
+  decode_rgba_row_portable(constants, decoder, output_batches.into_remainder())
+}
+
+fn decode_rgba_row_portable(
+  constants: &YuvConstantsSimd,
+  decoder: &mut RLEDecoder,
+  output: &mut [u8],
+) -> bool {
+  for output_batch in output.chunks_exact_mut(32) {
+    let (Some(sample1), Some(sample2), Some(sample3), Some(sample4)) = (
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+      decoder.read_sample(),
+    ) else {
+      return false; // Not enough data
+    };
+
+    yuva422_to_rgb_simd(
+      constants,
+      sample1,
+      sample2,
+      sample3,
+      sample4,
+      None,
+      output_batch,
+    );
+  }
+
+  true
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::options::VideoRange;
+
+  /// Deterministic noise, so that every channel value and combination of codes is likely to be covered
+  fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+      .map(|_| {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        (state >> 24) as u8
+      })
+      .collect()
+  }
+
+  fn all_constants() -> Vec<YuvConstantsSimd> {
+    let ranges = [VideoRange::Limited, VideoRange::Full];
+    ranges
+      .into_iter()
+      .flat_map(|range| {
+        [(0.299, 0.114), (0.2126, 0.0722)]
+          .map(|(kr, kb)| YuvConstantsSimd::create_with_range(kr, kb, range, range))
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_paths_encode_identically() {
+    for constants in all_constants() {
+      // Widths which leave a remainder for the portable path on every wider path
+      for width in [8, 16, 24, 40, 72] {
+        let input = noise(width * 4, width as u32);
+        let mut expected = vec![0; width * 4];
+        encode_rgba_row(&constants, SimdPath::Portable, &input, &mut expected);
+
+        for path in SimdPath::available() {
+          let mut output = vec![0; width * 4];
+          encode_rgba_row(&constants, path, &input, &mut output);
+          assert_eq!(output, expected, "{path:?} {width}");
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_paths_decode_identically() {
+    for constants in all_constants() {
+      for width in [8, 16, 24, 40, 72] {
+        let input = noise(width * 4, width as u32);
+        let mut expected = vec![0; width * 4];
+        assert!(decode_rgba_row(
+          &constants,
+          SimdPath::Portable,
+          &mut RLEDecoder::new(&input),
+          &mut expected,
+        ));
+
+        for path in SimdPath::available() {
+          let mut output = vec![0; width * 4];
+          assert!(decode_rgba_row(
+            &constants,
+            path,
+            &mut RLEDecoder::new(&input),
+            &mut output,
+          ));
+          assert_eq!(output, expected, "{path:?} {width}");
+
+          // Running out of data part way through a row is reported
+          let short = &input[..input.len() - 8];
+          assert!(!decode_rgba_row(
+            &constants,
+            path,
+            &mut RLEDecoder::new(short),
+            &mut output,
+          ));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_active_path_is_available() {
+    assert!(SimdPath::available().contains(&active_path()));
+    assert_eq!(capabilities().lanes, active_path().lanes());
+  }
+}
@@ -0,0 +1,222 @@
+use crate::options::Dither;
+use std::simd::Simd;
+use std::sync::OnceLock;
+
+/// The offsets added to the first and second pixels of 4 pairs before rounding
+pub type DitherOffsets = [Simd<f32, 4>; 2];
+
+const BAYER_SIZE: usize = 8;
+const BLUE_NOISE_SIZE: usize = 32;
+const BLUE_NOISE_LEN: usize = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+
+/// The offsets for the 8 pixels of a batch starting at `x` of row `y`, or `None` when not dithering
+///
+/// The offsets only depend on the position of the pixel, so the output is deterministic
+#[inline(always)]
+pub fn dither_offsets(dither: Option<Dither>, x: usize, y: usize) -> Option<DitherOffsets> {
+  let offset = match dither? {
+    Dither::Bayer => bayer_offset,
+    Dither::BlueNoise => blue_noise_offset,
+  };
+
+  Some([
+    Simd::from_array([0, 2, 4, 6].map(|i| offset(x + i, y))),
+    Simd::from_array([1, 3, 5, 7].map(|i| offset(x + i, y))),
+  ])
+}
+
+/// Convert a rank within a threshold matrix of `count` entries to an offset in the range -0.5 to 0.5
+#[inline(always)]
+fn rank_to_offset(rank: usize, count: usize) -> f32 {
+  (rank as f32 + 0.5) / count as f32 - 0.5
+}
+
+fn bayer_offset(x: usize, y: usize) -> f32 {
+  let (x, y) = (x % BAYER_SIZE, y % BAYER_SIZE);
+
+  // Interleave the bits of `x ^ y` and `y`, from least to most significant
+  let mut rank = 0;
+  for bit in 0..3 {
+    let x_bit = (x >> bit) & 1;
+    let y_bit = (y >> bit) & 1;
+    rank |= ((x_bit ^ y_bit) << 1 | y_bit) << (2 * (2 - bit));
+  }
+
+  rank_to_offset(rank, BAYER_SIZE * BAYER_SIZE)
+}
+
+fn blue_noise_offset(x: usize, y: usize) -> f32 {
+  static RANKS: OnceLock<Vec<u16>> = OnceLock::new();
+  let ranks = RANKS.get_or_init(generate_blue_noise);
+
+  let index = (y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE;
+  rank_to_offset(ranks[index] as usize, BLUE_NOISE_LEN)
+}
+
+/// A binary pattern on a torus, tracking how crowded each position is by the set positions around it
+struct Pattern<'a> {
+  kernel: &'a [f32],
+  set: Vec<bool>,
+  energy: Vec<f32>,
+}
+impl Pattern<'_> {
+  fn toggle(&mut self, index: usize) {
+    let on = !self.set[index];
+    self.set[index] = on;
+
+    let (x, y) = (index % BLUE_NOISE_SIZE, index / BLUE_NOISE_SIZE);
+    for (other, energy) in self.energy.iter_mut().enumerate() {
+      let dx = (other % BLUE_NOISE_SIZE + BLUE_NOISE_SIZE - x) % BLUE_NOISE_SIZE;
+      let dy = (other / BLUE_NOISE_SIZE + BLUE_NOISE_SIZE - y) % BLUE_NOISE_SIZE;
+      let weight = self.kernel[dy * BLUE_NOISE_SIZE + dx];
+      if on {
+        *energy += weight;
+      } else {
+        *energy -= weight;
+      }
+    }
+  }
+
+  /// The set position with the most crowded surroundings
+  fn tightest_cluster(&self) -> usize {
+    (0..BLUE_NOISE_LEN)
+      .filter(|&i| self.set[i])
+      .fold(None, |best: Option<usize>, i| match best {
+        Some(best) if self.energy[best] >= self.energy[i] => Some(best),
+        _ => Some(i),
+      })
+      .unwrap()
+  }
+
+  /// The unset position with the emptiest surroundings
+  fn largest_void(&self) -> usize {
+    (0..BLUE_NOISE_LEN)
+      .filter(|&i| !self.set[i])
+      .fold(None, |best: Option<usize>, i| match best {
+        Some(best) if self.energy[best] <= self.energy[i] => Some(best),
+        _ => Some(i),
+      })
+      .unwrap()
+  }
+}
+
+/// Generate a blue noise threshold matrix with the void-and-cluster method
+///
+/// Only exact floating point operations are used, so every platform generates the same matrix
+fn generate_blue_noise() -> Vec<u16> {
+  // A gaussian with a sigma of 1.5, as powers of exp(-1 / (2 * 1.5^2))
+  const GAUSSIAN_BASE: f32 = 0.800_737_4;
+  let kernel: Vec<f32> = (0..BLUE_NOISE_LEN)
+    .map(|i| {
+      let dx = (i % BLUE_NOISE_SIZE).min(BLUE_NOISE_SIZE - i % BLUE_NOISE_SIZE);
+      let dy = (i / BLUE_NOISE_SIZE).min(BLUE_NOISE_SIZE - i / BLUE_NOISE_SIZE);
+      (0..dx * dx + dy * dy).fold(1.0, |weight, _| weight * GAUSSIAN_BASE)
+    })
+    .collect();
+
+  let mut initial = Pattern {
+    kernel: &kernel,
+    set: vec![false; BLUE_NOISE_LEN],
+    energy: vec![0.0; BLUE_NOISE_LEN],
+  };
+
+  // Start from a sparse pseudo-random pattern, from a fixed seed
+  let initial_count = BLUE_NOISE_LEN / 10;
+  let mut state: u32 = 0x9e37_79b9;
+  let mut placed = 0;
+  while placed < initial_count {
+    state ^= state << 13;
+    state ^= state >> 17;
+    state ^= state << 5;
+    let index = state as usize % BLUE_NOISE_LEN;
+    if !initial.set[index] {
+      initial.toggle(index);
+      placed += 1;
+    }
+  }
+
+  // Spread the points out evenly, by moving the most crowded point to the emptiest space until it stays put
+  for _ in 0..BLUE_NOISE_LEN {
+    let cluster = initial.tightest_cluster();
+    initial.toggle(cluster);
+    let void = initial.largest_void();
+    initial.toggle(void);
+    if void == cluster {
+      break;
+    }
+  }
+
+  let mut ranks = vec![0; BLUE_NOISE_LEN];
+
+  // Rank the initial points by removing the most crowded first
+  let mut pattern = Pattern {
+    kernel: &kernel,
+    set: initial.set.clone(),
+    energy: initial.energy.clone(),
+  };
+  for rank in (0..initial_count).rev() {
+    let cluster = pattern.tightest_cluster();
+    pattern.toggle(cluster);
+    ranks[cluster] = rank as u16;
+  }
+
+  // Rank the rest by filling the emptiest space first
+  for rank in initial_count..BLUE_NOISE_LEN {
+    let void = initial.largest_void();
+    initial.toggle(void);
+    ranks[void] = rank as u16;
+  }
+
+  ranks
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn check_thresholds(offset: fn(usize, usize) -> f32, size: usize) {
+    let mut ranks: Vec<usize> = (0..size * size)
+      .map(|i| ((offset(i % size, i / size) + 0.5) * (size * size) as f32) as usize)
+      .collect();
+
+    // The matrix tiles the plane
+    assert_eq!(offset(3, 5), offset(3 + size, 5 + size));
+
+    // Every threshold is used once
+    ranks.sort();
+    assert_eq!(ranks, (0..size * size).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_bayer() {
+    check_thresholds(bayer_offset, BAYER_SIZE);
+
+    let first_row: Vec<f32> = (0..8).map(|x| bayer_offset(x, 0)).collect();
+    let expected: Vec<f32> = [0, 32, 8, 40, 2, 34, 10, 42]
+      .iter()
+      .map(|&rank| rank_to_offset(rank, 64))
+      .collect();
+    assert_eq!(first_row, expected);
+  }
+
+  #[test]
+  fn test_blue_noise() {
+    check_thresholds(blue_noise_offset, BLUE_NOISE_SIZE);
+
+    // Neighbouring thresholds should be far apart, unlike white noise
+    let mut total_difference = 0.0;
+    for y in 0..BLUE_NOISE_SIZE {
+      for x in 0..BLUE_NOISE_SIZE {
+        total_difference += (blue_noise_offset(x, y) - blue_noise_offset(x + 1, y)).abs();
+      }
+    }
+    let mean_difference = total_difference / BLUE_NOISE_LEN as f32;
+    assert!(mean_difference > 0.4, "{mean_difference}");
+  }
+
+  #[test]
+  fn test_no_dither() {
+    assert!(dither_offsets(None, 0, 0).is_none());
+    assert!(dither_offsets(Some(Dither::Bayer), 0, 0).is_some());
+  }
+}
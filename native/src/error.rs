@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Why a conversion failed, which the bindings turn into a JavaScript `Error` with the same message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+  reason: String,
+}
+impl Error {
+  pub fn from_reason<T: Into<String>>(reason: T) -> Error {
+    Error {
+      reason: reason.into(),
+    }
+  }
+
+  /// The message describing the failure
+  pub fn reason(&self) -> &str {
+    &self.reason
+  }
+}
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.reason)
+  }
+}
+impl std::error::Error for Error {}
+impl From<Error> for napi::Error {
+  fn from(error: Error) -> napi::Error {
+    napi::Error::from_reason(error.reason)
+  }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,189 @@
+//! The checks run by the fuzz targets in `fuzz/`, which are also run over the checked in crashes by the unit tests
+//!
+//! Each one takes arbitrary bytes, and panics if the code under test misbehaves
+
+use crate::api::{
+  decode_yuva_422_to_rgba, encode_rle, Arithmetic, DecodeOptions, Dither, OutputFormat, SimdPath,
+  TransferFunction, VideoRange,
+};
+use crate::canonical::{canonical_hash, stills_equal as samples_equal};
+use crate::rle::RLEDecoder;
+
+/// The most samples read from one input, as a RLE header can claim up to 2^64 repeats
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// Read samples from untrusted data with the RLE decoder
+pub fn rle_decode(data: &[u8]) {
+  let mut decoder = RLEDecoder::new(data);
+  for _ in 0..MAX_SAMPLES {
+    let Some(sample) = decoder.read_sample() else {
+      break;
+    };
+    assert_eq!(sample.len(), 8);
+  }
+}
+
+/// Decode untrusted data as a small image, with options picked by the first bytes
+pub fn decode(data: &[u8]) {
+  let [width, height, format, flags, data @ ..] = data else {
+    return;
+  };
+
+  // Mostly valid widths, which are a multiple of 8
+  let width = (*width as u32 % 17) * 8 + u32::from(*width == 255);
+  let height = *height as u32 % 32;
+  let format = [
+    OutputFormat::Rgba,
+    OutputFormat::Rgba16le,
+    OutputFormat::Rgba16be,
+    OutputFormat::Rgbaf32,
+    OutputFormat::R210,
+    OutputFormat::A2r10g10b10,
+  ][*format as usize % 6];
+  let range = |bit: u8| {
+    if flags & bit == 0 {
+      VideoRange::Limited
+    } else {
+      VideoRange::Full
+    }
+  };
+  let options = DecodeOptions {
+    format: Some(format),
+    range: Some(range(1)),
+    alpha_range: Some(range(2)),
+    dither: (flags & 4 != 0).then_some(Dither::Bayer),
+    transfer: Some(if flags & 8 == 0 {
+      TransferFunction::Gamma
+    } else {
+      TransferFunction::Linear
+    }),
+    arithmetic: Some(if flags & 16 == 0 {
+      Arithmetic::Float
+    } else {
+      Arithmetic::Fixed
+    }),
+    simd_path: Some(
+      [
+        SimdPath::Scalar,
+        SimdPath::Portable,
+        SimdPath::Avx2,
+        SimdPath::Avx512,
+      ][(flags >> 5) as usize % 4],
+    ),
+    threads: Some(if flags & 128 == 0 { 1 } else { 3 }),
+    stride: None,
+  };
+
+  let mut output = vec![0; width as usize * height as usize * format.bytes_per_pixel()];
+  // Invalid input must be rejected with an error rather than a panic
+  let _ = decode_yuva_422_to_rgba(width, height, data, &mut output, &options);
+}
+
+/// Compress samples built from untrusted data, and check that expanding them gives back the same samples
+///
+/// Every 9 bytes are a sample followed by how many times to repeat it, so that there are runs to compress
+pub fn rle_round_trip(data: &[u8]) {
+  let mut flat = Vec::new();
+  for chunk in data.chunks_exact(9) {
+    flat.extend(chunk[..8].repeat(chunk[8] as usize % 8 + 1));
+  }
+
+  let compressed = encode_rle(&flat);
+  let mut decoder = RLEDecoder::new(&compressed);
+  let mut expanded = Vec::with_capacity(flat.len());
+  while let Some(sample) = decoder.read_sample() {
+    expanded.extend_from_slice(sample);
+  }
+  assert!(expanded == flat, "RLE round trip changed the samples");
+}
+
+/// Hash untrusted data, and check that it gives the MD5 of the expanded samples when there are few enough to expand
+pub fn hash(data: &[u8]) {
+  let Ok(digest) = canonical_hash(data) else {
+    return;
+  };
+
+  let mut decoder = RLEDecoder::new(data);
+  let mut expanded = Vec::new();
+  for _ in 0..MAX_SAMPLES {
+    let Some(sample) = decoder.read_sample() else {
+      assert_eq!(
+        digest,
+        md5::compute(&expanded).0,
+        "Hash is not the MD5 of the samples"
+      );
+      return;
+    };
+    expanded.extend_from_slice(sample);
+  }
+}
+
+/// Compare two images split from untrusted data at a point picked by the first byte, and check that they are only
+/// equal when their hashes are
+pub fn stills_equal(data: &[u8]) {
+  let [split, data @ ..] = data else {
+    return;
+  };
+  let (a, b) = data.split_at(*split as usize % (data.len() + 1));
+
+  let equal = samples_equal(a, b).ok();
+  assert_eq!(
+    equal,
+    samples_equal(b, a).ok(),
+    "Comparison is not symmetric"
+  );
+  if let (Some(equal), Ok(hash_a), Ok(hash_b)) = (equal, canonical_hash(a), canonical_hash(b)) {
+    assert_eq!(
+      equal,
+      hash_a == hash_b,
+      "Comparison does not match the hashes"
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::Path;
+
+  /// Run a check over every input saved for a fuzz target in `fuzz/regressions`
+  fn run_regressions(target: &str, check: fn(&[u8])) {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("fuzz/regressions")
+      .join(target);
+    let entries = std::fs::read_dir(&directory).expect("Missing regressions directory");
+
+    let mut count = 0;
+    for entry in entries {
+      let path = entry.unwrap().path();
+      check(&std::fs::read(&path).unwrap());
+      count += 1;
+    }
+    assert!(count > 0, "No regressions in {directory:?}");
+  }
+
+  #[test]
+  fn test_rle_decode_regressions() {
+    run_regressions("rle_decode", rle_decode);
+  }
+
+  #[test]
+  fn test_decode_regressions() {
+    run_regressions("decode", decode);
+  }
+
+  #[test]
+  fn test_rle_round_trip_regressions() {
+    run_regressions("rle_round_trip", rle_round_trip);
+  }
+
+  #[test]
+  fn test_hash_regressions() {
+    run_regressions("hash", hash);
+  }
+
+  #[test]
+  fn test_stills_equal_regressions() {
+    run_regressions("stills_equal", stills_equal);
+  }
+}
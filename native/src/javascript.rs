@@ -0,0 +1,231 @@
+//! A reproduction of the RGBA to YUVA422 converter of atem-connection, which this library is a replacement for
+//!
+//! The JavaScript converter computes luma and chroma in double precision scaled to 16-bit, rounds them with
+//! `Math.round` and then truncates them to 10-bit, while alpha is truncated from its exact value. The other arithmetics
+//! truncate the 10-bit value directly in single precision, so around 1 in 200 codes are 1 different and the hashes of
+//! the stills differ. These kernels repeat each operation of the JavaScript in the same order and precision, so their
+//! output is identical to it.
+//!
+//! atem-connection only supports limited range, so there are no constants for full range video. Full range alpha, which
+//! it doesn't support either, is rounded to the nearest code as the other arithmetics round it.
+
+use crate::options::VideoRange;
+use std::simd::{cmp::SimdPartialOrd, num::SimdFloat, Simd, StdFloat};
+
+type F64s = Simd<f64, 4>;
+
+/// The coefficients of the JavaScript converter, with the same values it computes
+pub struct JavascriptConstants {
+  y_r: f64,
+  y_g: f64,
+  y_b: f64,
+  kr_o_kb_i: f64,
+  kg_o_kb_i: f64,
+  kb_o_kr_i: f64,
+  kg_o_kr_i: f64,
+  full_alpha: bool,
+}
+impl JavascriptConstants {
+  pub fn new(kr: f32, kb: f32, alpha_range: VideoRange) -> JavascriptConstants {
+    // The coefficients are short decimals, which the shortest representation of their single precision value gives
+    // back exactly, and JavaScript holds them as the nearest double to that
+    let double = |value: f32| value.to_string().parse::<f64>().unwrap();
+    let (kr, kb) = (double(kr), double(kb));
+    let kg = 1.0 - kr - kb;
+    let (kr_i, kb_i) = (1.0 - kr, 1.0 - kb);
+
+    JavascriptConstants {
+      y_r: kr * 219.0,
+      y_g: kg * 219.0,
+      y_b: kb * 219.0,
+      kr_o_kb_i: (kr / kb_i) * 112.0,
+      kg_o_kb_i: (kg / kb_i) * 112.0,
+      kb_o_kr_i: (kb / kr_i) * 112.0,
+      kg_o_kr_i: (kg / kr_i) * 112.0,
+      full_alpha: alpha_range == VideoRange::Full,
+    }
+  }
+}
+
+/// `Math.round`, which rounds halves up. The subtraction is exact for the positive values it is used on
+#[inline(always)]
+fn round(value: F64s) -> F64s {
+  let floor = value.floor();
+  (value - floor)
+    .simd_ge(Simd::splat(0.5))
+    .select(floor + Simd::splat(1.0), floor)
+}
+
+/// Convert 8 pixels of 8-bit RGBA to 4 YUVA422 samples, exactly as atem-connection does
+#[inline(always)]
+pub fn rgb_to_yuva422_javascript(constants: &JavascriptConstants, input: &[u8], target: &mut [u8]) {
+  let channel = |offset: usize| F64s::from_array([0, 8, 16, 24].map(|i| input[i + offset] as f64));
+  let (r1, g1, b1, a1) = (channel(0), channel(1), channel(2), channel(3));
+  let (r2, g2, b2, a2) = (channel(4), channel(5), channel(6), channel(7));
+
+  let c = constants;
+  let splat = F64s::splat;
+  let luma = |r: F64s, g: F64s, b: F64s| {
+    splat(4096.0) + splat(c.y_r) * r + splat(c.y_g) * g + splat(c.y_b) * b
+  };
+  let y16a = luma(r1, g1, b1);
+  let y16b = luma(r2, g2, b2);
+  // Chroma comes from the first pixel of each pair
+  let cb16 =
+    splat(32768.0) + (-splat(c.kr_o_kb_i) * r1 - splat(c.kg_o_kb_i) * g1 + splat(112.0) * b1);
+  let cr16 =
+    splat(32768.0) + (splat(112.0) * r1 - splat(c.kg_o_kr_i) * g1 - splat(c.kb_o_kr_i) * b1);
+
+  // `a << 20` truncates alpha to an integer, while the others are rounded at 16-bit then shifted down
+  let alpha = |a: F64s| {
+    if c.full_alpha {
+      round((a * splat(1023.0)) / splat(255.0)).cast::<i64>()
+    } else {
+      ((a * splat(4.0) * splat(219.0)) / splat(255.0) + splat(64.0)).cast::<i64>()
+    }
+  };
+  let code = |value: F64s| round(value).cast::<i64>() >> Simd::splat(6);
+  let word1 = (alpha(a1) << Simd::splat(20)) + (code(cb16) << Simd::splat(10)) + code(y16a);
+  let word2 = (alpha(a2) << Simd::splat(20)) + (code(cr16) << Simd::splat(10)) + code(y16b);
+
+  for i in 0..4 {
+    let offset = i * 8;
+    target[offset..offset + 4].copy_from_slice(&(word1[i] as u32).to_be_bytes());
+    target[offset + 4..offset + 8].copy_from_slice(&(word2[i] as u32).to_be_bytes());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::api::{encode_rgba_to_yuva_422, Arithmetic, EncodeOptions, InputFormat, VideoRange};
+  use proptest::prelude::*;
+
+  /// `convertRGBAToYUV422` of atem-connection, transcribed line by line
+  fn convert_rgba_to_yuv422(width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    // BT.709 or BT.601
+    let kr: f64 = if height >= 720 { 0.2126 } else { 0.299 };
+    let kb: f64 = if height >= 720 { 0.0722 } else { 0.114 };
+    let kg = 1.0 - kr - kb;
+
+    let kr_i = 1.0 - kr;
+    let kb_i = 1.0 - kb;
+
+    let y_range = 219.0;
+    let cb_cr_range = 224.0;
+    let half_cb_cr_range = cb_cr_range / 2.0;
+
+    let y_offset = (16 << 8) as f64;
+    let cb_cr_offset = (128 << 8) as f64;
+
+    let kr_o_kb_i = (kr / kb_i) * half_cb_cr_range;
+    let kg_o_kb_i = (kg / kb_i) * half_cb_cr_range;
+    let kb_o_kr_i = (kb / kr_i) * half_cb_cr_range;
+    let kg_o_kr_i = (kg / kr_i) * half_cb_cr_range;
+
+    // `Math.round` rounds halves up as V8 does, and `>>` and `<<` truncate to a 32-bit integer first
+    let math_round = |value: f64| {
+      let ceil = value.ceil();
+      if ceil - 0.5 > value {
+        ceil - 1.0
+      } else {
+        ceil
+      }
+    };
+    let gen_color = |raw_a: u8, uv16: f64, y16: f64| -> u32 {
+      let a = (((raw_a as i32) << 2) * 219) as f64 / 255.0 + (16 << 2) as f64;
+      let y = (math_round(y16) as i32) >> 6;
+      let uv = (math_round(uv16) as i32) >> 6;
+
+      (((a as i32) << 20) + (uv << 10) + y) as u32
+    };
+
+    let mut buffer = vec![0; (width * height * 4) as usize];
+    for i in (0..buffer.len()).step_by(8) {
+      let [r1, g1, b1, a1, r2, g2, b2, a2] = data[i..i + 8].try_into().unwrap();
+      let [r1, g1, b1, r2, g2, b2] = [r1, g1, b1, r2, g2, b2].map(f64::from);
+
+      let y16a = y_offset + kr * y_range * r1 + kg * y_range * g1 + kb * y_range * b1;
+      let cb16 = cb_cr_offset + (-kr_o_kb_i * r1 - kg_o_kb_i * g1 + half_cb_cr_range * b1);
+      let y16b = y_offset + kr * y_range * r2 + kg * y_range * g2 + kb * y_range * b2;
+      let cr16 = cb_cr_offset + (half_cb_cr_range * r1 - kg_o_kr_i * g1 - kb_o_kr_i * b1);
+
+      buffer[i..i + 4].copy_from_slice(&gen_color(a1, cb16, y16a).to_be_bytes());
+      buffer[i + 4..i + 8].copy_from_slice(&gen_color(a2, cr16, y16b).to_be_bytes());
+    }
+    buffer
+  }
+
+  fn encode(width: u32, height: u32, rgba: &[u8], arithmetic: Arithmetic) -> Vec<u8> {
+    let mut output = vec![0; (width * height * 4) as usize];
+    let options = EncodeOptions {
+      arithmetic: Some(arithmetic),
+      ..Default::default()
+    };
+    encode_rgba_to_yuva_422(width, height, rgba, &mut output, &options).unwrap();
+    output
+  }
+
+  /// Random images of 8 to 64 pixels wide, and either a few rows of BT.601 or 720 rows of BT.709
+  fn images() -> impl Strategy<Value = (u32, u32, Vec<u8>)> {
+    (1..=8u32, prop_oneof![1..=8u32, Just(720)]).prop_flat_map(|(width, height)| {
+      let width = width * 8;
+      prop::collection::vec(any::<u8>(), (width * height * 4) as usize)
+        .prop_map(move |image| (width, height, image))
+    })
+  }
+
+  #[test]
+  fn test_matches_atem_connection() {
+    // Encoded by the JavaScript converter, run in Node.js
+    let rgba = [
+      255, 255, 255, 255, 0, 0, 0, 0, 255, 0, 0, 128, 0, 255, 0, 1, 0, 0, 255, 254, 17, 99, 201,
+      77, 128, 128, 128, 128, 191, 0, 191, 64,
+    ];
+    let expected = [
+      0x3a, 0xc8, 0x03, 0xa8, 0x04, 0x08, 0x00, 0x40, 0x1f, 0x75, 0xa5, 0x44, 0x04, 0x3e, 0xfa,
+      0x40, 0x3a, 0x8e, 0xf8, 0xa3, 0x14, 0x86, 0xdd, 0x66, 0x1f, 0x78, 0x01, 0xf6, 0x11, 0xb8,
+      0x01, 0x4d,
+    ];
+    assert_eq!(convert_rgba_to_yuv422(8, 1, &rgba), expected);
+    assert_eq!(encode(8, 1, &rgba, Arithmetic::Javascript), expected);
+  }
+
+  #[test]
+  fn test_differs_from_float() {
+    // The float arithmetic truncates the exact value, rather than the value rounded at 16-bit
+    let rgba: Vec<u8> = (0..=255u8).flat_map(|v| [v, v, v, 255]).collect();
+    let javascript = encode(256, 1, &rgba, Arithmetic::Javascript);
+    assert_eq!(javascript, convert_rgba_to_yuv422(256, 1, &rgba));
+    assert_ne!(javascript, encode(256, 1, &rgba, Arithmetic::Float));
+  }
+
+  #[test]
+  fn test_unsupported_input_is_rejected() {
+    // atem-connection only encodes limited range 8-bit RGBA
+    let mut output = vec![0; 256 * 4];
+    for (range, format, bytes_per_pixel) in [
+      (VideoRange::Full, InputFormat::Rgba, 4),
+      (VideoRange::Limited, InputFormat::Rgba16le, 8),
+      (VideoRange::Limited, InputFormat::Rgbaf32, 16),
+    ] {
+      let options = EncodeOptions {
+        format: Some(format),
+        range: Some(range),
+        arithmetic: Some(Arithmetic::Javascript),
+        ..Default::default()
+      };
+      let input = vec![0; 256 * bytes_per_pixel];
+      assert!(encode_rgba_to_yuva_422(256, 1, &input, &mut output, &options).is_err());
+    }
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_random_images_match_atem_connection((width, height, rgba) in images()) {
+      let expected = convert_rgba_to_yuv422(width, height, &rgba);
+      prop_assert!(encode(width, height, &rgba, Arithmetic::Javascript) == expected);
+    }
+  }
+}
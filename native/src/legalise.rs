@@ -0,0 +1,315 @@
+use crate::error::{Error, Result};
+use crate::options::VideoRange;
+use napi::bindgen_prelude::Buffer;
+
+/// The result of legalising an image
+#[napi(object)]
+pub struct LegaliseReport {
+  /// The number of pixels which were modified
+  pub modified_pixels: u32,
+  /// One byte per pixel, set to 1 if the pixel was modified or 0 otherwise. Only present if it was requested
+  pub map: Option<Buffer>,
+}
+
+/// The EBU R103 limits of each RGB component, as a fraction of the nominal range
+const RGB_MIN: f32 = -0.05;
+const RGB_MAX: f32 = 1.05;
+/// The EBU R103 limits of luma, as a fraction of the nominal range
+const LUMA_MIN: f32 = -0.01;
+const LUMA_MAX: f32 = 1.03;
+
+/// Converts between 10-bit codes and the nominal range of a signal
+struct Levels {
+  /// The code of nominal black
+  black: f32,
+  /// The number of codes from nominal black to nominal white
+  luma_range: f32,
+  /// The number of codes between the nominal extremes of chroma
+  chroma_range: f32,
+}
+impl Levels {
+  fn new(range: VideoRange) -> Levels {
+    match range {
+      VideoRange::Limited => Levels {
+        black: 64.0,
+        luma_range: 876.0,
+        chroma_range: 896.0,
+      },
+      VideoRange::Full => Levels {
+        black: 0.0,
+        luma_range: 1023.0,
+        chroma_range: 1023.0,
+      },
+    }
+  }
+}
+
+/// Checks 10-bit YUV codes against the EBU R103 limits
+pub(crate) struct R103Limits {
+  levels: Levels,
+  kr: f32,
+  kb: f32,
+  kg: f32,
+  /// The smallest legal luma code
+  pub luma_min: u32,
+  /// The largest legal luma code
+  pub luma_max: u32,
+}
+impl R103Limits {
+  pub fn new(kr: f32, kb: f32, range: VideoRange) -> R103Limits {
+    let levels = Levels::new(range);
+    let luma_min = (levels.black + LUMA_MIN * levels.luma_range)
+      .ceil()
+      .max(0.0);
+    let luma_max = (levels.black + LUMA_MAX * levels.luma_range)
+      .floor()
+      .min(1023.0);
+
+    R103Limits {
+      levels,
+      kr,
+      kb,
+      kg: 1.0 - kr - kb,
+      luma_min: luma_min as u32,
+      luma_max: luma_max as u32,
+    }
+  }
+
+  /// The luma code as a fraction of the nominal range
+  fn luma(&self, y: u32) -> f32 {
+    (y as f32 - self.levels.black) / self.levels.luma_range
+  }
+
+  /// The change to each RGB component caused by the chroma, as a fraction of the nominal range
+  fn rgb_offsets(&self, cb: u32, cr: u32) -> [f32; 3] {
+    let pb = (cb as f32 - 512.0) / self.levels.chroma_range;
+    let pr = (cr as f32 - 512.0) / self.levels.chroma_range;
+    let r_offset = 2.0 * (1.0 - self.kr) * pr;
+    let b_offset = 2.0 * (1.0 - self.kb) * pb;
+    let g_offset = -(self.kr * r_offset + self.kb * b_offset) / self.kg;
+    [r_offset, g_offset, b_offset]
+  }
+
+  /// Whether a pixel is within the limits
+  pub fn is_legal(&self, y: u32, cb: u32, cr: u32) -> bool {
+    if y < self.luma_min || y > self.luma_max {
+      return false;
+    }
+
+    let luma = self.luma(y);
+    self
+      .rgb_offsets(cb, cr)
+      .iter()
+      .all(|offset| (RGB_MIN..=RGB_MAX).contains(&(luma + offset)))
+  }
+
+  /// How much of the chroma can be kept for every pixel sharing it to be legal, between 0 and 1.
+  /// The luma codes must already be within the limits
+  fn chroma_gain(&self, lumas: [u32; 2], cb: u32, cr: u32) -> f32 {
+    let offsets = self.rgb_offsets(cb, cr);
+
+    let mut gain: f32 = 1.0;
+    for y in lumas {
+      let luma = self.luma(y);
+      for offset in offsets {
+        if luma + offset > RGB_MAX {
+          gain = gain.min((RGB_MAX - luma) / offset);
+        } else if luma + offset < RGB_MIN {
+          gain = gain.min((RGB_MIN - luma) / offset);
+        }
+      }
+    }
+    gain.max(0.0)
+  }
+}
+
+/// Clip the luma, and reduce the chroma, of a flat YUVA422 image, so that it lies within the EBU R103 limits.
+/// This happens in place
+///
+/// Chroma is shared by each pair of pixels, so it gets reduced by the smallest amount which makes both pixels legal.
+/// The hue is kept, and alpha is left untouched.
+///
+/// When a `map` is given, each byte is set to 1 if the pixel was modified, or 0 otherwise.
+/// Returns the number of pixels which were modified
+pub(crate) fn legalise(
+  kr: f32,
+  kb: f32,
+  range: VideoRange,
+  width: u32,
+  height: u32,
+  data: &mut [u8],
+  mut map: Option<&mut [u8]>,
+) -> Result<u32> {
+  let pixel_count = width as usize * height as usize;
+  if data.len() != pixel_count * 4 {
+    return Err(Error::from_reason("Image buffer has incorrect length"));
+  }
+  if let Some(map) = &map {
+    if map.len() != pixel_count {
+      return Err(Error::from_reason("Map buffer has incorrect length"));
+    }
+  }
+
+  let limits = R103Limits::new(kr, kb, range);
+
+  let mut modified_count = 0;
+  for (pair, sample) in data.chunks_exact_mut(8).enumerate() {
+    let word1 = u32::from_be_bytes(sample[0..4].try_into().unwrap());
+    let word2 = u32::from_be_bytes(sample[4..8].try_into().unwrap());
+
+    let (y1, cb) = (word1 & 0x3ff, (word1 >> 10) & 0x3ff);
+    let (y2, cr) = (word2 & 0x3ff, (word2 >> 10) & 0x3ff);
+    let new_y1 = y1.clamp(limits.luma_min, limits.luma_max);
+    let new_y2 = y2.clamp(limits.luma_min, limits.luma_max);
+
+    let chroma_gain = limits.chroma_gain([new_y1, new_y2], cb, cr);
+    let (new_cb, new_cr) = if chroma_gain < 1.0 {
+      // Truncate towards neutral, so that rounding can't make the result illegal again
+      let scale = |c: u32| (512.0 + ((c as f32 - 512.0) * chroma_gain).trunc()) as u32;
+      (scale(cb), scale(cr))
+    } else {
+      (cb, cr)
+    };
+
+    let chroma_modified = new_cb != cb || new_cr != cr;
+    let modified = [
+      chroma_modified || new_y1 != y1,
+      chroma_modified || new_y2 != y2,
+    ];
+    if modified == [false, false] {
+      if let Some(map) = &mut map {
+        map[pair * 2..pair * 2 + 2].fill(0);
+      }
+      continue;
+    }
+
+    let new_word1 = (word1 & 0xfff00000) | (new_cb << 10) | new_y1;
+    let new_word2 = (word2 & 0xfff00000) | (new_cr << 10) | new_y2;
+    sample[0..4].copy_from_slice(&new_word1.to_be_bytes());
+    sample[4..8].copy_from_slice(&new_word2.to_be_bytes());
+
+    modified_count += modified.iter().filter(|&&m| m).count() as u32;
+    if let Some(map) = &mut map {
+      map[pair * 2] = modified[0] as u8;
+      map[pair * 2 + 1] = modified[1] as u8;
+    }
+  }
+
+  Ok(modified_count)
+}
+
+/// Legalise an image in place, allocating the map if it is requested
+pub(crate) fn legalise_with_report(
+  kr: f32,
+  kb: f32,
+  range: VideoRange,
+  width: u32,
+  height: u32,
+  data: &mut [u8],
+  with_map: bool,
+) -> Result<LegaliseReport> {
+  let mut map = with_map.then(|| vec![0; width as usize * height as usize]);
+  let modified_pixels = legalise(kr, kb, range, width, height, data, map.as_deref_mut())?;
+
+  Ok(LegaliseReport {
+    modified_pixels,
+    map: map.map(Buffer::from),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KR: f32 = 0.2126;
+  const KB: f32 = 0.0722;
+
+  fn pair(y1: u32, y2: u32, cb: u32, cr: u32) -> Vec<u8> {
+    [
+      (940 << 20 | cb << 10 | y1).to_be_bytes(),
+      (940 << 20 | cr << 10 | y2).to_be_bytes(),
+    ]
+    .concat()
+  }
+
+  fn components(sample: &[u8]) -> [u32; 4] {
+    let word1 = u32::from_be_bytes(sample[0..4].try_into().unwrap());
+    let word2 = u32::from_be_bytes(sample[4..8].try_into().unwrap());
+    [
+      word1 & 0x3ff,
+      word2 & 0x3ff,
+      (word1 >> 10) & 0x3ff,
+      (word2 >> 10) & 0x3ff,
+    ]
+  }
+
+  /// Decode a pixel to nominal RGB, to check it against the limits
+  fn to_rgb(y: u32, cb: u32, cr: u32) -> [f32; 3] {
+    let luma = (y as f32 - 64.0) / 876.0;
+    let pb = (cb as f32 - 512.0) / 896.0;
+    let pr = (cr as f32 - 512.0) / 896.0;
+    let r = luma + 2.0 * (1.0 - KR) * pr;
+    let b = luma + 2.0 * (1.0 - KB) * pb;
+    let g = (luma - KR * r - KB * b) / (1.0 - KR - KB);
+    [r, g, b]
+  }
+
+  #[test]
+  fn test_legal_image_untouched() {
+    // Black, white, and fully saturated red next to its own luma
+    let mut data = [
+      pair(64, 64, 512, 512),
+      pair(940, 940, 512, 512),
+      pair(250, 250, 409, 960),
+    ]
+    .concat();
+    let original = data.clone();
+    let mut map = vec![0xff; 6];
+
+    let count = legalise(KR, KB, VideoRange::Limited, 6, 1, &mut data, Some(&mut map)).unwrap();
+    assert_eq!(count, 0);
+    assert_eq!(data, original);
+    assert_eq!(map, [0; 6]);
+  }
+
+  #[test]
+  fn test_shared_chroma_reduced() {
+    // Saturated red chroma shared with a white pixel makes the white pixel's red far too high
+    let mut data = [pair(250, 940, 409, 960), pair(500, 500, 512, 512)].concat();
+    let mut map = vec![0; 4];
+
+    let count = legalise(KR, KB, VideoRange::Limited, 4, 1, &mut data, Some(&mut map)).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(map, [1, 1, 0, 0]);
+
+    let [y1, y2, cb, cr] = components(&data[0..8]);
+    assert_eq!([y1, y2], [250, 940]);
+    assert!(cr > 512 && cr < 960 && cb > 409 && cb < 512);
+    for y in [y1, y2] {
+      for value in to_rgb(y, cb, cr) {
+        assert!((RGB_MIN..=RGB_MAX).contains(&value), "{value}");
+      }
+    }
+
+    // The hue is kept, apart from quantising the reduced chroma
+    let original_ratio = (409.0 - 512.0) / (960.0 - 512.0);
+    let ratio = (cb as f32 - 512.0) / (cr as f32 - 512.0);
+    assert!(
+      (ratio - original_ratio).abs() < 0.03,
+      "{ratio} {original_ratio}"
+    );
+  }
+
+  #[test]
+  fn test_luma_clipped() {
+    let mut data = pair(4, 1019, 512, 512);
+    let count = legalise(KR, KB, VideoRange::Limited, 2, 1, &mut data, None).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(components(&data), [56, 966, 512, 512]);
+
+    // The whole range of full range luma is legal
+    let mut data = pair(0, 1023, 512, 512);
+    let count = legalise(KR, KB, VideoRange::Full, 2, 1, &mut data, None).unwrap();
+    assert_eq!(count, 0);
+  }
+}
@@ -1,158 +1,579 @@
 #![feature(portable_simd)]
 
-use napi::{Env, JsBuffer, JsUndefined};
-use rgba_to_yuva422_simd::rgb_to_yuva422_simd;
+use analyse::{analyse_still as analyse, StillAnalysis};
+use bytes::JsBytes;
+use canonical::{canonical_hash, stills_equal as samples_equal};
+use compare::{compare_stills as compare, decode_still, StillComparison};
+use convert::{rgba_to_yuva_422, validate_dimensions, yuva_422_to_rgba, RowLayout};
+use dispatch::{capabilities, Capabilities};
+use legalise::{legalise_with_report, LegaliseReport};
+use napi::bindgen_prelude::Buffer;
+use napi::{Env, JsUndefined};
+use options::{
+  AnalyseOptions, Arithmetic, Colorspace, CompareOptions, DecodeOptions, EncodeOptions,
+  FillKeyEncodeOptions, FillKeyOptions, LegaliseOptions, ScopeKind, ScopeOptions, StillFormat,
+  VideoRange,
+};
+use region::rgba_to_yuva_422_region;
+use scopes::render_scope as render;
 use yuv_constants::YuvConstantsSimd;
-use yuva422_to_rgba_simd::yuva422_to_rgb_simd;
 
+mod analyse;
+pub mod api;
+mod bytes;
+mod canonical;
+mod codec;
+mod compare;
+#[cfg(test)]
+mod conformance;
+mod convert;
+mod decode_lut;
+mod dispatch;
+mod dither;
+mod error;
+mod fixed_point;
+#[cfg(any(test, feature = "fuzz"))]
+#[doc(hidden)]
+pub mod fuzz;
+mod javascript;
+mod legalise;
+mod options;
+mod region;
 mod rgba_to_yuva422_simd;
 mod rle;
+mod scopes;
+mod transfer;
 mod yuv_constants;
 mod yuva422_to_rgba_simd;
 
 #[macro_use]
 extern crate napi_derive;
 
+fn constants_for_height(
+  width: u32,
+  height: u32,
+  range: Option<VideoRange>,
+  alpha_range: Option<VideoRange>,
+) -> error::Result<&'static YuvConstantsSimd> {
+  // @todo: MINT - 2018-5-24:
+  // Create util functions that handle proper colour spaces in UHD.
+
+  validate_dimensions(width, height)?;
+
+  Ok(YuvConstantsSimd::shared(
+    Colorspace::for_height(height),
+    range.unwrap_or(VideoRange::Limited),
+    alpha_range.unwrap_or(VideoRange::Limited),
+  ))
+}
+
 /// Convert a RGBA buffer to ATEM YUV422 packing in the correct colorspace
 ///
 /// This is performed synchronously
 ///
 /// @param width - The width of the image
 /// @param height - The height of the image
-/// @param input - The input RGBA pixel data
-/// @param output - The output YUVA422 pixel data
+/// @param input - The input RGBA pixel data, in any ArrayBuffer-backed value
+/// @param output - The output YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format and layout of the input
 #[napi]
 pub fn convert_rgba_to_yuva_422(
   env: Env,
   width: u32,
   height: u32,
-  input: JsBuffer,
-  output: JsBuffer,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut output: JsBytes,
+  options: Option<EncodeOptions>,
 ) -> napi::Result<JsUndefined> {
-  // @todo: MINT - 2018-5-24:
-  // Create util functions that handle proper colour spaces in UHD.
+  input.check_no_overlap(&output)?;
+  api::encode_rgba_to_yuva_422(
+    width,
+    height,
+    &input,
+    &mut output,
+    &options.unwrap_or_default(),
+  )?;
 
-  let input_vec = input.into_value()?;
-  let mut output_vec = output.into_value()?;
+  env.get_undefined()
+}
 
-  let pixel_count = (width * height) as usize;
-  if width % 8 != 0 {
-    env.throw_error("Width must be a multiple of 8", None)?;
-    return env.get_undefined();
-  }
-  let byte_count = pixel_count * 4;
-  if input_vec.len() != byte_count {
-    env.throw_error("Input buffer has incorrect length", None)?;
-    return env.get_undefined();
-  }
-  if output_vec.len() != byte_count {
-    env.throw_error("Output buffer has incorrect length", None)?;
-    return env.get_undefined();
-  }
+/// Convert a RGBA buffer to ATEM YUV422 packing in the correct colorspace, into a newly allocated buffer
+///
+/// The output buffer is not zero-filled before conversion, as every byte gets written
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param input - The input RGBA pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format and layout of the input
+/// @returns The output YUVA422 pixel data
+#[napi(ts_return_type = "Buffer")]
+pub fn convert_rgba_to_yuva_422_alloc(
+  env: Env,
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  options: Option<EncodeOptions>,
+) -> napi::Result<JsBytes> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(width, height, options.range, options.alpha_range)?;
+  let input_format = options.encode_format();
+  let input_layout = RowLayout::new(width, input_format.format.bytes_per_pixel(), options.stride)?;
+  let mut output = JsBytes::create_buffer(env, width as usize * height as usize * 4)?;
 
-  let [kr, kb] = if height >= 720 {
-    [0.2126, 0.0722] // BT.709
-  } else {
-    [0.299, 0.114] // BT.601
-  };
+  rgba_to_yuva_422(
+    constants,
+    width,
+    height,
+    &input,
+    input_format,
+    input_layout,
+    &mut output,
+  )?;
+
+  Ok(output)
+}
 
-  let sample_count = pixel_count / 2;
+/// Convert separate RGB fill and key images to ATEM YUV422 packing in the correct colorspace, with the key as the alpha
+///
+/// The fill and key are combined as they are converted, without an intermediate RGBA image. This is performed
+/// synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param fill - The input 8-bit RGB fill, of 3 bytes per pixel, in any ArrayBuffer-backed value
+/// @param key - The input key in the format given in the options, in any ArrayBuffer-backed value
+/// @param output - The output YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format of the key and how to adjust it
+#[napi]
+pub fn convert_fill_key_to_yuva_422(
+  env: Env,
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] fill: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] key: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut output: JsBytes,
+  options: Option<FillKeyEncodeOptions>,
+) -> napi::Result<JsUndefined> {
+  fill.check_no_overlap(&output)?;
+  key.check_no_overlap(&output)?;
+  api::encode_fill_key_to_yuva_422(
+    width,
+    height,
+    &fill,
+    &key,
+    &mut output,
+    &options.unwrap_or_default(),
+  )?;
 
-  let constants_simd = YuvConstantsSimd::create(kr, kb);
+  env.get_undefined()
+}
 
-  let batch_count = sample_count / 4;
-  for i in 0..batch_count {
-    let offset_start = i * 32;
-    let offset_end = offset_start + 32;
+/// Convert a RGBA image, and write it over a rectangle of an existing ATEM YUV422 image
+///
+/// The existing image must not be RLE compressed. Pixels sharing chroma with a pixel outside of the region keep their
+/// existing chroma
+///
+/// This is performed synchronously
+///
+/// @param target - The YUVA422 pixel data to write the region into, in any ArrayBuffer-backed value
+/// @param targetWidth - The width of the target image
+/// @param targetHeight - The height of the target image
+/// @param x - The x position of the region in the target image
+/// @param y - The y position of the region in the target image
+/// @param input - The input RGBA pixel data of the region, in any ArrayBuffer-backed value
+/// @param width - The width of the region
+/// @param height - The height of the region
+/// @param options - Options describing the format and layout of the input
+#[napi]
+#[allow(clippy::too_many_arguments)]
+pub fn convert_rgba_region_to_yuva_422(
+  env: Env,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut target: JsBytes,
+  target_width: u32,
+  target_height: u32,
+  x: u32,
+  y: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  width: u32,
+  height: u32,
+  options: Option<EncodeOptions>,
+) -> napi::Result<JsUndefined> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(
+    target_width,
+    target_height,
+    options.range,
+    options.alpha_range,
+  )?;
+  let input_format = options.encode_format();
+  let input_layout = RowLayout::new(width, input_format.format.bytes_per_pixel(), options.stride)?;
+  input.check_no_overlap(&target)?;
 
-    rgb_to_yuva422_simd(
-      &constants_simd,
-      &input_vec[offset_start..offset_end],
-      &mut output_vec[offset_start..offset_end],
-    );
-  }
+  rgba_to_yuva_422_region(
+    constants,
+    target_width,
+    target_height,
+    &mut target,
+    x,
+    y,
+    width,
+    height,
+    &input,
+    input_format,
+    input_layout,
+  )?;
 
   env.get_undefined()
 }
 
-/// Convert a RGBA buffer to ATEM YUV422 packing in the correct colorspace
+/// Convert an ATEM YUVA422 buffer to RGBA from the correct colorspace
 ///
 /// This is performed synchronously
 ///
 /// @param width - The width of the image
 /// @param height - The height of the image
-/// @param input - The input RGBA pixel data
-/// @param output - The output YUVA422 pixel data
+/// @param input - The input YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param output - The output RGBA pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format and layout of the output
 #[napi]
 pub fn convert_yuva_422_to_rgba(
   env: Env,
   width: u32,
   height: u32,
-  input: JsBuffer,
-  output: JsBuffer,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut output: JsBytes,
+  options: Option<DecodeOptions>,
 ) -> napi::Result<JsUndefined> {
-  // @todo: MINT - 2018-5-24:
-  // Create util functions that handle proper colour spaces in UHD.
+  input.check_no_overlap(&output)?;
+  api::decode_yuva_422_to_rgba(
+    width,
+    height,
+    &input,
+    &mut output,
+    &options.unwrap_or_default(),
+  )?;
 
-  let input_vec = input.into_value()?;
-  let mut output_vec = output.into_value()?;
+  env.get_undefined()
+}
 
-  let pixel_count = (width * height) as usize;
-  if width % 8 != 0 {
-    env.throw_error("Width must be a multiple of 8", None)?;
-    return env.get_undefined();
-  }
-  let byte_count = pixel_count * 4;
-  // if input_vec.len() % 32 != 0 {
-  //   env.throw_error("Input buffer has incorrect length", None)?;
-  //   return env.get_undefined();
-  // }
-  if output_vec.len() != byte_count {
-    env.throw_error("Output buffer has incorrect length", None)?;
-    return env.get_undefined();
+/// Convert an ATEM YUVA422 buffer to RGBA from the correct colorspace, into a newly allocated buffer
+///
+/// The output buffer is not zero-filled before conversion, as every byte gets written. Any row padding is zero-filled
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param input - The input YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format and layout of the output
+/// @returns The output RGBA pixel data
+#[napi(ts_return_type = "Buffer")]
+pub fn convert_yuva_422_to_rgba_alloc(
+  env: Env,
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  options: Option<DecodeOptions>,
+) -> napi::Result<JsBytes> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(width, height, options.range, options.alpha_range)?;
+  let output_format = options.decode_format();
+  let output_layout = RowLayout::new(
+    width,
+    output_format.format.bytes_per_pixel(),
+    options.stride,
+  )?;
+  let mut output = JsBytes::create_buffer(env, output_layout.byte_len(height))?;
+  if output_layout.padded {
+    output.fill(0);
   }
 
-  let [kr, kb] = if height >= 720 {
-    [0.2126, 0.0722] // BT.709
-  } else {
-    [0.299, 0.114] // BT.601
-  };
+  yuva_422_to_rgba(
+    constants,
+    width,
+    height,
+    &input,
+    &mut output,
+    output_format,
+    output_layout,
+  )?;
+
+  Ok(output)
+}
+
+/// Convert an ATEM YUVA422 buffer to separate RGB fill and key images from the correct colorspace, as the ATEM media
+/// player outputs them
+///
+/// The key is a luma-only matte decoded from the alpha. This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param input - The input YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param fill - The output 8-bit RGB fill, of 3 bytes per pixel, in any ArrayBuffer-backed value
+/// @param key - The output 8-bit key, of 1 byte per pixel, in any ArrayBuffer-backed value
+/// @param options - Options describing the range of the input, and whether to premultiply the fill
+#[napi]
+pub fn convert_yuva_422_to_fill_key(
+  env: Env,
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut fill: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut key: JsBytes,
+  options: Option<FillKeyOptions>,
+) -> napi::Result<JsUndefined> {
+  input.check_no_overlap(&fill)?;
+  input.check_no_overlap(&key)?;
+  fill.check_no_overlap(&key)?;
+  api::decode_yuva_422_to_fill_key(
+    width,
+    height,
+    &input,
+    &mut fill,
+    &mut key,
+    &options.unwrap_or_default(),
+  )?;
 
-  let constants_simd = YuvConstantsSimd::create(kr, kb);
+  env.get_undefined()
+}
 
-  let mut write_offset = 0;
-  let mut decoder = rle::RLEDecoder::new(&input_vec);
+/// Clip the luma, and reduce the chroma, of an ATEM YUVA422 buffer, so that it lies within the EBU R103 broadcast-safe
+/// limits. This modifies the buffer in place
+///
+/// The buffer must not be RLE compressed. Chroma is shared by each pair of pixels, so it gets reduced by the smallest
+/// amount which makes both pixels legal, keeping the hue
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param data - The YUVA422 pixel data to legalise, in any ArrayBuffer-backed value
+/// @param options - Options describing the range of the data, and whether to return a map of the modified pixels
+/// @returns The number of modified pixels, and the optional map
+#[napi]
+pub fn legalise_yuva_422(
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] mut data: JsBytes,
+  options: Option<LegaliseOptions>,
+) -> napi::Result<LegaliseReport> {
+  validate_dimensions(width, height)?;
+  let options = options.unwrap_or_default();
+  let [kr, kb] = Colorspace::for_height(height).coefficients();
 
-  // let batch_count = sample_count / 4;
-  while write_offset < output_vec.len() {
-    let sample1 = decoder.read_sample();
-    let sample2 = decoder.read_sample();
-    let sample3 = decoder.read_sample();
-    let sample4 = decoder.read_sample();
+  Ok(legalise_with_report(
+    kr,
+    kb,
+    options.range.unwrap_or(VideoRange::Limited),
+    width,
+    height,
+    &mut data,
+    options.map.unwrap_or(false),
+  )?)
+}
 
-    // TODO - this could be handled better if needed, but that is difficult to do sanely
-    if sample1.is_none() || sample2.is_none() || sample3.is_none() || sample4.is_none() {
-      break; // Not enough data
+/// Report the levels of a still image as it would be sent to the ATEM, for quality control
+///
+/// RGBA input is encoded with the same maths as `convertRGBAToYUV422` before being analysed. The report contains
+/// histograms of the 10-bit luma and chroma codes, the pixels outside of the EBU R103 broadcast-safe limits and the
+/// bounding boxes of the connected regions they form, and statistics about the alpha
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param data - The pixel data, in any ArrayBuffer-backed value
+/// @param options - Options describing the format and range of the data
+/// @returns The analysis of the image
+#[napi]
+pub fn analyse_still(
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] data: JsBytes,
+  options: Option<AnalyseOptions>,
+) -> napi::Result<StillAnalysis> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(width, height, options.range, options.alpha_range)?;
+  let input = match options.encode_format() {
+    Some(input_format) => {
+      let input_layout =
+        RowLayout::new(width, input_format.format.bytes_per_pixel(), options.stride)?;
+      Some((input_format, input_layout))
     }
+    None => None,
+  };
 
-    let old_write_offset = write_offset;
-    write_offset += 32;
-
-    yuva422_to_rgb_simd(
-      &constants_simd,
-      &sample1.unwrap(),
-      &sample2.unwrap(),
-      &sample3.unwrap(),
-      &sample4.unwrap(),
-      &mut output_vec[old_write_offset..write_offset],
-    );
-  }
+  let analysis = analyse(
+    constants,
+    options.range.unwrap_or(VideoRange::Limited),
+    options.alpha_range.unwrap_or(VideoRange::Limited),
+    width,
+    height,
+    &data,
+    input,
+    options.max_regions.unwrap_or(16) as usize,
+  )?;
 
-  // Throw if not enough data was decoded
-  if write_offset < output_vec.len() {
-    env.throw_error("Input buffer has less data than expected", None)?;
-    return env.get_undefined();
-  }
+  Ok(analysis.into())
+}
 
-  env.get_undefined()
+/// Render a waveform, RGB parade, vectorscope or histogram of an ATEM YUVA422 buffer, as an 8-bit RGBA image
+///
+/// The samples are read directly, so the image does not need decoding to RGB first. It may be RLE compressed
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the image
+/// @param height - The height of the image
+/// @param input - The input YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param kind - The scope to render
+/// @param scopeWidth - The width of the rendered scope
+/// @param scopeHeight - The height of the rendered scope
+/// @param options - Options describing the range of the data, and the vectorscope targets
+/// @returns The RGBA pixel data of the scope
+#[napi(ts_return_type = "Buffer")]
+#[allow(clippy::too_many_arguments)]
+pub fn render_scope(
+  env: Env,
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] input: JsBytes,
+  #[napi(ts_arg_type = "'waveform' | 'parade' | 'vectorscope' | 'histogram' | 'rgbHistogram'")]
+  kind: ScopeKind,
+  scope_width: u32,
+  scope_height: u32,
+  options: Option<ScopeOptions>,
+) -> napi::Result<JsBytes> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(width, height, options.range, None)?;
+  let target_saturation = options.target_saturation()?;
+  let mut output = JsBytes::create_buffer(env, scope_width as usize * scope_height as usize * 4)?;
+
+  render(
+    constants,
+    kind,
+    width,
+    height,
+    &input,
+    scope_width,
+    scope_height,
+    target_saturation,
+    &mut output,
+  )?;
+
+  Ok(output)
+}
+
+/// Compare two stills of the same size, such as a source image and the same image downloaded from an ATEM
+///
+/// YUVA422 images are decoded to 8-bit RGBA first, so differences in RLE compression are ignored. The comparison
+/// measures PSNR, SSIM and the CIEDE2000 colour difference, and passes if every measurement is within the thresholds
+///
+/// This is performed synchronously
+///
+/// @param width - The width of the images
+/// @param height - The height of the images
+/// @param a - The pixel data of the first image, in any ArrayBuffer-backed value
+/// @param b - The pixel data of the second image, in any ArrayBuffer-backed value
+/// @param options - Options describing the format of each image, and the thresholds
+/// @returns The measurements, the optional heat map, and whether the comparison passed
+#[napi]
+pub fn compare_stills(
+  width: u32,
+  height: u32,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] a: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] b: JsBytes,
+  options: Option<CompareOptions>,
+) -> napi::Result<StillComparison> {
+  let options = options.unwrap_or_default();
+  let constants = constants_for_height(width, height, options.range, options.alpha_range)?;
+  let [kr, kb] = Colorspace::for_height(height).coefficients();
+
+  let a = decode_still(
+    constants,
+    width,
+    height,
+    &a,
+    options.format_a.unwrap_or(StillFormat::Yuva422),
+  )?;
+  let b = decode_still(
+    constants,
+    width,
+    height,
+    &b,
+    options.format_b.unwrap_or(StillFormat::Yuva422),
+  )?;
+
+  let comparison = compare(
+    kr,
+    kb,
+    width,
+    height,
+    &a,
+    &b,
+    options.limits(),
+    options.heat_map.unwrap_or(true),
+  );
+
+  Ok(comparison.into())
+}
+
+/// Hash the samples of an ATEM YUVA422 buffer, which may be RLE compressed
+///
+/// The hash only depends on the expanded samples, so an image has the same hash however it was compressed. For a flat
+/// image it is the MD5 of the buffer. Images which expand to more than an 8K still are rejected
+///
+/// This is performed synchronously
+///
+/// @param data - The YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @returns The 16 byte MD5 digest
+#[napi]
+pub fn hash_yuva_422(
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] data: JsBytes,
+) -> napi::Result<Buffer> {
+  Ok(canonical_hash(&data)?.to_vec().into())
+}
+
+/// Check whether two ATEM YUVA422 buffers, which may be RLE compressed, hold the same samples
+///
+/// Neither image is expanded into memory. Images which expand to more than an 8K still are rejected
+///
+/// This is performed synchronously
+///
+/// @param a - The first YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @param b - The second YUVA422 pixel data, in any ArrayBuffer-backed value
+/// @returns Whether the expanded samples are identical
+#[napi]
+pub fn stills_equal(
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] a: JsBytes,
+  #[napi(ts_arg_type = "ArrayBufferView | ArrayBuffer")] b: JsBytes,
+) -> napi::Result<bool> {
+  Ok(samples_equal(&a, &b)?)
+}
+
+/// Report the SIMD support of the CPU, and which kernels are used for converting 8-bit RGBA
+///
+/// The widest kernels the CPU supports are chosen when the library is first used. A narrower path can be forced by
+/// setting the `ATEM_IMAGE_TOOLS_SIMD` environment variable to `scalar`, `portable`, `avx2` or `avx512` before then,
+/// or for a single conversion with the `simdPath` option. A forced path is also used for large images, which are
+/// otherwise decoded with lookup tables if those were faster when first timed
+#[napi]
+pub fn get_capabilities() -> Capabilities {
+  capabilities()
+}
+
+/// Set the arithmetic used for converting 8-bit RGBA when none is given in the options
+///
+/// `fixed` gives the same output on every architecture, so should be set when hashes of encoded images are compared
+/// between machines. `javascript` should be set when they are compared with stills encoded by atem-connection, and then
+/// full range video and deeper input must be encoded with another arithmetic given in the options
+///
+/// @param arithmetic - The arithmetic to use by default
+#[napi]
+pub fn set_default_arithmetic(
+  #[napi(ts_arg_type = "'float' | 'fixed' | 'javascript'")] arithmetic: Arithmetic,
+) {
+  fixed_point::set_default_arithmetic(arithmetic)
 }
@@ -0,0 +1,559 @@
+use crate::dispatch::SimdPath;
+use crate::error::{Error, Result};
+use crate::fixed_point::default_arithmetic;
+
+/// The colour matrix used for conversions
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Colorspace {
+  #[napi(value = "bt601")]
+  Bt601,
+  #[napi(value = "bt709")]
+  Bt709,
+}
+impl Colorspace {
+  /// The colorspace the ATEM uses for a given video mode
+  pub fn for_height(height: u32) -> Colorspace {
+    if height >= 720 {
+      Colorspace::Bt709
+    } else {
+      Colorspace::Bt601
+    }
+  }
+
+  /// The `[kr, kb]` coefficients of the colorspace
+  pub fn coefficients(&self) -> [f32; 2] {
+    match self {
+      Colorspace::Bt601 => [0.299, 0.114],
+      Colorspace::Bt709 => [0.2126, 0.0722],
+    }
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct EncodeOptions {
+  /// The pixel layout of the input. Defaults to `rgba`
+  #[napi(ts_type = "'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32'")]
+  pub format: Option<InputFormat>,
+  /// The number of bytes from the start of one row of the input to the start of the next, for when the rows are padded.
+  /// Defaults to `width` multiplied by the bytes per pixel of the format
+  pub stride: Option<u32>,
+  /// How the colour values of `rgbaf32` input are encoded. Defaults to `gamma`
+  #[napi(ts_type = "'gamma' | 'linear'")]
+  pub transfer: Option<TransferFunction>,
+  /// Dither `rgba16le`, `rgba16be` and `rgbaf32` input when rounding it to 10-bit. Defaults to no dithering
+  #[napi(ts_type = "'bayer' | 'blueNoise'")]
+  pub dither: Option<Dither>,
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic`, which is
+  /// initially `float`
+  #[napi(ts_type = "'float' | 'fixed' | 'javascript'")]
+  pub arithmetic: Option<Arithmetic>,
+  /// The kernels used for converting `rgba` pixels with `float` arithmetic. Every path gives identical output. Defaults
+  /// to the widest the CPU supports, and a path wider than that is ignored
+  #[napi(ts_type = "'scalar' | 'portable' | 'avx2' | 'avx512'")]
+  pub simd_path: Option<SimdPath>,
+  /// The number of threads the image is split between, which gives identical output. 0 uses one per CPU core, which
+  /// is also the most that are used. Defaults to 1, converting on the calling thread
+  pub threads: Option<u32>,
+}
+impl EncodeOptions {
+  /// The format of the input, with the defaults applied
+  pub fn encode_format(&self) -> EncodeFormat {
+    EncodeFormat {
+      format: self.format.unwrap_or(InputFormat::Rgba),
+      transfer: self.transfer.unwrap_or(TransferFunction::Gamma),
+      dither: self.dither,
+      arithmetic: self.arithmetic.unwrap_or_else(default_arithmetic),
+      path: self.simd_path.filter(|path| path.is_available()),
+      threads: thread_count(self.threads),
+    }
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct DecodeOptions {
+  /// The pixel layout of the output. Defaults to `rgba`
+  #[napi(ts_type = "'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32' | 'r210' | 'a2r10g10b10'")]
+  pub format: Option<OutputFormat>,
+  /// The number of bytes from the start of one row of the output to the start of the next, for when the rows are padded.
+  /// Defaults to `width` multiplied by the bytes per pixel of the format
+  pub stride: Option<u32>,
+  /// How the colour values of `rgbaf32` output are encoded. Defaults to `gamma`
+  #[napi(ts_type = "'gamma' | 'linear'")]
+  pub transfer: Option<TransferFunction>,
+  /// Dither `rgba` output when rounding it to 8-bit. Defaults to no dithering
+  #[napi(ts_type = "'bayer' | 'blueNoise'")]
+  pub dither: Option<Dither>,
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic`, which is
+  /// initially `float`
+  #[napi(ts_type = "'float' | 'fixed' | 'javascript'")]
+  pub arithmetic: Option<Arithmetic>,
+  /// The kernels used for converting `rgba` pixels with `float` arithmetic. Every path gives identical output. Defaults
+  /// to the widest the CPU supports, and a path wider than that is ignored
+  #[napi(ts_type = "'scalar' | 'portable' | 'avx2' | 'avx512'")]
+  pub simd_path: Option<SimdPath>,
+  /// The number of threads the image is split between, which gives identical output. 0 uses one per CPU core, which
+  /// is also the most that are used. Defaults to 1, converting on the calling thread
+  pub threads: Option<u32>,
+}
+impl DecodeOptions {
+  /// The format of the output, with the defaults applied
+  pub fn decode_format(&self) -> DecodeFormat {
+    DecodeFormat {
+      format: self.format.unwrap_or(OutputFormat::Rgba),
+      transfer: self.transfer.unwrap_or(TransferFunction::Gamma),
+      dither: self.dither,
+      arithmetic: self.arithmetic.unwrap_or_else(default_arithmetic),
+      path: self.simd_path.filter(|path| path.is_available()),
+      threads: thread_count(self.threads),
+    }
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct FillKeyOptions {
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes, which the key is decoded from. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// Multiply the fill by the key, for keyers which expect a shaped fill. Defaults to false
+  pub premultiply: Option<bool>,
+  /// The arithmetic used for decoding. Defaults to the one set with `setDefaultArithmetic`, which is initially `float`
+  #[napi(ts_type = "'float' | 'fixed' | 'javascript'")]
+  pub arithmetic: Option<Arithmetic>,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct FillKeyEncodeOptions {
+  /// The pixel layout of the key. Defaults to `luma`
+  #[napi(ts_type = "'luma' | 'rgb' | 'rgba'")]
+  pub key_format: Option<KeyFormat>,
+  /// Invert the key, for mattes which are black where the fill is opaque. Defaults to false
+  pub invert_key: Option<bool>,
+  /// The level of the key from 0 to 1 at and below which the fill is transparent, after any inversion. Defaults to 0
+  pub key_clip: Option<f64>,
+  /// The multiplier of the key above the clip level, with the result limited to opaque. Defaults to 1
+  pub key_gain: Option<f64>,
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes, which the key is encoded to. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// The arithmetic used for encoding. Defaults to the one set with `setDefaultArithmetic`, which is initially `float`.
+  /// With `fixed` and `javascript` the transformed key is rounded to 8 bits first, as they only take 8-bit input, and
+  /// `javascript` can't encode full range video
+  #[napi(ts_type = "'float' | 'fixed' | 'javascript'")]
+  pub arithmetic: Option<Arithmetic>,
+}
+impl FillKeyEncodeOptions {
+  /// The adjustment of the key, with the defaults applied
+  pub fn key_transform(&self) -> Result<KeyTransform> {
+    let clip = self.key_clip.unwrap_or(0.0);
+    if !(0.0..=1.0).contains(&clip) {
+      return Err(Error::from_reason("Key clip must be from 0 to 1"));
+    }
+    let gain = self.key_gain.unwrap_or(1.0);
+    if !(gain.is_finite() && gain >= 0.0) {
+      return Err(Error::from_reason("Key gain must be a positive number"));
+    }
+
+    Ok(KeyTransform {
+      invert: self.invert_key.unwrap_or(false),
+      clip: (clip * 255.0) as f32,
+      gain: gain as f32,
+    })
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct LegaliseOptions {
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// Return a map of which pixels were modified
+  pub map: Option<bool>,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct AnalyseOptions {
+  /// The pixel layout of the image. Defaults to `yuva422`, which may be RLE compressed. Other formats are encoded
+  /// before being analysed
+  #[napi(ts_type = "'yuva422' | 'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32'")]
+  pub format: Option<AnalyseFormat>,
+  /// The number of bytes from the start of one row of RGBA input to the start of the next, for when the rows are
+  /// padded. Defaults to `width` multiplied by the bytes per pixel of the format
+  pub stride: Option<u32>,
+  /// The transfer function of `rgbaf32` input. Defaults to `gamma`
+  #[napi(ts_type = "'gamma' | 'linear'")]
+  pub transfer: Option<TransferFunction>,
+  /// Dither `rgba16le`, `rgba16be` and `rgbaf32` input when encoding it. Defaults to no dithering
+  #[napi(ts_type = "'bayer' | 'blueNoise'")]
+  pub dither: Option<Dither>,
+  /// The range of the luma and chroma codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// The largest number of illegal regions to list. Defaults to 16
+  pub max_regions: Option<u32>,
+}
+impl AnalyseOptions {
+  /// The format to encode RGBA input with, or `None` when the input is already YUVA422
+  pub fn encode_format(&self) -> Option<EncodeFormat> {
+    let format = match self.format.unwrap_or(AnalyseFormat::Yuva422) {
+      AnalyseFormat::Yuva422 => return None,
+      AnalyseFormat::Rgba => InputFormat::Rgba,
+      AnalyseFormat::Rgba16le => InputFormat::Rgba16le,
+      AnalyseFormat::Rgba16be => InputFormat::Rgba16be,
+      AnalyseFormat::Rgbaf32 => InputFormat::Rgbaf32,
+    };
+
+    Some(EncodeFormat {
+      format,
+      transfer: self.transfer.unwrap_or(TransferFunction::Gamma),
+      dither: self.dither,
+      arithmetic: default_arithmetic(),
+      path: None,
+      threads: 1,
+    })
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct ScopeOptions {
+  /// The range of the luma and chroma codes, which positions the graticule. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The saturation of the colour targets marked on a vectorscope, as a percentage of either 75 or 100. Defaults to 75
+  #[napi(ts_type = "75 | 100")]
+  pub targets: Option<u32>,
+}
+impl ScopeOptions {
+  /// The saturation of the vectorscope targets, as a fraction
+  pub fn target_saturation(&self) -> Result<f32> {
+    match self.targets.unwrap_or(75) {
+      75 => Ok(0.75),
+      100 => Ok(1.0),
+      _ => Err(Error::from_reason("Vectorscope targets must be 75 or 100")),
+    }
+  }
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct CompareThresholds {
+  /// The smallest PSNR to pass, in decibels. Defaults to 40
+  pub min_psnr: Option<f64>,
+  /// The smallest SSIM to pass. Defaults to 0.98
+  pub min_ssim: Option<f64>,
+  /// The largest CIEDE2000 difference of any pixel to pass. Defaults to 3
+  pub max_delta_e: Option<f64>,
+  /// The largest difference in 8-bit alpha of any pixel to pass. Defaults to 2
+  pub max_alpha_difference: Option<u32>,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct CompareOptions {
+  /// The pixel layout of the first image. Defaults to `yuva422`, which may be RLE compressed
+  #[napi(ts_type = "'yuva422' | 'rgba'")]
+  pub format_a: Option<StillFormat>,
+  /// The pixel layout of the second image. Defaults to `yuva422`, which may be RLE compressed
+  #[napi(ts_type = "'yuva422' | 'rgba'")]
+  pub format_b: Option<StillFormat>,
+  /// The range of the luma and chroma codes of YUVA422 images. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub range: Option<VideoRange>,
+  /// The range of the alpha codes of YUVA422 images. Defaults to `limited`
+  #[napi(ts_type = "'limited' | 'full'")]
+  pub alpha_range: Option<VideoRange>,
+  /// Return an image of the difference of each pixel. Defaults to true
+  pub heat_map: Option<bool>,
+  /// The thresholds for the comparison to pass
+  pub thresholds: Option<CompareThresholds>,
+}
+impl CompareOptions {
+  /// The thresholds, with the defaults applied
+  pub fn limits(&self) -> CompareLimits {
+    let thresholds = self.thresholds.as_ref();
+    CompareLimits {
+      min_psnr: thresholds.and_then(|t| t.min_psnr).unwrap_or(40.0),
+      min_ssim: thresholds.and_then(|t| t.min_ssim).unwrap_or(0.98),
+      max_delta_e: thresholds.and_then(|t| t.max_delta_e).unwrap_or(3.0),
+      max_alpha_difference: thresholds.and_then(|t| t.max_alpha_difference).unwrap_or(2),
+    }
+  }
+}
+
+/// The thresholds for a comparison of two images to pass
+#[derive(Clone, Copy)]
+pub struct CompareLimits {
+  pub min_psnr: f64,
+  pub min_ssim: f64,
+  pub max_delta_e: f64,
+  pub max_alpha_difference: u32,
+}
+
+/// The adjustment of a key before it is encoded as alpha, in the 0-255 range of 8-bit input
+#[derive(Clone, Copy)]
+pub struct KeyTransform {
+  pub invert: bool,
+  pub clip: f32,
+  pub gain: f32,
+}
+
+/// How the pixels of an image being encoded are stored and quantised
+#[derive(Clone, Copy)]
+pub struct EncodeFormat {
+  pub format: InputFormat,
+  pub transfer: TransferFunction,
+  pub dither: Option<Dither>,
+  pub arithmetic: Arithmetic,
+  /// The requested SIMD path, or `None` for the active one
+  pub path: Option<SimdPath>,
+  pub threads: usize,
+}
+
+/// How the pixels of a decoded image are stored and quantised
+#[derive(Clone, Copy)]
+pub struct DecodeFormat {
+  pub format: OutputFormat,
+  pub transfer: TransferFunction,
+  pub dither: Option<Dither>,
+  pub arithmetic: Arithmetic,
+  /// The requested SIMD path, or `None` for the active one, or the lookup tables when they are faster
+  pub path: Option<SimdPath>,
+  pub threads: usize,
+}
+
+/// The number of threads to convert with, where 0 means one per CPU core
+///
+/// More threads than cores would only add the cost of starting them, so a larger count is limited to one per core
+fn thread_count(threads: Option<u32>) -> usize {
+  let cores = || std::thread::available_parallelism().map_or(1, |count| count.get());
+  match threads {
+    Some(0) => cores(),
+    Some(threads) => (threads as usize).min(cores()),
+    None => 1,
+  }
+}
+
+/// The pixel layout of images being encoded
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputFormat {
+  /// 8 bits per channel RGBA
+  #[napi(value = "rgba")]
+  Rgba,
+  /// 16 bits per channel RGBA, in little-endian byte order
+  #[napi(value = "rgba16le")]
+  Rgba16le,
+  /// 16 bits per channel RGBA, in big-endian byte order
+  #[napi(value = "rgba16be")]
+  Rgba16be,
+  /// 32-bit float per channel RGBA in the range 0-1, in the platform byte order like a `Float32Array`
+  #[napi(value = "rgbaf32")]
+  Rgbaf32,
+}
+impl InputFormat {
+  pub fn bytes_per_pixel(&self) -> usize {
+    match self {
+      InputFormat::Rgba => 4,
+      InputFormat::Rgba16le | InputFormat::Rgba16be => 8,
+      InputFormat::Rgbaf32 => 16,
+    }
+  }
+}
+
+/// The pixel layout of decoded images
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+  /// 8 bits per channel RGBA
+  #[napi(value = "rgba")]
+  Rgba,
+  /// 16 bits per channel RGBA, in little-endian byte order
+  #[napi(value = "rgba16le")]
+  Rgba16le,
+  /// 16 bits per channel RGBA, in big-endian byte order
+  #[napi(value = "rgba16be")]
+  Rgba16be,
+  /// 32-bit float per channel RGBA in the range 0-1, in the platform byte order like a `Float32Array`
+  #[napi(value = "rgbaf32")]
+  Rgbaf32,
+  /// 10 bits per channel RGB packed into a big-endian 32-bit word, with the top 2 bits unused
+  #[napi(value = "r210")]
+  R210,
+  /// 10 bits per channel RGB and 2-bit alpha packed into a little-endian 32-bit word, with alpha in the top 2 bits
+  #[napi(value = "a2r10g10b10")]
+  A2r10g10b10,
+}
+impl OutputFormat {
+  pub fn bytes_per_pixel(&self) -> usize {
+    match self {
+      OutputFormat::Rgba | OutputFormat::R210 | OutputFormat::A2r10g10b10 => 4,
+      OutputFormat::Rgba16le | OutputFormat::Rgba16be => 8,
+      OutputFormat::Rgbaf32 => 16,
+    }
+  }
+}
+
+/// The pixel layout of a key being combined with a fill
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyFormat {
+  /// 8-bit greyscale, the key being the value of each pixel
+  #[napi(value = "luma")]
+  Luma,
+  /// 8 bits per channel RGB, the key being the luma of each pixel
+  #[napi(value = "rgb")]
+  Rgb,
+  /// 8 bits per channel RGBA, the key being the alpha of each pixel
+  #[napi(value = "rgba")]
+  Rgba,
+}
+impl KeyFormat {
+  pub fn bytes_per_pixel(&self) -> usize {
+    match self {
+      KeyFormat::Luma => 1,
+      KeyFormat::Rgb => 3,
+      KeyFormat::Rgba => 4,
+    }
+  }
+}
+
+/// The pixel layout of images being analysed
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum AnalyseFormat {
+  /// ATEM YUVA422, either flat or RLE compressed
+  #[napi(value = "yuva422")]
+  Yuva422,
+  /// 8 bits per channel RGBA
+  #[napi(value = "rgba")]
+  Rgba,
+  /// 16 bits per channel RGBA, in little-endian byte order
+  #[napi(value = "rgba16le")]
+  Rgba16le,
+  /// 16 bits per channel RGBA, in big-endian byte order
+  #[napi(value = "rgba16be")]
+  Rgba16be,
+  /// 32-bit float per channel RGBA in the range 0-1, in the platform byte order like a `Float32Array`
+  #[napi(value = "rgbaf32")]
+  Rgbaf32,
+}
+
+/// The pixel layout of images being compared
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum StillFormat {
+  /// ATEM YUVA422, either flat or RLE compressed
+  #[napi(value = "yuva422")]
+  Yuva422,
+  /// 8 bits per channel RGBA
+  #[napi(value = "rgba")]
+  Rgba,
+}
+
+/// The scopes which can be rendered from an image
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScopeKind {
+  /// A luma waveform
+  #[napi(value = "waveform")]
+  Waveform,
+  /// Waveforms of the red, green and blue components, side by side
+  #[napi(value = "parade")]
+  Parade,
+  /// A plot of the chroma, with targets at the primary and secondary colours
+  #[napi(value = "vectorscope")]
+  Vectorscope,
+  /// A histogram of the luma codes
+  #[napi(value = "histogram")]
+  Histogram,
+  /// Overlaid histograms of the red, green and blue components
+  #[napi(value = "rgbHistogram")]
+  RgbHistogram,
+}
+
+/// How the colour values of float pixels are encoded. Alpha is always linear
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransferFunction {
+  /// The values are already gamma encoded, as with 8-bit images
+  #[napi(value = "gamma")]
+  Gamma,
+  /// The values are linear light, and get converted with the BT.709 transfer function
+  #[napi(value = "linear")]
+  Linear,
+}
+
+/// The pattern used to dither values when rounding them to fewer bits. The pattern only depends on the position of each
+/// pixel, so the output is always the same for the same input
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dither {
+  /// An 8x8 ordered Bayer matrix
+  #[napi(value = "bayer")]
+  Bayer,
+  /// A 32x32 blue noise threshold matrix, which is less visible than the Bayer pattern
+  #[napi(value = "blueNoise")]
+  BlueNoise,
+}
+
+/// The arithmetic used for converting 8-bit RGBA
+///
+/// `fixed` uses integer arithmetic, which gives the same output on every architecture, so is suited to comparing hashes
+/// of images encoded on different machines. Its output differs from `float` by at most 1 code
+///
+/// `javascript` encodes exactly as the JavaScript converter of atem-connection, so that stills have the same hash
+/// whether or not this library is loaded. Like atem-connection it only encodes limited range `rgba` pixels, so
+/// encoding full range video or deeper input with it is an error, while decoding with it is the same as `float`
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Arithmetic {
+  /// 32-bit float SIMD, using the widest vectors the CPU supports
+  #[napi(value = "float")]
+  Float,
+  /// Integer fixed-point, which is bit-exact on every architecture
+  #[napi(value = "fixed")]
+  Fixed,
+  /// Double precision, reproducing atem-connection
+  #[napi(value = "javascript")]
+  Javascript,
+}
+
+/// The range of 10-bit codes used for a signal
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum VideoRange {
+  /// Studio range, with luma and alpha from 64 to 940 and chroma from 64 to 960
+  #[napi(value = "limited")]
+  Limited,
+  /// Every code from 0 to 1023
+  #[napi(value = "full")]
+  Full,
+}
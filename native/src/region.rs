@@ -0,0 +1,332 @@
+use crate::convert::{check_arithmetic, encode_row, RowLayout, MAX_BYTES_PER_PIXEL};
+use crate::error::{Error, Result};
+use crate::options::EncodeFormat;
+use crate::yuv_constants::YuvConstantsSimd;
+
+/// The bits of a sample holding the alpha and luma, leaving the chroma
+const ALPHA_LUMA_MASK: u32 = 0x3ff003ff;
+/// The bits of a sample holding the chroma
+const CHROMA_MASK: u32 = 0x000ffc00;
+
+/// Convert an image, and write it over a rectangle of an existing flat YUVA422 image
+///
+/// Pairs of pixels share their chroma, which is taken from the first pixel of the pair. When the region starts or
+/// ends halfway through a pair, only the components belonging to the pixels inside the region get replaced.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn rgba_to_yuva_422_region(
+  constants: &YuvConstantsSimd,
+  target_width: u32,
+  target_height: u32,
+  target: &mut [u8],
+  x: u32,
+  y: u32,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  input_format: EncodeFormat,
+  input_layout: RowLayout,
+) -> Result<()> {
+  if target.len() != target_width as usize * target_height as usize * 4 {
+    return Err(Error::from_reason("Target buffer has incorrect length"));
+  }
+  if u64::from(x) + u64::from(width) > u64::from(target_width)
+    || u64::from(y) + u64::from(height) > u64::from(target_height)
+  {
+    return Err(Error::from_reason("Region is outside of the target image"));
+  }
+  if !input_layout.check_len(height, input.len()) {
+    return Err(Error::from_reason("Input buffer has incorrect length"));
+  }
+  check_arithmetic(constants, input_format.format, input_format.arithmetic)?;
+
+  let target_row_bytes = target_width as usize * 4;
+  for row in 0..height as usize {
+    let input_start = row * input_layout.stride;
+    let input_row = &input[input_start..input_start + input_layout.row_bytes];
+
+    let target_y = y as usize + row;
+    let target_start = target_y * target_row_bytes;
+    let target_row = &mut target[target_start..target_start + target_row_bytes];
+
+    encode_row_span(
+      constants,
+      x as usize,
+      target_y,
+      input_row,
+      input_format,
+      target_row,
+    );
+  }
+
+  Ok(())
+}
+
+/// Encode a span of pixels starting at `x`, into a full row `y` of the target
+fn encode_row_span(
+  constants: &YuvConstantsSimd,
+  x: usize,
+  y: usize,
+  input: &[u8],
+  input_format: EncodeFormat,
+  target_row: &mut [u8],
+) {
+  let bytes_per_pixel = input_format.format.bytes_per_pixel();
+  let mut input = input;
+  let mut x = x;
+
+  if x % 2 == 1 && !input.is_empty() {
+    // The first pixel is the second of a pair, whose chroma belongs to the pixel outside of the region
+    let [_, sample2] =
+      encode_single_pixel(constants, x - 1, y, &input[..bytes_per_pixel], input_format);
+    merge_sample(&mut target_row[x * 4..x * 4 + 4], sample2, ALPHA_LUMA_MASK);
+
+    input = &input[bytes_per_pixel..];
+    x += 1;
+  }
+
+  let pair_count = input.len() / bytes_per_pixel / 2;
+  let pairs_len = pair_count * bytes_per_pixel * 2;
+  let batches_len = pairs_len / (bytes_per_pixel * 8) * (bytes_per_pixel * 8);
+
+  // Whole batches can be written directly
+  let batches_width = batches_len / bytes_per_pixel;
+  encode_row(
+    constants,
+    input_format,
+    x,
+    y,
+    &input[..batches_len],
+    &mut target_row[x * 4..(x + batches_width) * 4],
+  );
+  x += batches_width;
+
+  // The remaining pairs are converted as a padded batch
+  let remainder = &input[batches_len..pairs_len];
+  if !remainder.is_empty() {
+    let mut scratch_input = [0; 8 * MAX_BYTES_PER_PIXEL];
+    scratch_input[..remainder.len()].copy_from_slice(remainder);
+    let mut scratch_output = [0; 32];
+    encode_row(
+      constants,
+      input_format,
+      x,
+      y,
+      &scratch_input[..bytes_per_pixel * 8],
+      &mut scratch_output,
+    );
+
+    let remainder_width = remainder.len() / bytes_per_pixel;
+    target_row[x * 4..(x + remainder_width) * 4]
+      .copy_from_slice(&scratch_output[..remainder_width * 4]);
+    x += remainder_width;
+  }
+
+  if input.len() > pairs_len {
+    // The last pixel is the first of a pair, so provides the chroma for the pixel outside of the region
+    let [sample1, sample2] =
+      encode_single_pixel(constants, x, y, &input[pairs_len..], input_format);
+    target_row[x * 4..x * 4 + 4].copy_from_slice(&sample1.to_be_bytes());
+    merge_sample(&mut target_row[x * 4 + 4..x * 4 + 8], sample2, CHROMA_MASK);
+  }
+}
+
+/// Encode a pair made of the same pixel twice, as if the pair started at `x`
+fn encode_single_pixel(
+  constants: &YuvConstantsSimd,
+  x: usize,
+  y: usize,
+  pixel: &[u8],
+  input_format: EncodeFormat,
+) -> [u32; 2] {
+  let bytes_per_pixel = input_format.format.bytes_per_pixel();
+  let mut scratch_input = [0; 8 * MAX_BYTES_PER_PIXEL];
+  scratch_input[..bytes_per_pixel].copy_from_slice(pixel);
+  scratch_input[bytes_per_pixel..bytes_per_pixel * 2].copy_from_slice(pixel);
+  let mut scratch_output = [0; 32];
+  encode_row(
+    constants,
+    input_format,
+    x,
+    y,
+    &scratch_input[..bytes_per_pixel * 8],
+    &mut scratch_output,
+  );
+
+  [
+    u32::from_be_bytes(scratch_output[0..4].try_into().unwrap()),
+    u32::from_be_bytes(scratch_output[4..8].try_into().unwrap()),
+  ]
+}
+
+fn merge_sample(target: &mut [u8], sample: u32, mask: u32) {
+  let existing = u32::from_be_bytes(target[0..4].try_into().unwrap());
+  let merged = (existing & !mask) | (sample & mask);
+  target.copy_from_slice(&merged.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::convert::rgba_to_yuva_422;
+  use crate::options::{Dither, EncodeOptions, InputFormat};
+
+  fn encode_full(
+    constants: &YuvConstantsSimd,
+    width: u32,
+    height: u32,
+    input: &[u8],
+    input_format: EncodeFormat,
+  ) -> Vec<u8> {
+    let mut output = vec![0; (width * height * 4) as usize];
+    let layout = RowLayout::new(width, input_format.format.bytes_per_pixel(), None).unwrap();
+    rgba_to_yuva_422(
+      constants,
+      width,
+      height,
+      input,
+      input_format,
+      layout,
+      &mut output,
+    )
+    .unwrap();
+    output
+  }
+
+  fn check_region(x: u32, y: u32, width: u32, height: u32) {
+    check_region_with_format(
+      EncodeOptions::default().encode_format(),
+      x,
+      y,
+      width,
+      height,
+    );
+  }
+
+  fn check_region_with_format(input_format: EncodeFormat, x: u32, y: u32, width: u32, height: u32) {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (target_width, target_height) = (32, 4);
+    let bytes_per_pixel = input_format.format.bytes_per_pixel();
+
+    let background: Vec<u8> = (0..target_width as usize * target_height as usize * bytes_per_pixel)
+      .map(|i| (i * 37 % 256) as u8)
+      .collect();
+    let patch: Vec<u8> = (0..width as usize * height as usize * bytes_per_pixel)
+      .map(|i| (i * 91 % 256) as u8)
+      .collect();
+
+    let mut composited = background.clone();
+    let patch_row_bytes = width as usize * bytes_per_pixel;
+    for row in 0..height as usize {
+      let start = ((y as usize + row) * target_width as usize + x as usize) * bytes_per_pixel;
+      let patch_start = row * patch_row_bytes;
+      composited[start..start + patch_row_bytes]
+        .copy_from_slice(&patch[patch_start..patch_start + patch_row_bytes]);
+    }
+
+    let mut target = encode_full(
+      &constants,
+      target_width,
+      target_height,
+      &background,
+      input_format,
+    );
+    let layout = RowLayout::new(width, bytes_per_pixel, None).unwrap();
+    rgba_to_yuva_422_region(
+      &constants,
+      target_width,
+      target_height,
+      &mut target,
+      x,
+      y,
+      width,
+      height,
+      &patch,
+      input_format,
+      layout,
+    )
+    .unwrap();
+
+    assert_eq!(
+      target,
+      encode_full(
+        &constants,
+        target_width,
+        target_height,
+        &composited,
+        input_format
+      )
+    );
+  }
+
+  #[test]
+  fn test_aligned_region() {
+    check_region(8, 1, 16, 2);
+  }
+
+  #[test]
+  fn test_unaligned_region() {
+    check_region(3, 0, 13, 4);
+    check_region(5, 2, 2, 1);
+    check_region(1, 1, 1, 1);
+    check_region(30, 3, 2, 1);
+  }
+
+  #[test]
+  fn test_dithered_region() {
+    // The dither pattern follows the position in the target image, not in the region
+    for dither in [Dither::Bayer, Dither::BlueNoise] {
+      let input_format = EncodeOptions {
+        format: Some(InputFormat::Rgba16le),
+        dither: Some(dither),
+        ..Default::default()
+      }
+      .encode_format();
+
+      check_region_with_format(input_format, 8, 1, 16, 2);
+      check_region_with_format(input_format, 3, 0, 13, 4);
+      check_region_with_format(input_format, 1, 1, 1, 1);
+    }
+  }
+
+  #[test]
+  fn test_region_outside_image() {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let mut target = vec![0; 32 * 4 * 4];
+    let layout = RowLayout::new(4, 4, None).unwrap();
+    let result = rgba_to_yuva_422_region(
+      &constants,
+      32,
+      4,
+      &mut target,
+      30,
+      0,
+      4,
+      1,
+      &[0; 16],
+      EncodeOptions::default().encode_format(),
+      layout,
+    );
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn test_target_length_does_not_overflow() {
+    // 65536 * 16384 * 4 wraps to 0 in u32, which an empty target would match
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let layout = RowLayout::new(1, 4, None).unwrap();
+    let result = rgba_to_yuva_422_region(
+      &constants,
+      65536,
+      16384,
+      &mut [],
+      0,
+      0,
+      1,
+      1,
+      &[0; 4],
+      EncodeOptions::default().encode_format(),
+      layout,
+    );
+    assert!(result.is_err());
+  }
+}
@@ -1,99 +1,471 @@
+use crate::dither::DitherOffsets;
+use crate::options::{KeyFormat, KeyTransform};
+use crate::transfer::linear_to_gamma_simd;
 use crate::yuv_constants::YuvConstantsSimd;
-use std::simd::{num::SimdFloat, num::SimdUint, Simd};
+use std::simd::{num::SimdFloat, num::SimdUint, LaneCount, Simd, StdFloat, SupportedLaneCount};
+
+/// The components of the pairs of pixels converted in each batch, 4 pairs unless a wider kernel is used
+pub struct PixelPairs<const N: usize = 4>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  pub r1: Simd<f32, N>,
+  pub g1: Simd<f32, N>,
+  pub b1: Simd<f32, N>,
+  pub a1: Simd<f32, N>,
+
+  pub r2: Simd<f32, N>,
+  pub g2: Simd<f32, N>,
+  pub b2: Simd<f32, N>,
+  pub a2: Simd<f32, N>,
+}
+
+/// How the components of a batch of pixels are scaled and rounded, which depends on the depth of the input
+#[derive(Clone, Copy, PartialEq)]
+pub enum InputDepth {
+  /// 8-bit input, scaled and rounded as earlier versions did so that the output stays the same
+  Bits8,
+  /// 16-bit and float input, scaled exactly and rounded to the nearest code
+  Deep,
+}
+
+/// Broadcast a constant to a vector of any width. The constants are the same in every lane
+#[inline(always)]
+pub(crate) fn splat<T, const N: usize>(constant: Simd<T, 4>) -> Simd<T, N>
+where
+  T: std::simd::SimdElement,
+  LaneCount<N>: SupportedLaneCount,
+{
+  Simd::splat(constant[0])
+}
 
 #[inline(always)]
 pub fn rgb_to_yuva422_simd(constants: &YuvConstantsSimd, input: &[u8], target: &mut [u8]) {
-  let vec_r1 = Simd::gather_or_default(input, constants.gather_idx).cast::<f32>();
-  let vec_g1 = Simd::gather_or_default(&input[1..], constants.gather_idx).cast::<f32>();
-  let vec_b1 = Simd::gather_or_default(&input[2..], constants.gather_idx).cast::<f32>();
-  let vec_a1 = Simd::gather_or_default(&input[3..], constants.gather_idx).cast::<f32>();
+  let pixels = PixelPairs {
+    r1: Simd::gather_or_default(input, constants.gather_idx).cast::<f32>(),
+    g1: Simd::gather_or_default(&input[1..], constants.gather_idx).cast::<f32>(),
+    b1: Simd::gather_or_default(&input[2..], constants.gather_idx).cast::<f32>(),
+    a1: Simd::gather_or_default(&input[3..], constants.gather_idx).cast::<f32>(),
 
-  let vec_r2 = Simd::gather_or_default(&input[4..], constants.gather_idx).cast::<f32>();
-  let vec_g2 = Simd::gather_or_default(&input[5..], constants.gather_idx).cast::<f32>();
-  let vec_b2 = Simd::gather_or_default(&input[6..], constants.gather_idx).cast::<f32>();
-  let vec_a2 = Simd::gather_or_default(&input[7..], constants.gather_idx).cast::<f32>();
+    r2: Simd::gather_or_default(&input[4..], constants.gather_idx).cast::<f32>(),
+    g2: Simd::gather_or_default(&input[5..], constants.gather_idx).cast::<f32>(),
+    b2: Simd::gather_or_default(&input[6..], constants.gather_idx).cast::<f32>(),
+    a2: Simd::gather_or_default(&input[7..], constants.gather_idx).cast::<f32>(),
+  };
 
-  let y16a = calc_y(constants, &vec_r1, &vec_g1, &vec_b1);
-  let cb16 = calc_cb(constants, &vec_r1, &vec_g1, &vec_b1);
-  let y16b = calc_y(constants, &vec_r2, &vec_g2, &vec_b2);
-  let cr16 = calc_cr(constants, &vec_r1, &vec_g1, &vec_b1);
+  encode_pixel_pairs(constants, &pixels, InputDepth::Bits8, None, target);
+}
 
-  let a1 = alpha_8_to_10bit(constants, &vec_a1);
-  let a2 = alpha_8_to_10bit(constants, &vec_a2);
+/// Convert 8 pixels of 8-bit RGB fill with the matching pixels of a separate key, giving the same output as
+/// `rgb_to_yuva422_simd` would for RGBA of the fill and the transformed key
+#[inline(always)]
+pub fn fill_key_to_yuva422_simd(
+  constants: &YuvConstantsSimd,
+  fill: &[u8],
+  key: &[u8],
+  key_format: KeyFormat,
+  key_transform: KeyTransform,
+  target: &mut [u8],
+) {
+  let gather = |data: &[u8], idx: Simd<usize, 4>| Simd::gather_or_default(data, idx).cast::<f32>();
+  let fill_idx = Simd::from_array([0, 6, 12, 18]);
+  let [a1, a2] = key_pairs(constants, key, key_format, key_transform);
 
-  let block1 = combine_components(constants, &a1, &cb16, &y16a);
-  let block2 = combine_components(constants, &a2, &cr16, &y16b);
+  let pixels = PixelPairs {
+    r1: gather(fill, fill_idx),
+    g1: gather(&fill[1..], fill_idx),
+    b1: gather(&fill[2..], fill_idx),
+    a1,
+
+    r2: gather(&fill[3..], fill_idx),
+    g2: gather(&fill[4..], fill_idx),
+    b2: gather(&fill[5..], fill_idx),
+    a2,
+  };
+
+  encode_pixel_pairs(constants, &pixels, InputDepth::Bits8, None, target);
+}
 
-  for i in 0..4 {
-    let offset = i * 8;
-    let offset4 = offset + 4;
-    let offset8 = offset + 8;
+/// Combine 8 pixels of 8-bit RGB fill with the matching pixels of a separate key into 8-bit RGBA, for the kernels which
+/// only take RGBA. The transformed key is rounded to 8 bits
+#[inline(always)]
+pub fn fill_key_to_rgba(
+  constants: &YuvConstantsSimd,
+  fill: &[u8],
+  key: &[u8],
+  key_format: KeyFormat,
+  key_transform: KeyTransform,
+) -> [u8; 32] {
+  let keys =
+    key_pairs(constants, key, key_format, key_transform).map(|key| key.round().cast::<u8>());
 
-    target[offset..offset4].copy_from_slice(&block1[i].to_be_bytes());
-    target[offset4..offset8].copy_from_slice(&block2[i].to_be_bytes())
+  let mut rgba = [0; 32];
+  for (i, (fill, pixel)) in fill
+    .chunks_exact(3)
+    .zip(rgba.chunks_exact_mut(4))
+    .enumerate()
+  {
+    pixel[..3].copy_from_slice(fill);
+    pixel[3] = keys[i % 2][i / 2];
   }
+  rgba
 }
 
+/// The transformed key of the first and of the second pixel of each pair in a batch of 8 pixels
 #[inline(always)]
-fn calc_y(
+fn key_pairs(
   constants: &YuvConstantsSimd,
-  r: &Simd<f32, 4>,
-  g: &Simd<f32, 4>,
-  b: &Simd<f32, 4>,
-) -> Simd<f32, 4> {
-  let luma = constants.kr * r + constants.kg * g + constants.kb * b;
+  key: &[u8],
+  key_format: KeyFormat,
+  key_transform: KeyTransform,
+) -> [Simd<f32, 4>; 2] {
+  let gather = |data: &[u8], idx: Simd<usize, 4>| Simd::gather_or_default(data, idx).cast::<f32>();
+  let key_bytes = key_format.bytes_per_pixel();
+  let key_idx = Simd::from_array([0, 2, 4, 6]) * Simd::splat(key_bytes);
+  let key_channel = |pixel: usize| {
+    let key = &key[pixel * key_bytes..];
+    let value = match key_format {
+      KeyFormat::Luma => gather(key, key_idx),
+      KeyFormat::Rgb => {
+        // Measured from green, so that grey keys keep their exact value
+        let (r, g, b) = (
+          gather(key, key_idx),
+          gather(&key[1..], key_idx),
+          gather(&key[2..], key_idx),
+        );
+        g + constants.kr * (r - g) + constants.kb * (b - g)
+      }
+      KeyFormat::Rgba => gather(&key[3..], key_idx),
+    };
+    transform_key(constants, key_transform, value)
+  };
 
-  constants.luma_offset + (constants.luma_scale * luma)
+  [key_channel(0), key_channel(1)]
 }
 
+/// Invert, clip and gain a key in the 0-255 range. The default transform leaves it unchanged
 #[inline(always)]
-fn calc_cb(
+fn transform_key(
   constants: &YuvConstantsSimd,
-  r: &Simd<f32, 4>,
-  g: &Simd<f32, 4>,
-  b: &Simd<f32, 4>,
+  transform: KeyTransform,
+  key: Simd<f32, 4>,
 ) -> Simd<f32, 4> {
-  let val = constants.kr_o_kb_i * r + constants.kg_o_kb_i * g + b;
+  let key = if transform.invert {
+    constants.splat255f - key
+  } else {
+    key
+  };
+
+  ((key - Simd::splat(transform.clip)) * Simd::splat(transform.gain))
+    .simd_clamp(constants.splat0f, constants.splat255f)
+}
 
-  constants.cb_cr_offset + (constants.half_cb_cr_scale * val)
+/// Convert `2 * N` pixels of 8-bit RGBA, giving the same output as `rgb_to_yuva422_simd`
+///
+/// Whole pixels are loaded and split into pairs with shuffles, rather than gathering each channel, so this is only
+/// fast when compiled for wide vectors
+#[inline(always)]
+pub fn rgb_to_yuva422_lanes<const N: usize>(
+  constants: &YuvConstantsSimd,
+  input: &[u8],
+  target: &mut [u8],
+) where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let low: Simd<u32, N> = load_u32_le(&input[..N * 4]);
+  let high: Simd<u32, N> = load_u32_le(&input[N * 4..N * 8]);
+  let (first, second) = low.deinterleave(high);
+
+  let channel = |pixels: Simd<u32, N>, shift: u32| {
+    ((pixels >> Simd::splat(shift)) & Simd::splat(0xff)).cast::<f32>()
+  };
+  let pixels = PixelPairs {
+    r1: channel(first, 0),
+    g1: channel(first, 8),
+    b1: channel(first, 16),
+    a1: channel(first, 24),
+
+    r2: channel(second, 0),
+    g2: channel(second, 8),
+    b2: channel(second, 16),
+    a2: channel(second, 24),
+  };
+
+  encode_pixel_pairs(constants, &pixels, InputDepth::Bits8, None, target);
 }
 
+/// Load little-endian 32-bit words
 #[inline(always)]
-fn calc_cr(
+fn load_u32_le<const N: usize>(input: &[u8]) -> Simd<u32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  Simd::from_array(std::array::from_fn(|i| {
+    u32::from_le_bytes(input[i * 4..i * 4 + 4].try_into().unwrap())
+  }))
+}
+
+/// Convert 8 pixels of 16-bit per channel RGBA, using the full precision of the 10-bit output
+#[inline(always)]
+pub fn rgb16_to_yuva422_simd(
   constants: &YuvConstantsSimd,
-  r: &Simd<f32, 4>,
-  g: &Simd<f32, 4>,
-  b: &Simd<f32, 4>,
+  input: &[u8],
+  big_endian: bool,
+  dither: Option<&DitherOffsets>,
+  target: &mut [u8],
+) {
+  let pixels = PixelPairs {
+    r1: gather_u16(constants, input, 0, big_endian),
+    g1: gather_u16(constants, input, 2, big_endian),
+    b1: gather_u16(constants, input, 4, big_endian),
+    a1: gather_u16(constants, input, 6, big_endian),
+
+    r2: gather_u16(constants, input, 8, big_endian),
+    g2: gather_u16(constants, input, 10, big_endian),
+    b2: gather_u16(constants, input, 12, big_endian),
+    a2: gather_u16(constants, input, 14, big_endian),
+  };
+
+  encode_pixel_pairs(constants, &pixels, InputDepth::Deep, dither, target);
+}
+
+/// Gather a 16-bit channel from each pair, scaled to the 0-255 range of 8-bit input
+#[inline(always)]
+fn gather_u16(
+  constants: &YuvConstantsSimd,
+  input: &[u8],
+  offset: usize,
+  big_endian: bool,
 ) -> Simd<f32, 4> {
-  let val = r + constants.kg_o_kr_i * g + constants.kb_o_kr_i * b;
+  let (high_offset, low_offset) = if big_endian {
+    (offset, offset + 1)
+  } else {
+    (offset + 1, offset)
+  };
+
+  let high = Simd::gather_or_default(&input[high_offset..], constants.gather_idx_16).cast::<u32>();
+  let low = Simd::gather_or_default(&input[low_offset..], constants.gather_idx_16).cast::<u32>();
+
+  ((high << constants.shift_8) | low).cast::<f32>() * constants.scale_16bit
+}
+
+/// Convert 8 pixels of 32-bit float RGBA, clamping the values to the 0-1 range
+///
+/// When `linear` is set, the colour values are gamma encoded before conversion
+#[inline(always)]
+pub fn rgbf32_to_yuva422_simd(
+  constants: &YuvConstantsSimd,
+  input: &[u8],
+  linear: bool,
+  dither: Option<&DitherOffsets>,
+  target: &mut [u8],
+) {
+  let mut values = [0.0; 32];
+  for (value, bytes) in values.iter_mut().zip(input.chunks_exact(4)) {
+    *value = f32::from_ne_bytes(bytes.try_into().unwrap());
+  }
 
-  constants.cb_cr_offset + (constants.half_cb_cr_scale * val)
+  let pixels = PixelPairs {
+    r1: gather_f32(constants, &values[0..], linear),
+    g1: gather_f32(constants, &values[1..], linear),
+    b1: gather_f32(constants, &values[2..], linear),
+    a1: gather_f32(constants, &values[3..], false),
+
+    r2: gather_f32(constants, &values[4..], linear),
+    g2: gather_f32(constants, &values[5..], linear),
+    b2: gather_f32(constants, &values[6..], linear),
+    a2: gather_f32(constants, &values[7..], false),
+  };
+
+  encode_pixel_pairs(constants, &pixels, InputDepth::Deep, dither, target);
+}
+
+/// Gather a float channel from each pair, clamped and scaled to the 0-255 range of 8-bit input
+#[inline(always)]
+fn gather_f32(constants: &YuvConstantsSimd, values: &[f32], linear: bool) -> Simd<f32, 4> {
+  // Clamping with max then min turns NaN into 0
+  let mut channel = Simd::gather_or_default(values, constants.gather_idx)
+    .simd_max(constants.splat0f)
+    .simd_min(constants.splat1f);
+  if linear {
+    channel = linear_to_gamma_simd(channel);
+  }
+
+  channel * constants.splat255f
+}
+
+/// Convert the components of the pairs of pixels, and write the YUVA422 samples
+///
+/// Deep input, and full range components of 8-bit input, are rounded to the nearest code instead of truncated, after
+/// adding any `dither` offsets
+#[inline(always)]
+pub fn encode_pixel_pairs<const N: usize>(
+  constants: &YuvConstantsSimd,
+  pixels: &PixelPairs<N>,
+  depth: InputDepth,
+  dither: Option<&[Simd<f32, N>; 2]>,
+  target: &mut [u8],
+) where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let (luma_scale, half_cb_cr_scale, round_video, round_alpha) = match depth {
+    InputDepth::Bits8 => (
+      splat(constants.luma_scale),
+      splat(constants.half_cb_cr_scale),
+      constants.round_8bit_video,
+      constants.round_8bit_alpha,
+    ),
+    InputDepth::Deep => (
+      splat(constants.deep_luma_scale),
+      splat(constants.deep_half_cb_cr_scale),
+      true,
+      true,
+    ),
+  };
+
+  let mut y16a = calc_y(constants, luma_scale, &pixels.r1, &pixels.g1, &pixels.b1);
+  let mut cb16 = calc_cb(
+    constants,
+    half_cb_cr_scale,
+    &pixels.r1,
+    &pixels.g1,
+    &pixels.b1,
+  );
+  let mut y16b = calc_y(constants, luma_scale, &pixels.r2, &pixels.g2, &pixels.b2);
+  let mut cr16 = calc_cr(
+    constants,
+    half_cb_cr_scale,
+    &pixels.r1,
+    &pixels.g1,
+    &pixels.b1,
+  );
+
+  let mut a1 = alpha_8_to_10bit(constants, &pixels.a1);
+  let mut a2 = alpha_8_to_10bit(constants, &pixels.a2);
+
+  if let Some([first, second]) = dither {
+    // Chroma comes from the first pixel of each pair. Only deep input is dithered, which is always rounded
+    y16a += first;
+    cb16 += first;
+    y16b += second;
+    cr16 += first;
+    a1 += first;
+    a2 += second;
+  }
+
+  if round_video {
+    y16a = y16a.round();
+    cb16 = cb16.round();
+    y16b = y16b.round();
+    cr16 = cr16.round();
+  }
+  if round_alpha {
+    a1 = a1.round();
+    a2 = a2.round();
+  }
+
+  // Full range values can round or dither past the largest code
+  let max_code = splat(constants.splat1023f);
+  y16a = y16a.simd_min(max_code);
+  cb16 = cb16.simd_min(max_code);
+  y16b = y16b.simd_min(max_code);
+  cr16 = cr16.simd_min(max_code);
+  a1 = a1.simd_min(max_code);
+  a2 = a2.simd_min(max_code);
+
+  let block1 = combine_components(constants, &a1, &cb16, &y16a);
+  let block2 = combine_components(constants, &a2, &cr16, &y16b);
+
+  // Each sample is the first pixel's word followed by the second's
+  let (low, high) = block1.interleave(block2);
+  let words = low.to_array().into_iter().chain(high.to_array());
+  for (bytes, word) in target[..N * 8].chunks_exact_mut(4).zip(words) {
+    bytes.copy_from_slice(&word.to_be_bytes());
+  }
 }
 
 #[inline(always)]
-fn alpha_8_to_10bit(constants: &YuvConstantsSimd, val: &Simd<f32, 4>) -> Simd<f32, 4> {
-  constants.alpha_offset + (val * constants.alpha_scale)
+fn calc_y<const N: usize>(
+  constants: &YuvConstantsSimd,
+  luma_scale: Simd<f32, N>,
+  r: &Simd<f32, N>,
+  g: &Simd<f32, N>,
+  b: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let luma = splat(constants.kr) * r + splat(constants.kg) * g + splat(constants.kb) * b;
+
+  splat(constants.luma_offset) + (luma_scale * luma)
 }
 
 #[inline(always)]
-fn combine_components(
+fn calc_cb<const N: usize>(
   constants: &YuvConstantsSimd,
-  a: &Simd<f32, 4>,
-  uv: &Simd<f32, 4>,
-  y: &Simd<f32, 4>,
-) -> Simd<u32, 4> {
+  half_cb_cr_scale: Simd<f32, N>,
+  r: &Simd<f32, N>,
+  g: &Simd<f32, N>,
+  b: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let val = splat(constants.kr_o_kb_i) * r + splat(constants.kg_o_kb_i) * g + b;
+
+  splat(constants.cb_cr_offset) + (half_cb_cr_scale * val)
+}
+
+#[inline(always)]
+fn calc_cr<const N: usize>(
+  constants: &YuvConstantsSimd,
+  half_cb_cr_scale: Simd<f32, N>,
+  r: &Simd<f32, N>,
+  g: &Simd<f32, N>,
+  b: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let val = r + splat(constants.kg_o_kr_i) * g + splat(constants.kb_o_kr_i) * b;
+
+  splat(constants.cb_cr_offset) + (half_cb_cr_scale * val)
+}
+
+#[inline(always)]
+fn alpha_8_to_10bit<const N: usize>(
+  constants: &YuvConstantsSimd,
+  val: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  splat(constants.alpha_offset) + (val * splat(constants.alpha_scale))
+}
+
+#[inline(always)]
+fn combine_components<const N: usize>(
+  constants: &YuvConstantsSimd,
+  a: &Simd<f32, N>,
+  uv: &Simd<f32, N>,
+  y: &Simd<f32, N>,
+) -> Simd<u32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
   // TODO - round these values?
   let a2 = a.cast::<u32>();
   let uv2 = uv.cast::<u32>();
   let y2 = y.cast::<u32>();
 
-  (a2 << constants.shift_20) + (uv2 << constants.shift_10) + y2
+  (a2 << splat(constants.shift_20)) + (uv2 << splat(constants.shift_10)) + y2
 }
 
 #[cfg(test)]
 mod tests {
   // Note this useful idiom: importing names from outer (for mod tests) scope.
   use super::*;
+  use crate::options::VideoRange;
 
   fn rgb_to_yuv422_single(input: &[u8; 8]) -> [u8; 8] {
     let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
@@ -190,4 +562,298 @@ mod tests {
     let output = [37, 236, 81, 197, 36, 173, 30, 74];
     assert_eq!(rgb_to_yuv422_single(&input), output);
   }
+
+  fn rgb16_to_yuv422_single(input: &[u16; 8]) -> [u8; 8] {
+    let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let mut outputs = Vec::new();
+    for big_endian in [false, true] {
+      let mut input_ext = [0; 64];
+      for (i, value) in input.iter().cycle().take(32).enumerate() {
+        let bytes = if big_endian {
+          value.to_be_bytes()
+        } else {
+          value.to_le_bytes()
+        };
+        input_ext[i * 2..i * 2 + 2].copy_from_slice(&bytes);
+      }
+
+      let mut target = [0; 32];
+      rgb16_to_yuva422_simd(&bt601_constants, &input_ext, big_endian, None, &mut target);
+
+      assert_eq!(&target[0..8], &target[8..16]);
+      assert_eq!(&target[0..8], &target[16..24]);
+      assert_eq!(&target[0..8], &target[24..32]);
+
+      outputs.push(target);
+    }
+
+    assert_eq!(outputs[0], outputs[1], "byte orders differ");
+
+    let mut target_trimmed = [0; 8];
+    target_trimmed.copy_from_slice(&outputs[0][0..8]);
+    target_trimmed
+  }
+
+  // Expected values are the 10-bit codes nearest to the exact conversion, computed in double precision
+
+  #[test]
+  fn test_16bit_black() {
+    let input = [0, 0, 0, 0, 0, 0, 0, 0];
+    let output = [4, 8, 0, 64, 4, 8, 0, 64];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_white() {
+    let input = [65535, 65535, 65535, 65535, 65535, 65535, 65535, 65535];
+    let output = [58, 200, 3, 172, 58, 200, 3, 172];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_one() {
+    let input = [18605, 40646, 2907, 28881, 33227, 2670, 20179, 3587];
+    let output = [28, 36, 197, 206, 7, 6, 76, 248];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_two() {
+    let input = [60912, 59872, 38826, 29404, 40604, 47419, 33847, 55087];
+    let output = [28, 149, 187, 68, 50, 8, 126, 138];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_three() {
+    let input = [11264, 45584, 64787, 55459, 22579, 38406, 5768, 37067];
+    let output = [50, 91, 74, 53, 34, 244, 1, 208];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_four() {
+    let input = [10958, 810, 48898, 30775, 64210, 20251, 40484, 39877];
+    let output = [29, 188, 196, 189, 37, 88, 66, 29];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_five() {
+    let input = [41472, 60408, 59453, 8615, 21529, 63209, 1712, 58344];
+    let output = [11, 56, 151, 26, 52, 198, 2, 137];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_six() {
+    let input = [64426, 1833, 62269, 16113, 59541, 10243, 65435, 2982];
+    let output = [17, 124, 53, 175, 6, 141, 165, 226];
+    assert_eq!(rgb16_to_yuv422_single(&input), output);
+  }
+
+  #[test]
+  fn test_16bit_precision() {
+    // Values between two 8-bit steps must produce distinct 10-bit luma codes
+    let luma_codes: Vec<u32> = [0x8000, 0x8040, 0x8080, 0x80c0]
+      .iter()
+      .map(|&v| {
+        let output = rgb16_to_yuv422_single(&[v, v, v, 0xffff, v, v, v, 0xffff]);
+        u32::from_be_bytes(output[0..4].try_into().unwrap()) & 0x3ff
+      })
+      .collect();
+
+    assert!(luma_codes.windows(2).all(|w| w[0] < w[1]), "{luma_codes:?}");
+  }
+
+  fn rgbf32_to_yuv422_single(input: &[f32; 8], linear: bool) -> [u8; 8] {
+    let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let mut input_ext = [0; 128];
+    for (i, value) in input.iter().cycle().take(32).enumerate() {
+      input_ext[i * 4..i * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    let mut target = [0; 32];
+    rgbf32_to_yuva422_simd(&bt601_constants, &input_ext, linear, None, &mut target);
+
+    assert_eq!(&target[0..8], &target[8..16]);
+    assert_eq!(&target[0..8], &target[16..24]);
+    assert_eq!(&target[0..8], &target[24..32]);
+
+    target[0..8].try_into().unwrap()
+  }
+
+  #[test]
+  fn test_f32_matches_16bit() {
+    for pixels in [
+      [0, 0, 0, 0, 0, 0, 0, 0],
+      [255, 255, 255, 255, 255, 255, 255, 255],
+      [28, 69, 148, 247, 117, 221, 18, 95],
+      [161, 62, 67, 203, 195, 251, 198, 239],
+      [189, 218, 98, 133, 76, 128, 210, 222],
+    ] {
+      let input_16 = pixels.map(|v| v as u16 * 257);
+      let input_f32 = pixels.map(|v| v as f32 / 255.0);
+      assert_eq!(
+        rgbf32_to_yuv422_single(&input_f32, false),
+        rgb16_to_yuv422_single(&input_16),
+        "{pixels:?}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_f32_clamping() {
+    let black = rgbf32_to_yuv422_single(&[0.0; 8], false);
+    let white = rgbf32_to_yuv422_single(&[1.0; 8], false);
+
+    assert_eq!(rgbf32_to_yuv422_single(&[-0.5; 8], false), black);
+    assert_eq!(rgbf32_to_yuv422_single(&[f32::NAN; 8], false), black);
+    assert_eq!(
+      rgbf32_to_yuv422_single(&[f32::NEG_INFINITY; 8], true),
+      black
+    );
+    assert_eq!(rgbf32_to_yuv422_single(&[1.5; 8], false), white);
+    assert_eq!(rgbf32_to_yuv422_single(&[f32::INFINITY; 8], true), white);
+  }
+
+  #[test]
+  fn test_f32_linear() {
+    // Linear mid grey is gamma encoded before conversion, while alpha is left alone
+    let linear = rgbf32_to_yuv422_single(&[0.18, 0.18, 0.18, 0.5, 0.18, 0.18, 0.18, 0.5], true);
+    let gamma = 1.099 * 0.18f32.powf(0.45) - 0.099;
+    let encoded =
+      rgbf32_to_yuv422_single(&[gamma, gamma, gamma, 0.5, gamma, gamma, gamma, 0.5], false);
+    assert_eq!(linear, encoded);
+  }
+
+  /// The `[y, cb, cr, a]` codes of a 16-bit pixel
+  fn rgb16_components(constants: &YuvConstantsSimd, pixel: [u16; 4]) -> [u32; 4] {
+    let input = pixel.map(u16::to_le_bytes).concat().repeat(8);
+    let mut target = [0; 32];
+    rgb16_to_yuva422_simd(constants, &input, false, None, &mut target);
+
+    let word1 = u32::from_be_bytes(target[0..4].try_into().unwrap());
+    let word2 = u32::from_be_bytes(target[4..8].try_into().unwrap());
+    [
+      word1 & 0x3ff,
+      (word1 >> 10) & 0x3ff,
+      (word2 >> 10) & 0x3ff,
+      word1 >> 20,
+    ]
+  }
+
+  #[test]
+  fn test_range_extremes() {
+    let limited = YuvConstantsSimd::create(0.299, 0.114);
+    assert_eq!(rgb16_components(&limited, [0, 0, 0, 0]), [64, 512, 512, 64]);
+    assert_eq!(
+      rgb16_components(&limited, [0xffff, 0xffff, 0xffff, 0xffff]),
+      [940, 512, 512, 940]
+    );
+    // Saturated blue and red reach the top of the chroma range
+    assert_eq!(rgb16_components(&limited, [0, 0, 0xffff, 0xffff])[1], 960);
+    assert_eq!(rgb16_components(&limited, [0xffff, 0, 0, 0xffff])[2], 960);
+
+    let full =
+      YuvConstantsSimd::create_with_range(0.299, 0.114, VideoRange::Full, VideoRange::Full);
+    assert_eq!(rgb16_components(&full, [0, 0, 0, 0]), [0, 512, 512, 0]);
+    assert_eq!(
+      rgb16_components(&full, [0xffff, 0xffff, 0xffff, 0xffff]),
+      [1023, 512, 512, 1023]
+    );
+
+    // Saturated chroma must not overflow into the neighbouring component
+    assert_eq!(rgb16_components(&full, [0, 0, 0xffff, 0xffff])[1], 1023);
+    assert_eq!(rgb16_components(&full, [0xffff, 0, 0, 0xffff])[2], 1023);
+
+    // The alpha range is independent of the luma and chroma range
+    let full_alpha =
+      YuvConstantsSimd::create_with_range(0.299, 0.114, VideoRange::Limited, VideoRange::Full);
+    assert_eq!(
+      rgb16_components(&full_alpha, [0, 0, 0, 0]),
+      [64, 512, 512, 0]
+    );
+    assert_eq!(rgb16_components(&full_alpha, [0, 0, 0, 0xffff])[3], 1023);
+  }
+
+  /// The alpha codes of a pair of pixels of grey fill, with the given key values
+  fn fill_key_alpha(key: [u8; 2], key_transform: KeyTransform) -> [u32; 2] {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+    let (fill, key) = ([128; 24], key.repeat(4));
+
+    let mut target = [0; 32];
+    fill_key_to_yuva422_simd(
+      &constants,
+      &fill,
+      &key,
+      KeyFormat::Luma,
+      key_transform,
+      &mut target,
+    );
+    [0, 4].map(|offset| u32::from_be_bytes(target[offset..offset + 4].try_into().unwrap()) >> 20)
+  }
+
+  #[test]
+  fn test_key_transform() {
+    let transform = |invert, clip, gain| KeyTransform { invert, clip, gain };
+    assert_eq!(
+      fill_key_alpha([0, 255], transform(false, 0.0, 1.0)),
+      [64, 940]
+    );
+    assert_eq!(
+      fill_key_alpha([0, 255], transform(true, 0.0, 1.0)),
+      [940, 64]
+    );
+
+    // Clipping makes the key transparent up to the clip level, and the gain scales it from there
+    assert_eq!(
+      fill_key_alpha([100, 102], transform(false, 100.0, 1.0)),
+      [64, 70]
+    );
+    assert_eq!(
+      fill_key_alpha([100, 228], transform(false, 100.0, 2.0)),
+      [64, 940]
+    );
+    assert_eq!(
+      fill_key_alpha([64, 255], transform(false, 0.0, 0.5)),
+      [173, 502]
+    );
+    assert_eq!(
+      fill_key_alpha([55, 200], transform(true, 100.0, 2.0)),
+      [751, 64]
+    );
+  }
+
+  #[test]
+  fn test_fill_key_matches_rgba() {
+    let constants = YuvConstantsSimd::create(0.2126, 0.0722);
+    let rgba: Vec<u8> = (0..32u8).map(|i| i.wrapping_mul(97)).collect();
+    let mut expected = [0; 32];
+    rgb_to_yuva422_simd(&constants, &rgba, &mut expected);
+
+    let fill: Vec<u8> = rgba
+      .chunks_exact(4)
+      .flat_map(|pixel| &pixel[0..3])
+      .copied()
+      .collect();
+    let luma: Vec<u8> = rgba.chunks_exact(4).map(|pixel| pixel[3]).collect();
+    let grey: Vec<u8> = luma.iter().flat_map(|&key| [key; 3]).collect();
+    let identity = KeyTransform {
+      invert: false,
+      clip: 0.0,
+      gain: 1.0,
+    };
+    for (key, key_format) in [
+      (&luma, KeyFormat::Luma),
+      (&grey, KeyFormat::Rgb),
+      (&rgba, KeyFormat::Rgba),
+    ] {
+      let mut target = [0; 32];
+      fill_key_to_yuva422_simd(&constants, &fill, key, key_format, identity, &mut target);
+      assert_eq!(target, expected, "{key_format:?}");
+    }
+  }
 }
@@ -1,12 +1,14 @@
-use napi::JsBufferValue;
+/// The first 8 bytes of a RLE header, which is followed by the big endian number of repeats and then the sample
+const RLE_MARKER: [u8; 8] = [0xfe; 8];
 
+#[derive(Clone)]
 pub(crate) struct RLEDecoder<'a> {
-  input: &'a JsBufferValue,
+  input: &'a [u8],
   read_offset: usize,
   rle_remaining: usize,
 }
 impl<'a> RLEDecoder<'a> {
-  pub fn new(input: &'a JsBufferValue) -> Self {
+  pub fn new(input: &'a [u8]) -> Self {
     RLEDecoder {
       input,
       read_offset: 0,
@@ -15,50 +17,140 @@ impl<'a> RLEDecoder<'a> {
   }
 
   pub fn read_sample(&mut self) -> Option<&'a [u8]> {
-    // Make sure we aren't beyond the end of the input
-    if self.read_offset + 8 > self.input.len() {
-      return None; // No more data to read
-    }
+    loop {
+      // Make sure we aren't beyond the end of the input
+      if self.read_offset + 8 > self.input.len() {
+        return None; // No more data to read
+      }
+
+      // Check for the start of a RLE sequence
+      if self.rle_remaining == 0
+        && self.input.len() >= self.read_offset + 24 // this will take 3 samples
+        && self.input[self.read_offset..self.read_offset + 8] == RLE_MARKER
+      {
+        let count = u64::from_be_bytes(
+          self.input[self.read_offset + 8..self.read_offset + 16]
+            .try_into()
+            .unwrap(),
+        );
 
-    // In the middle of an RLE sequence, so repeat the value
-    if self.rle_remaining > 0 {
-      self.rle_remaining -= 1;
+        self.read_offset += 16; // Move past the RLE header
+
+        if count == 0 {
+          // An empty sequence, so skip its sample too
+          self.read_offset += 8;
+          continue;
+        }
+        self.rle_remaining = usize::try_from(count).unwrap_or(usize::MAX);
+      }
 
-      // If Not the last sample, read the sample without moving the offset
+      // In the middle of an RLE sequence, so repeat the value
       if self.rle_remaining > 0 {
-        return Some(&self.input[self.read_offset..self.read_offset + 8]);
+        self.rle_remaining -= 1;
+
+        // If Not the last sample, read the sample without moving the offset
+        if self.rle_remaining > 0 {
+          return Some(&self.input[self.read_offset..self.read_offset + 8]);
+        }
+
+        // The last sample, handle with the default case
       }
 
-      // The last sample, handle with the default case
-    } else
-    // Check for the start of a RLE sequence
-    if self.input.len() >= self.read_offset + 24 // this will take 3 samples
-      && self.input[self.read_offset] == 0xfe
-      && self.input[self.read_offset + 1] == 0xfe
-      && self.input[self.read_offset + 2] == 0xfe
-      && self.input[self.read_offset + 3] == 0xfe
-      && self.input[self.read_offset + 4] == 0xfe
-      && self.input[self.read_offset + 5] == 0xfe
-      && self.input[self.read_offset + 6] == 0xfe
-      && self.input[self.read_offset + 7] == 0xfe
-    {
-      self.rle_remaining = u64::from_be_bytes(
-        self.input[self.read_offset + 8..self.read_offset + 16]
-          .try_into()
-          .unwrap(),
-      ) as usize
-        - 1; // -1 because we will read the first sample now
-
-      self.read_offset += 16; // Move past the RLE header
-
-      return Some(&self.input[self.read_offset..self.read_offset + 8]);
+      // Read a normal sample
+      let value = &self.input[self.read_offset..self.read_offset + 8];
+
+      self.read_offset += 8; // Move past the sample
+
+      return Some(value);
     }
+  }
+
+  /// Move past a number of samples, without reading each sample of a run. Returns false if the input ran out of data
+  pub fn skip_samples(&mut self, mut count: usize) -> bool {
+    while count > 0 {
+      if self.rle_remaining > 0 {
+        // The last sample of a run is followed by the next sample or header
+        let skipped = count.min(self.rle_remaining);
+        self.rle_remaining -= skipped;
+        if self.rle_remaining == 0 {
+          self.read_offset += 8;
+        }
+        count -= skipped;
+      } else if self.read_sample().is_some() {
+        count -= 1;
+      } else {
+        return false;
+      }
+    }
+
+    true
+  }
+}
 
-    // Read a normal sample
-    let value = &self.input[self.read_offset..self.read_offset + 8];
+/// The smallest run of identical samples worth compressing, as the header takes the space of 2 samples
+const MIN_RLE_RUN: usize = 3;
 
-    self.read_offset += 8; // Move past the sample
+/// Compress a flat YUVA422 image, replacing runs of identical samples with an RLE header followed by the sample
+///
+/// A sample which looks like the start of a header is written as a run of 1, so that it can't be misread
+pub(crate) fn encode_rle(input: &[u8]) -> Vec<u8> {
+  let samples: Vec<&[u8]> = input.chunks_exact(8).collect();
+  let mut output = Vec::with_capacity(input.len());
+
+  let mut start = 0;
+  while start < samples.len() {
+    let run = samples[start..]
+      .iter()
+      .take_while(|&&sample| sample == samples[start])
+      .count();
+
+    if run >= MIN_RLE_RUN || samples[start] == RLE_MARKER {
+      output.extend_from_slice(&RLE_MARKER);
+      output.extend_from_slice(&(run as u64).to_be_bytes());
+      output.extend_from_slice(samples[start]);
+    } else {
+      for sample in &samples[start..start + run] {
+        output.extend_from_slice(sample);
+      }
+    }
+    start += run;
+  }
+
+  output
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(input: &[u8]) -> Vec<u8> {
+    let mut decoder = RLEDecoder::new(input);
+    let mut output = Vec::new();
+    while let Some(sample) = decoder.read_sample() {
+      output.extend_from_slice(sample);
+    }
+    output
+  }
+
+  fn run(count: u64, sample: [u8; 8]) -> Vec<u8> {
+    [RLE_MARKER.as_slice(), &count.to_be_bytes(), &sample].concat()
+  }
+
+  #[test]
+  fn test_run_lengths() {
+    let sample = [1, 2, 3, 4, 5, 6, 7, 8];
+    let next = [9; 8];
+    for count in 0..5 {
+      let input = [run(count, sample), next.to_vec()].concat();
+      let expected = [sample.repeat(count as usize), next.to_vec()].concat();
+      assert_eq!(decode(&input), expected, "{count}");
+    }
+  }
 
-    return Some(value);
+  #[test]
+  fn test_marker_sample_round_trips() {
+    let input = [RLE_MARKER, [0, 0, 0, 0, 0, 0, 0, 2], [3; 8], RLE_MARKER].concat();
+    let compressed = encode_rle(&input);
+    assert_eq!(decode(&compressed), input);
   }
 }
@@ -0,0 +1,496 @@
+use crate::error::{Error, Result};
+use crate::options::ScopeKind;
+use crate::rle::RLEDecoder;
+use crate::yuv_constants::YuvConstantsSimd;
+use crate::yuva422_to_rgba_simd::decode_pixel_pairs_unclamped;
+
+/// The colour of the graticule lines
+const GRATICULE: [u8; 3] = [80, 80, 80];
+/// The colour of a luma trace
+const LUMA_TRACE: [u8; 3] = [255, 255, 255];
+/// The colours of the red, green and blue traces
+const RGB_TRACES: [[u8; 3]; 3] = [[255, 40, 40], [40, 255, 40], [60, 60, 255]];
+/// The nominal percentages marked on a waveform
+const WAVEFORM_LEVELS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+/// The primary and secondary colours marked on a vectorscope, in the order red, magenta, blue, cyan, green, yellow
+const VECTORSCOPE_TARGETS: [[f32; 3]; 6] = [
+  [1.0, 0.0, 0.0],
+  [1.0, 0.0, 1.0],
+  [0.0, 0.0, 1.0],
+  [0.0, 1.0, 1.0],
+  [0.0, 1.0, 0.0],
+  [1.0, 1.0, 0.0],
+];
+
+/// Render a scope of a flat or RLE compressed YUVA422 image, as an 8-bit RGBA image of `scope_width` by `scope_height`
+///
+/// The samples are read directly, without decoding the image to RGB first. `target_saturation` is the saturation of
+/// the colour targets marked on a vectorscope, as a fraction
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn render_scope(
+  constants: &YuvConstantsSimd,
+  kind: ScopeKind,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  scope_width: u32,
+  scope_height: u32,
+  target_saturation: f32,
+  output: &mut [u8],
+) -> Result<()> {
+  if scope_width == 0 || scope_height == 0 {
+    return Err(Error::from_reason("Scope size must not be zero"));
+  }
+  if output.len() != scope_width as usize * scope_height as usize * 4 {
+    return Err(Error::from_reason("Output buffer has incorrect length"));
+  }
+
+  let mut canvas = Canvas {
+    width: scope_width as usize,
+    height: scope_height as usize,
+    pixels: output,
+  };
+  canvas.clear();
+
+  match kind {
+    ScopeKind::Waveform => render_waveform(constants, width, height, input, false, &mut canvas),
+    ScopeKind::Parade => render_waveform(constants, width, height, input, true, &mut canvas),
+    ScopeKind::Vectorscope => render_vectorscope(
+      constants,
+      width,
+      height,
+      input,
+      target_saturation,
+      &mut canvas,
+    ),
+    ScopeKind::Histogram => render_histogram(constants, width, height, input, false, &mut canvas),
+    ScopeKind::RgbHistogram => render_histogram(constants, width, height, input, true, &mut canvas),
+  }
+}
+
+/// Call `f` with the x position of the first pixel, and the 4 samples, of each batch of 8 pixels of the image
+fn for_each_batch<'a>(
+  width: u32,
+  height: u32,
+  input: &'a [u8],
+  mut f: impl FnMut(usize, [&'a [u8]; 4]),
+) -> Result<()> {
+  let mut decoder = RLEDecoder::new(input);
+
+  for _ in 0..height {
+    for x in (0..width as usize).step_by(8) {
+      let samples = [
+        decoder.read_sample(),
+        decoder.read_sample(),
+        decoder.read_sample(),
+        decoder.read_sample(),
+      ];
+      let [Some(sample1), Some(sample2), Some(sample3), Some(sample4)] = samples else {
+        return Err(Error::from_reason(
+          "Input buffer has less data than expected",
+        ));
+      };
+
+      f(x, [sample1, sample2, sample3, sample4]);
+    }
+  }
+
+  Ok(())
+}
+
+/// The two words of a sample, holding the first pixel with Cb and the second pixel with Cr
+fn sample_words(sample: &[u8]) -> [u32; 2] {
+  [
+    u32::from_be_bytes(sample[0..4].try_into().unwrap()),
+    u32::from_be_bytes(sample[4..8].try_into().unwrap()),
+  ]
+}
+
+/// The 10-bit luma code of a colour value in the 0-255 range, without clamping
+fn luma_code(constants: &YuvConstantsSimd, value: f32) -> f32 {
+  value * constants.luma_scale[0] + constants.luma_offset[0]
+}
+
+fn render_waveform(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  parade: bool,
+  canvas: &mut Canvas,
+) -> Result<()> {
+  let section_count = if parade { 3 } else { 1 };
+  let section_width = (canvas.width / section_count).max(1);
+  let mut planes = vec![Density::new(canvas.width, canvas.height); section_count];
+
+  let column =
+    |section: usize, x: usize| section * section_width + x * section_width / width as usize;
+  let last_row = canvas.height - 1;
+  let row = |code: f32| {
+    let code = code.clamp(0.0, 1023.0);
+    ((1023.0 - code) / 1023.0 * last_row as f32).round() as usize
+  };
+
+  for_each_batch(width, height, input, |x, samples| {
+    if parade {
+      let pixels = decode_pixel_pairs_unclamped(
+        constants, samples[0], samples[1], samples[2], samples[3], false,
+      );
+      let channels = [
+        [pixels.r1, pixels.r2],
+        [pixels.g1, pixels.g2],
+        [pixels.b1, pixels.b2],
+      ];
+      for (section, [first, second]) in channels.into_iter().enumerate() {
+        for pair in 0..4 {
+          let pixel_x = x + pair * 2;
+          planes[section].add(
+            column(section, pixel_x),
+            row(luma_code(constants, first[pair])),
+          );
+          planes[section].add(
+            column(section, pixel_x + 1),
+            row(luma_code(constants, second[pair])),
+          );
+        }
+      }
+    } else {
+      for (pair, sample) in samples.iter().enumerate() {
+        for (i, word) in sample_words(sample).into_iter().enumerate() {
+          let pixel_x = x + pair * 2 + i;
+          planes[0].add(column(0, pixel_x), row((word & 0x3ff) as f32));
+        }
+      }
+    }
+  })?;
+
+  for level in WAVEFORM_LEVELS {
+    let y = row(luma_code(constants, level * 255.0));
+    canvas.horizontal_line(y, 0, canvas.width, GRATICULE);
+  }
+  for section in 1..section_count {
+    canvas.vertical_line(section * section_width, 0, canvas.height, GRATICULE);
+  }
+
+  // The number of samples in each column, which would all be in one cell for a flat image
+  let reference = width as f32 * height as f32 / section_width as f32;
+  let colours: &[[u8; 3]] = if parade { &RGB_TRACES } else { &[LUMA_TRACE] };
+  for (plane, &colour) in planes.iter().zip(colours) {
+    canvas.add_density(plane, colour, reference);
+  }
+
+  Ok(())
+}
+
+fn render_vectorscope(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  target_saturation: f32,
+  canvas: &mut Canvas,
+) -> Result<()> {
+  // The chroma codes are mapped onto the largest square which fits, with Cb across and Cr upwards
+  let size = canvas.width.min(canvas.height);
+  let left = (canvas.width - size) / 2;
+  let top = (canvas.height - size) / 2;
+  let position = |cb: f32, cr: f32| {
+    let x = (cb.clamp(0.0, 1023.0) * size as f32 / 1024.0) as usize;
+    let y = ((1023.0 - cr.clamp(0.0, 1023.0)) * size as f32 / 1024.0) as usize;
+    (left + x, top + y)
+  };
+
+  let mut plane = Density::new(canvas.width, canvas.height);
+  for_each_batch(width, height, input, |_, samples| {
+    for sample in samples {
+      let [word1, word2] = sample_words(sample);
+      let (x, y) = position(
+        ((word1 >> 10) & 0x3ff) as f32,
+        ((word2 >> 10) & 0x3ff) as f32,
+      );
+      plane.add(x, y);
+    }
+  })?;
+
+  let (centre_x, centre_y) = position(512.0, 512.0);
+  canvas.horizontal_line(centre_y, left, left + size, GRATICULE);
+  canvas.vertical_line(centre_x, top, top + size, GRATICULE);
+
+  let [kr, kb] = [constants.kr[0], constants.kb[0]];
+  let box_size = (size / 50).max(2);
+  for target in VECTORSCOPE_TARGETS {
+    let [r, g, b] = target.map(|value| value * target_saturation * 255.0);
+    let y = kr * r + (1.0 - kr - kb) * g + kb * b;
+    let cb = 512.0 + constants.half_cb_cr_scale[0] * (b - y) / (1.0 - kb);
+    let cr = 512.0 + constants.half_cb_cr_scale[0] * (r - y) / (1.0 - kr);
+
+    let (x, y) = position(cb, cr);
+    let colour = target.map(|value| (64.0 + value * 128.0) as u8);
+    canvas.square(x, y, box_size, colour);
+  }
+
+  // The number of pairs in each column of the scope, which would all be in one cell for a flat image
+  let reference = width as f32 * height as f32 / 2.0 / size as f32;
+  canvas.add_density(&plane, LUMA_TRACE, reference);
+
+  Ok(())
+}
+
+fn render_histogram(
+  constants: &YuvConstantsSimd,
+  width: u32,
+  height: u32,
+  input: &[u8],
+  rgb: bool,
+  canvas: &mut Canvas,
+) -> Result<()> {
+  let scope_width = canvas.width;
+  let column = |code: f32| code.clamp(0.0, 1023.0) as usize * scope_width / 1024;
+  let mut bins = vec![vec![0u32; canvas.width]; if rgb { 3 } else { 1 }];
+
+  for_each_batch(width, height, input, |_, samples| {
+    if rgb {
+      let pixels = decode_pixel_pairs_unclamped(
+        constants, samples[0], samples[1], samples[2], samples[3], false,
+      );
+      let channels = [
+        [pixels.r1, pixels.r2],
+        [pixels.g1, pixels.g2],
+        [pixels.b1, pixels.b2],
+      ];
+      for (channel, [first, second]) in channels.into_iter().enumerate() {
+        for pair in 0..4 {
+          bins[channel][column(luma_code(constants, first[pair]))] += 1;
+          bins[channel][column(luma_code(constants, second[pair]))] += 1;
+        }
+      }
+    } else {
+      for sample in samples {
+        for word in sample_words(sample) {
+          bins[0][column((word & 0x3ff) as f32)] += 1;
+        }
+      }
+    }
+  })?;
+
+  for level in [0.0, 1.0] {
+    let x = column(luma_code(constants, level * 255.0));
+    canvas.vertical_line(x, 0, canvas.height, GRATICULE);
+  }
+
+  // The bars are scaled to the tallest bin of any channel
+  let peak = bins.iter().flatten().copied().max().unwrap_or(0).max(1);
+  let colours: &[[u8; 3]] = if rgb { &RGB_TRACES } else { &[LUMA_TRACE] };
+  for (channel, &colour) in bins.iter().zip(colours) {
+    for (x, &count) in channel.iter().enumerate() {
+      let bar_height = (count as u64 * canvas.height as u64).div_ceil(peak as u64) as usize;
+      for y in canvas.height - bar_height..canvas.height {
+        canvas.add(x, y, colour, 0.8);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// The number of samples which landed in each cell of a scope
+#[derive(Clone)]
+struct Density {
+  width: usize,
+  counts: Vec<u32>,
+}
+impl Density {
+  fn new(width: usize, height: usize) -> Density {
+    Density {
+      width,
+      counts: vec![0; width * height],
+    }
+  }
+
+  fn add(&mut self, x: usize, y: usize) {
+    if x < self.width {
+      if let Some(count) = self.counts.get_mut(y * self.width + x) {
+        *count += 1;
+      }
+    }
+  }
+}
+
+/// The brightness of a cell of a trace, from the number of samples in it and the count which should be at full
+/// brightness
+fn trace_intensity(count: u32, reference: f32) -> f32 {
+  if count == 0 {
+    return 0.0;
+  }
+
+  // Scale logarithmically, so that single samples stay visible next to flat areas of the image
+  let scaled = (1.0 + count as f32).ln() / (1.0 + reference.max(1.0)).ln();
+  (0.25 + 0.75 * scaled).min(1.0)
+}
+
+/// An opaque 8-bit RGBA image being drawn into
+struct Canvas<'a> {
+  width: usize,
+  height: usize,
+  pixels: &'a mut [u8],
+}
+impl Canvas<'_> {
+  fn clear(&mut self) {
+    for pixel in self.pixels.chunks_exact_mut(4) {
+      pixel.copy_from_slice(&[0, 0, 0, 255]);
+    }
+  }
+
+  fn set(&mut self, x: usize, y: usize, colour: [u8; 3]) {
+    if x < self.width && y < self.height {
+      let offset = (y * self.width + x) * 4;
+      self.pixels[offset..offset + 3].copy_from_slice(&colour);
+    }
+  }
+
+  /// Add a colour scaled by `intensity` onto a pixel, so that overlapping traces mix
+  fn add(&mut self, x: usize, y: usize, colour: [u8; 3], intensity: f32) {
+    if x < self.width && y < self.height {
+      let offset = (y * self.width + x) * 4;
+      for (pixel, component) in self.pixels[offset..offset + 3].iter_mut().zip(colour) {
+        *pixel = pixel.saturating_add((component as f32 * intensity).round() as u8);
+      }
+    }
+  }
+
+  fn horizontal_line(&mut self, y: usize, start: usize, end: usize, colour: [u8; 3]) {
+    for x in start..end {
+      self.set(x, y, colour);
+    }
+  }
+
+  fn vertical_line(&mut self, x: usize, start: usize, end: usize, colour: [u8; 3]) {
+    for y in start..end {
+      self.set(x, y, colour);
+    }
+  }
+
+  /// Draw the outline of a square centred on a point
+  fn square(&mut self, x: usize, y: usize, half_size: usize, colour: [u8; 3]) {
+    let (left, top) = (x.saturating_sub(half_size), y.saturating_sub(half_size));
+    let (right, bottom) = (x + half_size, y + half_size);
+    self.horizontal_line(top, left, right + 1, colour);
+    self.horizontal_line(bottom, left, right + 1, colour);
+    self.vertical_line(left, top, bottom + 1, colour);
+    self.vertical_line(right, top, bottom + 1, colour);
+  }
+
+  fn add_density(&mut self, density: &Density, colour: [u8; 3], reference: f32) {
+    for (index, &count) in density.counts.iter().enumerate() {
+      let intensity = trace_intensity(count, reference);
+      if intensity > 0.0 {
+        self.add(index % self.width, index / self.width, colour, intensity);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn grey_image(width: usize, height: usize, y: u32) -> Vec<u8> {
+    let word1 = (940 << 20 | 512 << 10 | y).to_be_bytes();
+    let word2 = (940 << 20 | 512 << 10 | y).to_be_bytes();
+    [word1, word2].concat().repeat(width * height / 2)
+  }
+
+  fn render(kind: ScopeKind, input: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let constants = YuvConstantsSimd::create(0.2126, 0.0722);
+    let mut output = vec![0; 64 * 32 * 4];
+    render_scope(
+      &constants,
+      kind,
+      width,
+      height,
+      input,
+      64,
+      32,
+      0.75,
+      &mut output,
+    )
+    .unwrap();
+    output
+  }
+
+  fn pixel(image: &[u8], x: usize, y: usize) -> [u8; 4] {
+    let offset = (y * 64 + x) * 4;
+    image[offset..offset + 4].try_into().unwrap()
+  }
+
+  #[test]
+  fn test_waveform() {
+    // Nominal white lands on the top graticule line, and black on the bottom one
+    let image = render(ScopeKind::Waveform, &grey_image(16, 4, 940), 16, 4);
+    let white_row = (83.0 / 1023.0 * 31.0_f32).round() as usize;
+    assert_eq!(pixel(&image, 8, white_row), [255, 255, 255, 255]);
+    assert_eq!(pixel(&image, 8, white_row + 1), [0, 0, 0, 255]);
+
+    let image = render(ScopeKind::Waveform, &grey_image(16, 4, 64), 16, 4);
+    let black_row = (959.0 / 1023.0 * 31.0_f32).round() as usize;
+    assert_eq!(pixel(&image, 8, black_row), [255, 255, 255, 255]);
+    assert_eq!(pixel(&image, 8, white_row), [80, 80, 80, 255]);
+  }
+
+  #[test]
+  fn test_parade() {
+    // Grey has equal RGB, so each section has its trace at the same height
+    let image = render(ScopeKind::Parade, &grey_image(16, 4, 700), 16, 4);
+    let row = (323.0 / 1023.0 * 31.0_f32).round() as usize;
+    assert_eq!(pixel(&image, 5, row), [255, 40, 40, 255]);
+    assert_eq!(pixel(&image, 26, row), [40, 255, 40, 255]);
+    assert_eq!(pixel(&image, 47, row), [60, 60, 255, 255]);
+  }
+
+  #[test]
+  fn test_vectorscope() {
+    // Neutral chroma lands in the centre
+    let image = render(ScopeKind::Vectorscope, &grey_image(16, 4, 502), 16, 4);
+    assert_eq!(pixel(&image, 32, 15), [255, 255, 255, 255]);
+    assert_eq!(pixel(&image, 33, 17), [0, 0, 0, 255]);
+
+    // The 75% red target is drawn where the encoder puts 75% red
+    let red = [0.75 * 255.0, 0.0, 0.0];
+    let y = 0.2126 * red[0];
+    let cb = 512.0 + 1.75 * (0.0 - y) / (1.0 - 0.0722);
+    let cr = 512.0 + 1.75 * (red[0] - y) / (1.0 - 0.2126);
+    let x = 16 + (cb * 32.0 / 1024.0) as usize;
+    let y = ((1023.0 - cr) * 32.0 / 1024.0) as usize;
+    assert_eq!(pixel(&image, x - 2, y), [192, 64, 64, 255]);
+  }
+
+  #[test]
+  fn test_histogram() {
+    let image = render(ScopeKind::Histogram, &grey_image(16, 4, 502), 16, 4);
+    let column = 502 * 64 / 1024;
+    assert_eq!(pixel(&image, column, 0), [204, 204, 204, 255]);
+    assert_eq!(pixel(&image, column + 1, 31), [0, 0, 0, 255]);
+
+    // Equal RGB bars mix to white
+    let image = render(ScopeKind::RgbHistogram, &grey_image(16, 4, 502), 16, 4);
+    assert_eq!(pixel(&image, column, 31), [255, 255, 255, 255]);
+  }
+
+  #[test]
+  fn test_short_input() {
+    let constants = YuvConstantsSimd::create(0.2126, 0.0722);
+    let mut output = vec![0; 64 * 32 * 4];
+    let result = render_scope(
+      &constants,
+      ScopeKind::Waveform,
+      16,
+      4,
+      &grey_image(16, 3, 502),
+      64,
+      32,
+      0.75,
+      &mut output,
+    );
+    assert!(result.is_err());
+  }
+}
@@ -0,0 +1,73 @@
+use std::simd::Simd;
+
+/// Convert a linear light value to gamma encoded, with the BT.709 opto-electronic transfer function
+#[inline(always)]
+pub fn linear_to_gamma(value: f32) -> f32 {
+  if value < 0.018 {
+    value * 4.5
+  } else {
+    1.099 * value.powf(0.45) - 0.099
+  }
+}
+
+/// Convert a gamma encoded value to linear light, inverting the BT.709 opto-electronic transfer function
+#[inline(always)]
+pub fn gamma_to_linear(value: f32) -> f32 {
+  if value < 0.081 {
+    value / 4.5
+  } else {
+    ((value + 0.099) / 1.099).powf(1.0 / 0.45)
+  }
+}
+
+/// Convert an sRGB encoded value to the linear light a display emits, with the sRGB electro-optical transfer function
+pub fn srgb_to_linear(value: f64) -> f64 {
+  if value <= 0.04045 {
+    value / 12.92
+  } else {
+    ((value + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+#[inline(always)]
+pub fn linear_to_gamma_simd(values: Simd<f32, 4>) -> Simd<f32, 4> {
+  Simd::from_array(values.to_array().map(linear_to_gamma))
+}
+
+#[inline(always)]
+pub fn gamma_to_linear_simd(values: Simd<f32, 4>) -> Simd<f32, 4> {
+  Simd::from_array(values.to_array().map(gamma_to_linear))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    for i in 0..=1000 {
+      let value = i as f32 / 1000.0;
+      let round_trip = gamma_to_linear(linear_to_gamma(value));
+      assert!(
+        (round_trip - value).abs() < 1e-5,
+        "{value} became {round_trip}"
+      );
+    }
+  }
+
+  #[test]
+  fn test_srgb() {
+    assert_eq!(srgb_to_linear(0.0), 0.0);
+    assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+    assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    // The two segments meet
+    assert!((srgb_to_linear(0.04045) - srgb_to_linear(0.040451)).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_extremes() {
+    assert_eq!(linear_to_gamma(0.0), 0.0);
+    assert!((linear_to_gamma(1.0) - 1.0).abs() < 1e-6);
+    assert!((linear_to_gamma(0.18) - 0.409).abs() < 1e-3);
+  }
+}
@@ -1,4 +1,9 @@
+use crate::decode_lut::DecodeLut;
+use crate::fixed_point::FixedConstants;
+use crate::javascript::JavascriptConstants;
+use crate::options::{Colorspace, VideoRange};
 use std::simd::{f32x4, u32x4, Simd};
+use std::sync::OnceLock;
 
 pub struct YuvConstantsSimd {
   pub kr: Simd<f32, 4>,
@@ -19,31 +24,103 @@ pub struct YuvConstantsSimd {
   pub cb_cr_offset: Simd<f32, 4>,
   pub half_cb_cr_scale: Simd<f32, 4>,
 
+  /// The scales of 16-bit and float input, and of output deeper than 8 bits, which are converted exactly rather than as
+  /// earlier versions converted 8-bit
+  pub deep_luma_scale: Simd<f32, 4>,
+  pub deep_half_cb_cr_scale: Simd<f32, 4>,
+  /// The codes spanned by the deep scales, which deep output divides by so that mid grey and white decode exactly
+  pub deep_luma_codes: Simd<f32, 4>,
+  pub deep_half_cb_cr_codes: Simd<f32, 4>,
+
   pub alpha_scale: Simd<f32, 4>,
   pub alpha_offset: Simd<f32, 4>,
 
+  /// Whether the luma and chroma, and the alpha, of 8-bit input are rounded to the nearest code. Each is rounded when
+  /// its range is full, and truncated when it is limited, to match earlier versions
+  pub round_8bit_video: bool,
+  pub round_8bit_alpha: bool,
+
   pub cr_to_g: Simd<f32, 4>,
   pub cb_to_g: Simd<f32, 4>,
 
+  pub shift_30: Simd<u32, 4>,
   pub shift_20: Simd<u32, 4>,
   pub shift_10: Simd<u32, 4>,
+  pub shift_8: Simd<u32, 4>,
 
-  pub splat2: Simd<u32, 4>,
-  pub splat4: Simd<u32, 4>,
-  pub splat6: Simd<u32, 4>,
-  pub splat8: Simd<u32, 4>,
-  pub splat15: Simd<u32, 4>,
-  pub splat255: Simd<u32, 4>,
   pub splat1023: Simd<u32, 4>,
 
   pub splat0f: Simd<f32, 4>,
+  pub splat1f: Simd<f32, 4>,
   pub splat255f: Simd<f32, 4>,
+  pub splat1023f: Simd<f32, 4>,
+
+  pub scale_16bit: Simd<f32, 4>,
+  pub splat3f: Simd<f32, 4>,
+  pub splat65535f: Simd<f32, 4>,
 
   pub gather_idx: Simd<usize, 4>,
+  pub gather_idx_16: Simd<usize, 4>,
   pub scatter_idx: Simd<usize, 4>,
+
+  /// The coefficients of the integer kernels
+  pub fixed: FixedConstants,
+  /// The coefficients of the kernels which reproduce atem-connection, which only has them for limited range video
+  pub javascript: Option<JavascriptConstants>,
+
+  decode_lut: OnceLock<Box<DecodeLut>>,
 }
 impl YuvConstantsSimd {
+  /// Create the constants for limited range luma, chroma and alpha
+  #[cfg(test)]
   pub fn create(kr: f32, kb: f32) -> YuvConstantsSimd {
+    YuvConstantsSimd::create_with_range(kr, kb, VideoRange::Limited, VideoRange::Limited)
+  }
+
+  pub fn create_with_range(
+    kr: f32,
+    kb: f32,
+    range: VideoRange,
+    alpha_range: VideoRange,
+  ) -> YuvConstantsSimd {
+    // Values are scaled from the 0-255 range of 8-bit RGB. They are kept in double precision for the fixed-point
+    // coefficients. Limited range 8-bit input is scaled by 1/256 rather than 1/255 per step, as earlier versions did,
+    // so white is a few codes short of the top of the range
+    let (luma_offset, luma_scale, half_cb_cr_scale) = match range {
+      VideoRange::Limited => (64.0, 219.0 / 64.0, 224.0 / 64.0 / 2.0),
+      VideoRange::Full => (0.0, 1023.0 / 255.0, 1023.0 / 255.0 / 2.0),
+    };
+    let (deep_luma_codes, deep_half_cb_cr_codes) = match range {
+      VideoRange::Limited => (876.0, 896.0 / 2.0),
+      VideoRange::Full => (1023.0, 1023.0 / 2.0),
+    };
+    let (deep_luma_scale, deep_half_cb_cr_scale) =
+      (deep_luma_codes / 255.0, deep_half_cb_cr_codes / 255.0);
+    let (alpha_offset, alpha_scale) = match alpha_range {
+      VideoRange::Limited => (64.0, 219.0 / 255.0 * 4.0),
+      VideoRange::Full => (0.0, 1023.0 / 255.0),
+    };
+    let fixed = FixedConstants::new(
+      kr,
+      kb,
+      luma_offset,
+      luma_scale,
+      half_cb_cr_scale,
+      alpha_offset,
+      alpha_scale,
+      range == VideoRange::Full,
+      alpha_range == VideoRange::Full,
+    );
+    let (luma_offset, luma_scale, half_cb_cr_scale) = (
+      luma_offset as f32,
+      luma_scale as f32,
+      half_cb_cr_scale as f32,
+    );
+    let (alpha_offset, alpha_scale) = (alpha_offset as f32, alpha_scale as f32);
+
+    let javascript =
+      (range == VideoRange::Limited).then(|| JavascriptConstants::new(kr, kb, alpha_range));
+
     let kg = 1.0 - kr - kb;
     let kr_i = 1.0 - kr;
     let kb_i = 1.0 - kb;
@@ -61,34 +138,72 @@ impl YuvConstantsSimd {
       kb_o_kr_i: f32x4::splat(-kb / kr_i),
       kg_o_kr_i: f32x4::splat(-kg / kr_i),
 
-      luma_scale: f32x4::splat(219.0 / 64.0),
-      luma_offset: f32x4::splat(64.0),
+      luma_scale: f32x4::splat(luma_scale),
+      luma_offset: f32x4::splat(luma_offset),
 
       cb_cr_offset: f32x4::splat(512.0),
-      half_cb_cr_scale: f32x4::splat(224.0 / 64.0 / 2.0),
+      half_cb_cr_scale: f32x4::splat(half_cb_cr_scale),
+
+      deep_luma_scale: f32x4::splat(deep_luma_scale),
+      deep_half_cb_cr_scale: f32x4::splat(deep_half_cb_cr_scale),
+      deep_luma_codes: f32x4::splat(deep_luma_codes),
+      deep_half_cb_cr_codes: f32x4::splat(deep_half_cb_cr_codes),
 
-      alpha_scale: f32x4::splat(219.0 / 255.0 * 4.0),
-      alpha_offset: f32x4::splat(64.0),
+      alpha_scale: f32x4::splat(alpha_scale),
+      alpha_offset: f32x4::splat(alpha_offset),
+
+      round_8bit_video: range == VideoRange::Full,
+      round_8bit_alpha: alpha_range == VideoRange::Full,
 
       cr_to_g: f32x4::splat(kr * kr_i / kg),
       cb_to_g: f32x4::splat(kb * kb_i / kg),
 
+      shift_30: u32x4::splat(30),
       shift_20: u32x4::splat(20),
       shift_10: u32x4::splat(10),
+      shift_8: u32x4::splat(8),
 
-      splat2: u32x4::splat(2),
-      splat4: u32x4::splat(4),
-      splat6: u32x4::splat(6),
-      splat8: u32x4::splat(8),
-      splat15: u32x4::splat(0x0f),
-      splat255: u32x4::splat(0xff),
       splat1023: u32x4::splat(0x3ff),
 
       splat0f: f32x4::splat(0.0),
+      splat1f: f32x4::splat(1.0),
       splat255f: f32x4::splat(255.0),
+      splat1023f: f32x4::splat(1023.0),
+
+      // 16-bit values are scaled to the 0-255 range of 8-bit input, with the deep scales converting them exactly
+      scale_16bit: f32x4::splat(255.0 / 65535.0),
+      splat3f: f32x4::splat(3.0),
+      splat65535f: f32x4::splat(65535.0),
 
       gather_idx: Simd::from_array([0, 8, 16, 24]),
+      gather_idx_16: Simd::from_array([0, 16, 32, 48]),
       scatter_idx: Simd::from_array([0, 8, 16, 24]),
+
+      fixed,
+      javascript,
+
+      decode_lut: OnceLock::new(),
     }
   }
+
+  /// The constants of a colorspace and ranges, which are created the first time they are needed and then shared, so
+  /// that their lookup tables are only built once
+  pub fn shared(
+    colorspace: Colorspace,
+    range: VideoRange,
+    alpha_range: VideoRange,
+  ) -> &'static YuvConstantsSimd {
+    static SHARED: [OnceLock<YuvConstantsSimd>; 8] = [const { OnceLock::new() }; 8];
+
+    let index = colorspace as usize * 4 + range as usize * 2 + alpha_range as usize;
+    SHARED[index].get_or_init(|| {
+      let [kr, kb] = colorspace.coefficients();
+      YuvConstantsSimd::create_with_range(kr, kb, range, alpha_range)
+    })
+  }
+
+  /// The lookup tables for decoding, which are built the first time they are needed
+  pub fn decode_lut(&self) -> &DecodeLut {
+    self.decode_lut.get_or_init(|| DecodeLut::new(self))
+  }
 }
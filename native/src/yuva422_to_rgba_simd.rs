@@ -1,5 +1,10 @@
+use crate::dither::DitherOffsets;
+use crate::rgba_to_yuva422_simd::{splat, PixelPairs};
+use crate::transfer::gamma_to_linear_simd;
 use crate::yuv_constants::YuvConstantsSimd;
-use std::simd::{num::SimdFloat, num::SimdUint, u32x4, Simd, StdFloat};
+use std::simd::{
+  num::SimdFloat, num::SimdUint, u32x4, LaneCount, Simd, StdFloat, SupportedLaneCount,
+};
 
 #[inline(always)]
 pub fn yuva422_to_rgb_simd(
@@ -8,40 +13,31 @@ pub fn yuva422_to_rgb_simd(
   sample2: &[u8],
   sample3: &[u8],
   sample4: &[u8],
+  dither: Option<&DitherOffsets>,
   target: &mut [u8],
 ) {
-  let ycba1_1 = u32::from_be_bytes((&sample1[0..4]).try_into().unwrap());
-  let ycra1_2 = u32::from_be_bytes((&sample1[4..8]).try_into().unwrap());
-  let ycba2_1 = u32::from_be_bytes((&sample2[0..4]).try_into().unwrap());
-  let ycra2_2 = u32::from_be_bytes((&sample2[4..8]).try_into().unwrap());
-  let ycba3_1 = u32::from_be_bytes((&sample3[0..4]).try_into().unwrap());
-  let ycra3_2 = u32::from_be_bytes((&sample3[4..8]).try_into().unwrap());
-  let ycba4_1 = u32::from_be_bytes((&sample4[0..4]).try_into().unwrap());
-  let ycra4_2 = u32::from_be_bytes((&sample4[4..8]).try_into().unwrap());
+  let mut pixels = decode_pixel_pairs(constants, sample1, sample2, sample3, sample4, false);
+  if let Some([first, second]) = dither {
+    pixels.r1 += first;
+    pixels.g1 += first;
+    pixels.b1 += first;
+    pixels.a1 += first;
 
-  let vec_ycba = u32x4::from_array([ycba1_1, ycba2_1, ycba3_1, ycba4_1]);
-  let vec_ycra = u32x4::from_array([ycra1_2, ycra2_2, ycra3_2, ycra4_2]);
-
-  let (vec_y1, vec_cb, a1) = split_components(constants, &vec_ycba);
-  let (vec_y2, vec_cr, a2) = split_components(constants, &vec_ycra);
-
-  let r1 = calc_r(constants, &vec_y1, &vec_cr);
-  let g1 = calc_g(constants, &vec_y1, &vec_cb, &vec_cr);
-  let b1 = calc_b(constants, &vec_y1, &vec_cb);
-
-  let r2 = calc_r(constants, &vec_y2, &vec_cr);
-  let g2 = calc_g(constants, &vec_y2, &vec_cb, &vec_cr);
-  let b2 = calc_b(constants, &vec_y2, &vec_cb);
+    pixels.r2 += second;
+    pixels.g2 += second;
+    pixels.b2 += second;
+    pixels.a2 += second;
+  }
 
-  let r1_u8 = r1.round().cast::<u8>();
-  let g1_u8 = g1.round().cast::<u8>();
-  let b1_u8 = b1.round().cast::<u8>();
-  let a1_u8 = a1.round().cast::<u8>();
+  let r1_u8 = pixels.r1.round().cast::<u8>();
+  let g1_u8 = pixels.g1.round().cast::<u8>();
+  let b1_u8 = pixels.b1.round().cast::<u8>();
+  let a1_u8 = pixels.a1.round().cast::<u8>();
 
-  let r2_u8 = r2.round().cast::<u8>();
-  let g2_u8 = g2.round().cast::<u8>();
-  let b2_u8 = b2.round().cast::<u8>();
-  let a2_u8 = a2.round().cast::<u8>();
+  let r2_u8 = pixels.r2.round().cast::<u8>();
+  let g2_u8 = pixels.g2.round().cast::<u8>();
+  let b2_u8 = pixels.b2.round().cast::<u8>();
+  let a2_u8 = pixels.a2.round().cast::<u8>();
 
   r1_u8.scatter(target, constants.scatter_idx);
   g1_u8.scatter(&mut target[1..], constants.scatter_idx);
@@ -54,41 +50,409 @@ pub fn yuva422_to_rgb_simd(
   a2_u8.scatter(&mut target[7..], constants.scatter_idx);
 }
 
+/// Convert 4 samples to 8 pixels of 8-bit RGB fill, and 8 pixels of 8-bit key taken from the 10-bit alpha
+///
+/// Without `premultiply` the fill and key are the same as the output of `yuva422_to_rgb_simd`. With it, the fill is
+/// multiplied by the key before both are rounded
+#[inline(always)]
+#[allow(clippy::too_many_arguments)]
+pub fn yuva422_to_fill_key_simd(
+  constants: &YuvConstantsSimd,
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  premultiply: bool,
+  fill: &mut [u8],
+  key: &mut [u8],
+) {
+  let mut pixels = decode_pixel_pairs(constants, sample1, sample2, sample3, sample4, false);
+  let a1 = pixels.a1.simd_clamp(constants.splat0f, constants.splat255f);
+  let a2 = pixels.a2.simd_clamp(constants.splat0f, constants.splat255f);
+  if premultiply {
+    let (scale1, scale2) = (a1 / constants.splat255f, a2 / constants.splat255f);
+    pixels.r1 *= scale1;
+    pixels.g1 *= scale1;
+    pixels.b1 *= scale1;
+
+    pixels.r2 *= scale2;
+    pixels.g2 *= scale2;
+    pixels.b2 *= scale2;
+  }
+
+  let fill_idx = Simd::from_array([0, 6, 12, 18]);
+  let channels = [
+    pixels.r1, pixels.g1, pixels.b1, pixels.r2, pixels.g2, pixels.b2,
+  ];
+  for (offset, channel) in channels.into_iter().enumerate() {
+    channel
+      .round()
+      .cast::<u8>()
+      .scatter(&mut fill[offset..], fill_idx);
+  }
+
+  let key_idx = Simd::from_array([0, 2, 4, 6]);
+  a1.round().cast::<u8>().scatter(key, key_idx);
+  a2.round().cast::<u8>().scatter(&mut key[1..], key_idx);
+}
+
+/// Convert `N` samples to `2 * N` pixels of 8-bit RGBA, giving the same output as `yuva422_to_rgb_simd` without
+/// dithering
+///
+/// The samples are split into pixels with shuffles, rather than scattering each channel, so this is only fast when
+/// compiled for wide vectors
+#[inline(always)]
+pub fn yuva422_to_rgb_lanes<const N: usize>(
+  constants: &YuvConstantsSimd,
+  samples: &[u8],
+  target: &mut [u8],
+) where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let low: Simd<u32, N> = load_u32_be(&samples[..N * 4]);
+  let high: Simd<u32, N> = load_u32_be(&samples[N * 4..N * 8]);
+  let (ycba, ycra) = low.deinterleave(high);
+
+  let pixels = decode_words(constants, ycba, ycra, false);
+  let clamp = |channel: Simd<f32, N>| {
+    channel
+      .simd_clamp(splat(constants.splat0f), splat(constants.splat255f))
+      .round()
+      .cast::<u8>()
+      .cast::<u32>()
+  };
+  // Alpha is not clamped, but saturates when cast to 8-bit, as in `yuva422_to_rgb_simd`
+  let alpha = |channel: Simd<f32, N>| channel.round().cast::<u8>().cast::<u32>();
+
+  let pack = |r, g, b, a| {
+    clamp(r)
+      | (clamp(g) << Simd::splat(8))
+      | (clamp(b) << Simd::splat(16))
+      | (alpha(a) << Simd::splat(24))
+  };
+  let first = pack(pixels.r1, pixels.g1, pixels.b1, pixels.a1);
+  let second = pack(pixels.r2, pixels.g2, pixels.b2, pixels.a2);
+
+  let (low, high) = first.interleave(second);
+  let words = low.to_array().into_iter().chain(high.to_array());
+  for (bytes, word) in target[..N * 8].chunks_exact_mut(4).zip(words) {
+    bytes.copy_from_slice(&word.to_le_bytes());
+  }
+}
+
+/// Load big-endian 32-bit words
+#[inline(always)]
+fn load_u32_be<const N: usize>(input: &[u8]) -> Simd<u32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  Simd::from_array(std::array::from_fn(|i| {
+    u32::from_be_bytes(input[i * 4..i * 4 + 4].try_into().unwrap())
+  }))
+}
+
+/// Convert 4 samples to 8 pixels of 32-bit float RGBA in the range 0-1
+///
+/// When `linear` is set, the colour values are converted to linear light
+#[inline(always)]
+pub fn yuva422_to_rgbf32_simd(
+  constants: &YuvConstantsSimd,
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  linear: bool,
+  target: &mut [u8],
+) {
+  let pixels = decode_pixel_pairs(constants, sample1, sample2, sample3, sample4, true);
+
+  let mut values = [0.0; 32];
+  scatter_f32(constants, pixels.r1, &mut values[0..], linear);
+  scatter_f32(constants, pixels.g1, &mut values[1..], linear);
+  scatter_f32(constants, pixels.b1, &mut values[2..], linear);
+  scatter_f32(constants, pixels.a1, &mut values[3..], false);
+
+  scatter_f32(constants, pixels.r2, &mut values[4..], linear);
+  scatter_f32(constants, pixels.g2, &mut values[5..], linear);
+  scatter_f32(constants, pixels.b2, &mut values[6..], linear);
+  scatter_f32(constants, pixels.a2, &mut values[7..], false);
+
+  for (bytes, value) in target.chunks_exact_mut(4).zip(values) {
+    bytes.copy_from_slice(&value.to_ne_bytes());
+  }
+}
+
+/// Convert 4 samples to 8 pixels of packed 10-bit RGB, with the components in full range 0-1023
+///
+/// Each pixel is a 32-bit word of `a << 30 | r << 20 | g << 10 | b`. When `with_alpha` is unset the alpha bits are left
+/// as zero and the words are written big-endian (r210), otherwise the alpha is quantised to 2 bits and the words are
+/// written little-endian (a2r10g10b10)
+#[inline(always)]
+pub fn yuva422_to_rgb10_simd(
+  constants: &YuvConstantsSimd,
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  with_alpha: bool,
+  target: &mut [u8],
+) {
+  let pixels = decode_pixel_pairs(constants, sample1, sample2, sample3, sample4, true);
+
+  let word1 = pack_rgb10(
+    constants, &pixels.r1, &pixels.g1, &pixels.b1, &pixels.a1, with_alpha,
+  );
+  let word2 = pack_rgb10(
+    constants, &pixels.r2, &pixels.g2, &pixels.b2, &pixels.a2, with_alpha,
+  );
+
+  for i in 0..4 {
+    let offset = i * 8;
+    let (bytes1, bytes2) = if with_alpha {
+      (word1[i].to_le_bytes(), word2[i].to_le_bytes())
+    } else {
+      (word1[i].to_be_bytes(), word2[i].to_be_bytes())
+    };
+
+    target[offset..offset + 4].copy_from_slice(&bytes1);
+    target[offset + 4..offset + 8].copy_from_slice(&bytes2);
+  }
+}
+
 #[inline(always)]
-fn calc_r(constants: &YuvConstantsSimd, y: &Simd<f32, 4>, cr: &Simd<f32, 4>) -> Simd<f32, 4> {
-  let val = y + constants.kr_i * cr;
-  val.simd_clamp(constants.splat0f, constants.splat255f)
+fn pack_rgb10(
+  constants: &YuvConstantsSimd,
+  r: &Simd<f32, 4>,
+  g: &Simd<f32, 4>,
+  b: &Simd<f32, 4>,
+  a: &Simd<f32, 4>,
+  with_alpha: bool,
+) -> Simd<u32, 4> {
+  let r = scale_channel(constants, r, constants.splat1023f);
+  let g = scale_channel(constants, g, constants.splat1023f);
+  let b = scale_channel(constants, b, constants.splat1023f);
+
+  let rgb = (r << constants.shift_20) | (g << constants.shift_10) | b;
+  if with_alpha {
+    let a = scale_channel(constants, a, constants.splat3f);
+    rgb | (a << constants.shift_30)
+  } else {
+    rgb
+  }
+}
+
+/// Convert 4 samples to 8 pixels of 16-bit per channel RGBA, in the given byte order
+#[inline(always)]
+pub fn yuva422_to_rgb16_simd(
+  constants: &YuvConstantsSimd,
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  big_endian: bool,
+  target: &mut [u8],
+) {
+  let pixels = decode_pixel_pairs(constants, sample1, sample2, sample3, sample4, true);
+
+  let mut values = [0u32; 32];
+  let channels = [
+    pixels.r1, pixels.g1, pixels.b1, pixels.a1, pixels.r2, pixels.g2, pixels.b2, pixels.a2,
+  ];
+  for (offset, channel) in channels.iter().enumerate() {
+    scale_channel(constants, channel, constants.splat65535f)
+      .scatter(&mut values[offset..], constants.scatter_idx);
+  }
+
+  for (bytes, value) in target.chunks_exact_mut(2).zip(values) {
+    let value = value as u16;
+    bytes.copy_from_slice(&if big_endian {
+      value.to_be_bytes()
+    } else {
+      value.to_le_bytes()
+    });
+  }
 }
 
+/// Scale a channel of each pair from the 0-255 range of 8-bit output to the range 0-`max`, and round it to an integer
+///
+/// Multiplying before dividing keeps halves of the 8-bit range exact, so they round up as expected
 #[inline(always)]
-fn calc_g(
+fn scale_channel(
   constants: &YuvConstantsSimd,
-  y: &Simd<f32, 4>,
-  cb: &Simd<f32, 4>,
-  cr: &Simd<f32, 4>,
-) -> Simd<f32, 4> {
-  let val = y - (constants.cr_to_g * cr) - (constants.cb_to_g * cb);
-  val.simd_clamp(constants.splat0f, constants.splat255f)
+  channel: &Simd<f32, 4>,
+  max: Simd<f32, 4>,
+) -> Simd<u32, 4> {
+  (channel.simd_clamp(constants.splat0f, constants.splat255f) * max / constants.splat255f)
+    .round()
+    .cast::<u32>()
 }
 
+/// Scale a channel of each pair to the 0-1 range, and scatter it into the output values
 #[inline(always)]
-fn calc_b(constants: &YuvConstantsSimd, y: &Simd<f32, 4>, cb: &Simd<f32, 4>) -> Simd<f32, 4> {
-  let val = y + constants.kb_i * cb;
-  val.simd_clamp(constants.splat0f, constants.splat255f)
+fn scatter_f32(
+  constants: &YuvConstantsSimd,
+  channel: Simd<f32, 4>,
+  values: &mut [f32],
+  linear: bool,
+) {
+  let mut channel =
+    channel.simd_clamp(constants.splat0f, constants.splat255f) / constants.splat255f;
+  if linear {
+    channel = gamma_to_linear_simd(channel);
+  }
+
+  channel.scatter(values, constants.scatter_idx);
 }
 
+/// Split 4 samples into the components of their 4 pairs of pixels, in the 0-255 range of 8-bit output
+///
+/// When `deep` is set the luma and chroma are scaled exactly, for outputs deeper than 8 bits, rather than as earlier
+/// versions scaled them for 8-bit output
 #[inline(always)]
-fn split_components(
+fn decode_pixel_pairs(
   constants: &YuvConstantsSimd,
-  vec_combined: &Simd<u32, 4>,
-) -> (Simd<f32, 4>, Simd<f32, 4>, Simd<f32, 4>) {
-  let a = (vec_combined >> constants.shift_20) & constants.splat1023;
-  let uv = (vec_combined >> constants.shift_10) & constants.splat1023;
-  let y = vec_combined & constants.splat1023;
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  deep: bool,
+) -> PixelPairs {
+  let pixels = decode_pixel_pairs_unclamped(constants, sample1, sample2, sample3, sample4, deep);
+  let clamp = |channel: Simd<f32, 4>| channel.simd_clamp(constants.splat0f, constants.splat255f);
+
+  PixelPairs {
+    r1: clamp(pixels.r1),
+    g1: clamp(pixels.g1),
+    b1: clamp(pixels.b1),
+    a1: pixels.a1,
+
+    r2: clamp(pixels.r2),
+    g2: clamp(pixels.g2),
+    b2: clamp(pixels.b2),
+    a2: pixels.a2,
+  }
+}
 
-  let y_full = (y.cast::<f32>() - constants.luma_offset) / constants.luma_scale;
-  let uv_full = (uv.cast::<f32>() - constants.cb_cr_offset) / constants.half_cb_cr_scale;
-  let a_full = (a.cast::<f32>() - constants.alpha_offset) / constants.alpha_scale;
+/// Split 4 samples into the components of their 4 pairs of pixels, scaled to the 0-255 range of 8-bit output but
+/// without clamping the colour to it, so that out of range values can be measured
+#[inline(always)]
+pub(crate) fn decode_pixel_pairs_unclamped(
+  constants: &YuvConstantsSimd,
+  sample1: &[u8],
+  sample2: &[u8],
+  sample3: &[u8],
+  sample4: &[u8],
+  deep: bool,
+) -> PixelPairs {
+  let ycba1_1 = u32::from_be_bytes(sample1[0..4].try_into().unwrap());
+  let ycra1_2 = u32::from_be_bytes(sample1[4..8].try_into().unwrap());
+  let ycba2_1 = u32::from_be_bytes(sample2[0..4].try_into().unwrap());
+  let ycra2_2 = u32::from_be_bytes(sample2[4..8].try_into().unwrap());
+  let ycba3_1 = u32::from_be_bytes(sample3[0..4].try_into().unwrap());
+  let ycra3_2 = u32::from_be_bytes(sample3[4..8].try_into().unwrap());
+  let ycba4_1 = u32::from_be_bytes(sample4[0..4].try_into().unwrap());
+  let ycra4_2 = u32::from_be_bytes(sample4[4..8].try_into().unwrap());
+
+  let vec_ycba = u32x4::from_array([ycba1_1, ycba2_1, ycba3_1, ycba4_1]);
+  let vec_ycra = u32x4::from_array([ycra1_2, ycra2_2, ycra3_2, ycra4_2]);
+
+  decode_words(constants, vec_ycba, vec_ycra, deep)
+}
+
+/// Split the first and second words of each sample into the components of their pairs of pixels
+#[inline(always)]
+fn decode_words<const N: usize>(
+  constants: &YuvConstantsSimd,
+  vec_ycba: Simd<u32, N>,
+  vec_ycra: Simd<u32, N>,
+  deep: bool,
+) -> PixelPairs<N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let (vec_y1, vec_cb, a1) = split_components(constants, &vec_ycba, deep);
+  let (vec_y2, vec_cr, a2) = split_components(constants, &vec_ycra, deep);
+
+  PixelPairs {
+    r1: calc_r(constants, &vec_y1, &vec_cr),
+    g1: calc_g(constants, &vec_y1, &vec_cb, &vec_cr),
+    b1: calc_b(constants, &vec_y1, &vec_cb),
+    a1,
+
+    r2: calc_r(constants, &vec_y2, &vec_cr),
+    g2: calc_g(constants, &vec_y2, &vec_cb, &vec_cr),
+    b2: calc_b(constants, &vec_y2, &vec_cb),
+    a2,
+  }
+}
+
+#[inline(always)]
+fn calc_r<const N: usize>(
+  constants: &YuvConstantsSimd,
+  y: &Simd<f32, N>,
+  cr: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  y + splat(constants.kr_i) * cr
+}
+
+#[inline(always)]
+fn calc_g<const N: usize>(
+  constants: &YuvConstantsSimd,
+  y: &Simd<f32, N>,
+  cb: &Simd<f32, N>,
+  cr: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  y - (splat(constants.cr_to_g) * cr) - (splat(constants.cb_to_g) * cb)
+}
+
+#[inline(always)]
+fn calc_b<const N: usize>(
+  constants: &YuvConstantsSimd,
+  y: &Simd<f32, N>,
+  cb: &Simd<f32, N>,
+) -> Simd<f32, N>
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  y + splat(constants.kb_i) * cb
+}
+
+#[inline(always)]
+fn split_components<const N: usize>(
+  constants: &YuvConstantsSimd,
+  vec_combined: &Simd<u32, N>,
+  deep: bool,
+) -> (Simd<f32, N>, Simd<f32, N>, Simd<f32, N>)
+where
+  LaneCount<N>: SupportedLaneCount,
+{
+  let mask = splat(constants.splat1023);
+  let a = (vec_combined >> splat(constants.shift_20)) & mask;
+  let uv = (vec_combined >> splat(constants.shift_10)) & mask;
+  let y = vec_combined & mask;
+
+  let y = y.cast::<f32>() - splat(constants.luma_offset);
+  let uv = uv.cast::<f32>() - splat(constants.cb_cr_offset);
+  let (y_full, uv_full) = if deep {
+    // Multiplying before dividing keeps codes that fall on the 8-bit range exact
+    let splat255f = splat(constants.splat255f);
+    (
+      y * splat255f / splat(constants.deep_luma_codes),
+      uv * splat255f / splat(constants.deep_half_cb_cr_codes),
+    )
+  } else {
+    (
+      y / splat(constants.luma_scale),
+      uv / splat(constants.half_cb_cr_scale),
+    )
+  };
+  let a_full = (a.cast::<f32>() - splat(constants.alpha_offset)) / splat(constants.alpha_scale);
 
   (y_full, uv_full, a_full)
 }
@@ -97,12 +461,21 @@ fn split_components(
 mod tests {
   // Note this useful idiom: importing names from outer (for mod tests) scope.
   use super::*;
+  use crate::options::VideoRange;
 
   fn yuva422_to_rgb_single(input: &[u8; 8]) -> [u8; 8] {
     let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
 
     let mut target = [0; 32];
-    yuva422_to_rgb_simd(&bt601_constants, input, input, input, input, &mut target);
+    yuva422_to_rgb_simd(
+      &bt601_constants,
+      input,
+      input,
+      input,
+      input,
+      None,
+      &mut target,
+    );
 
     let mut target_trimmed = [0; 8];
     target_trimmed.copy_from_slice(&target[0..8]);
@@ -114,6 +487,31 @@ mod tests {
     target_trimmed
   }
 
+  fn yuva422_to_rgbf32_single(input: &[u8; 8], linear: bool) -> [f32; 8] {
+    let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let mut target = [0; 128];
+    yuva422_to_rgbf32_simd(
+      &bt601_constants,
+      input,
+      input,
+      input,
+      input,
+      linear,
+      &mut target,
+    );
+
+    assert_eq!(&target[0..32], &target[32..64]);
+    assert_eq!(&target[0..32], &target[64..96]);
+    assert_eq!(&target[0..32], &target[96..128]);
+
+    let mut values = [0.0; 8];
+    for (value, bytes) in values.iter_mut().zip(target.chunks_exact(4)) {
+      *value = f32::from_ne_bytes(bytes.try_into().unwrap());
+    }
+    values
+  }
+
   #[test]
   fn test_black() {
     let input = [4, 8, 0, 64, 4, 8, 0, 64];
@@ -121,6 +519,307 @@ mod tests {
     assert_eq!(yuva422_to_rgb_single(&input), output);
   }
 
+  #[test]
+  fn test_f32_black_and_white() {
+    assert_eq!(
+      yuva422_to_rgbf32_single(&[4, 8, 0, 64, 4, 8, 0, 64], false),
+      [0.0; 8]
+    );
+
+    // White is 940 luma and alpha, with neutral chroma
+    let white = [0x3a, 0xc8, 0x03, 0xac, 0x3a, 0xc8, 0x03, 0xac];
+    for value in yuva422_to_rgbf32_single(&white, true) {
+      assert!((value - 1.0).abs() < 1e-3, "{value}");
+    }
+  }
+
+  #[test]
+  fn test_f32_matches_16bit() {
+    let inputs = [
+      [57, 10, 137, 32, 24, 102, 134, 122],
+      [47, 151, 57, 123, 55, 90, 175, 76],
+      [25, 230, 157, 102, 32, 136, 188, 213],
+    ];
+    for input in inputs {
+      let output_16bit = yuva422_to_rgb16_single(&input);
+      let output_f32 = yuva422_to_rgbf32_single(&input, false);
+      for (value_16bit, value_f32) in output_16bit.iter().zip(output_f32) {
+        assert!(
+          (*value_16bit as f32 - value_f32 * 65535.0).abs() <= 0.5,
+          "{output_16bit:?} {output_f32:?}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_f32_exact_values() {
+    // Float output is scaled exactly, so black, mid grey and white are exact and the codes below white stay distinct
+    assert_eq!(yuva422_to_rgbf32_single(&grey_sample(64), false)[0], 0.0);
+    assert_eq!(yuva422_to_rgbf32_single(&grey_sample(502), false)[0], 0.5);
+    assert_eq!(yuva422_to_rgbf32_single(&grey_sample(940), false)[0], 1.0);
+
+    let values = [937, 938, 939, 940].map(|y| yuva422_to_rgbf32_single(&grey_sample(y), false)[0]);
+    for (value, y) in values.iter().zip([937, 938, 939, 940]) {
+      let expected = (y - 64) as f32 / 876.0;
+      assert!((value - expected).abs() < 1e-6, "luma {y} was {value}");
+    }
+  }
+
+  fn grey_sample(y: u32) -> [u8; 8] {
+    let word1 = 940 << 20 | 512 << 10 | y;
+    let word2 = 940 << 20 | 512 << 10 | y;
+    let mut sample = [0; 8];
+    sample[0..4].copy_from_slice(&word1.to_be_bytes());
+    sample[4..8].copy_from_slice(&word2.to_be_bytes());
+    sample
+  }
+
+  fn yuva422_to_rgb10_single(input: &[u8; 8], with_alpha: bool) -> [u32; 2] {
+    let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let mut target = [0; 32];
+    yuva422_to_rgb10_simd(
+      &bt601_constants,
+      input,
+      input,
+      input,
+      input,
+      with_alpha,
+      &mut target,
+    );
+    assert_eq!(&target[0..8], &target[24..32]);
+
+    let word = |bytes: &[u8]| {
+      let bytes = bytes.try_into().unwrap();
+      if with_alpha {
+        u32::from_le_bytes(bytes)
+      } else {
+        u32::from_be_bytes(bytes)
+      }
+    };
+    [word(&target[0..4]), word(&target[4..8])]
+  }
+
+  fn yuva422_to_rgb16_single(input: &[u8; 8]) -> [u16; 8] {
+    let bt601_constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let mut outputs = Vec::new();
+    for big_endian in [false, true] {
+      let mut target = [0; 64];
+      yuva422_to_rgb16_simd(
+        &bt601_constants,
+        input,
+        input,
+        input,
+        input,
+        big_endian,
+        &mut target,
+      );
+      assert_eq!(&target[0..16], &target[48..64]);
+
+      let mut values = [0; 8];
+      for (value, bytes) in values.iter_mut().zip(target.chunks_exact(2)) {
+        let bytes = bytes.try_into().unwrap();
+        *value = if big_endian {
+          u16::from_be_bytes(bytes)
+        } else {
+          u16::from_le_bytes(bytes)
+        };
+      }
+      outputs.push(values);
+    }
+
+    assert_eq!(outputs[0], outputs[1], "byte orders differ");
+    outputs[0]
+  }
+
+  #[test]
+  fn test_deep_black_and_white() {
+    let black = [4, 8, 0, 64, 4, 8, 0, 64];
+    assert_eq!(yuva422_to_rgb10_single(&black, false), [0, 0]);
+    assert_eq!(yuva422_to_rgb10_single(&black, true), [0, 0]);
+    assert_eq!(yuva422_to_rgb16_single(&black), [0; 8]);
+
+    let white = grey_sample(940);
+    assert_eq!(yuva422_to_rgb10_single(&white, false), [0x3fffffff; 2]);
+    assert_eq!(yuva422_to_rgb10_single(&white, true), [0xffffffff; 2]);
+    assert_eq!(yuva422_to_rgb16_single(&white), [0xffff; 8]);
+  }
+
+  #[test]
+  fn test_deep_precision() {
+    // Neighbouring luma codes collapse to the same 8-bit value, but stay distinct at higher depths
+    let (low, high) = (grey_sample(498), grey_sample(499));
+    assert_eq!(yuva422_to_rgb_single(&low), yuva422_to_rgb_single(&high));
+
+    let [low_10bit, _] = yuva422_to_rgb10_single(&low, false);
+    let [high_10bit, _] = yuva422_to_rgb10_single(&high, false);
+    assert!((low_10bit & 0x3ff) < (high_10bit & 0x3ff));
+    assert_eq!(low_10bit >> 20, low_10bit & 0x3ff);
+
+    assert!(yuva422_to_rgb16_single(&low)[0] < yuva422_to_rgb16_single(&high)[0]);
+  }
+
+  #[test]
+  fn test_deep_exact_codes() {
+    // Deep outputs are scaled exactly, so black, mid grey and white land on the exact codes
+    for (y, code_10bit, code_16bit) in [(64, 0, 0), (502, 512, 32768), (940, 1023, 65535)] {
+      let sample = grey_sample(y);
+      let [word, _] = yuva422_to_rgb10_single(&sample, false);
+      assert_eq!(
+        [word >> 20, word >> 10 & 0x3ff, word & 0x3ff],
+        [code_10bit; 3],
+        "luma {y}"
+      );
+      let [word, _] = yuva422_to_rgb10_single(&sample, true);
+      assert_eq!(word & 0x3fffffff, code_10bit * 0x100401, "luma {y}");
+      assert_eq!(
+        yuva422_to_rgb16_single(&sample)[0..3],
+        [code_16bit; 3],
+        "luma {y}"
+      );
+    }
+  }
+
+  fn sample(y: u32, cb: u32, cr: u32, a: u32) -> [u8; 8] {
+    let mut sample = [0; 8];
+    sample[0..4].copy_from_slice(&(a << 20 | cb << 10 | y).to_be_bytes());
+    sample[4..8].copy_from_slice(&(a << 20 | cr << 10 | y).to_be_bytes());
+    sample
+  }
+
+  fn yuva422_to_rgb_with_range(
+    input: &[u8; 8],
+    range: VideoRange,
+    alpha_range: VideoRange,
+  ) -> [u8; 8] {
+    let constants = YuvConstantsSimd::create_with_range(0.299, 0.114, range, alpha_range);
+
+    let mut target = [0; 32];
+    yuva422_to_rgb_simd(&constants, input, input, input, input, None, &mut target);
+    target[0..8].try_into().unwrap()
+  }
+
+  #[test]
+  fn test_range_extremes() {
+    let (limited, full) = (VideoRange::Limited, VideoRange::Full);
+
+    let limited_white = sample(940, 512, 512, 940);
+    assert_eq!(
+      yuva422_to_rgb_with_range(&limited_white, limited, limited),
+      [255; 8]
+    );
+    let limited_black = sample(64, 512, 512, 64);
+    assert_eq!(
+      yuva422_to_rgb_with_range(&limited_black, limited, limited),
+      [0; 8]
+    );
+
+    let full_white = sample(1023, 512, 512, 1023);
+    assert_eq!(yuva422_to_rgb_with_range(&full_white, full, full), [255; 8]);
+    let full_black = sample(0, 512, 512, 0);
+    assert_eq!(yuva422_to_rgb_with_range(&full_black, full, full), [0; 8]);
+
+    // Limited range codes below black and above white get clipped
+    assert_eq!(
+      yuva422_to_rgb_with_range(&full_black, limited, limited),
+      [0; 8]
+    );
+    assert_eq!(
+      yuva422_to_rgb_with_range(&full_white, limited, limited),
+      [255; 8]
+    );
+
+    // The alpha range is independent of the luma and chroma range
+    let full_alpha = sample(940, 512, 512, 1023);
+    assert_eq!(
+      yuva422_to_rgb_with_range(&full_alpha, limited, full),
+      [255; 8]
+    );
+    let half_alpha = sample(64, 512, 512, 512);
+    assert_eq!(
+      yuva422_to_rgb_with_range(&half_alpha, limited, full),
+      [0, 0, 0, 128, 0, 0, 0, 128]
+    );
+  }
+
+  fn yuva422_to_fill_key_single(input: &[u8; 8], premultiply: bool) -> ([u8; 6], [u8; 2]) {
+    let constants = YuvConstantsSimd::create(0.299, 0.114);
+
+    let (mut fill, mut key) = ([0; 24], [0; 8]);
+    yuva422_to_fill_key_simd(
+      &constants,
+      input,
+      input,
+      input,
+      input,
+      premultiply,
+      &mut fill,
+      &mut key,
+    );
+    (
+      fill[0..6].try_into().unwrap(),
+      key[0..2].try_into().unwrap(),
+    )
+  }
+
+  #[test]
+  fn test_fill_key_matches_rgba() {
+    let samples = [
+      sample(940, 512, 512, 940),
+      sample(64, 512, 512, 64),
+      sample(600, 300, 700, 502),
+      sample(1023, 64, 960, 1023),
+      sample(0, 1023, 0, 0),
+    ];
+    for input in samples {
+      let rgba = yuva422_to_rgb_single(&input);
+      let (fill, key) = yuva422_to_fill_key_single(&input, false);
+      assert_eq!(fill, [rgba[0], rgba[1], rgba[2], rgba[4], rgba[5], rgba[6]]);
+      assert_eq!(key, [rgba[3], rgba[7]]);
+    }
+  }
+
+  #[test]
+  fn test_premultiplied_fill() {
+    // Opaque and transparent pixels are unchanged, or black
+    let white = sample(940, 512, 512, 940);
+    assert_eq!(
+      yuva422_to_fill_key_single(&white, true),
+      ([255; 6], [255; 2])
+    );
+    let transparent = sample(940, 512, 512, 64);
+    assert_eq!(
+      yuva422_to_fill_key_single(&transparent, true),
+      ([0; 6], [0; 2])
+    );
+
+    // Alpha beyond the limits is clipped before it scales the fill
+    let super_white = sample(940, 512, 512, 1023);
+    assert_eq!(
+      yuva422_to_fill_key_single(&super_white, true),
+      ([255; 6], [255; 2])
+    );
+
+    // The fill is scaled by the unrounded key
+    let half = sample(940, 512, 512, 502);
+    assert_eq!(
+      yuva422_to_fill_key_single(&half, true),
+      ([128; 6], [128; 2])
+    );
+    let colour = sample(600, 300, 700, 283);
+    let (fill, [key, _]) = yuva422_to_fill_key_single(&colour, false);
+    let (premultiplied, _) = yuva422_to_fill_key_single(&colour, true);
+    for (value, premultiplied) in fill.into_iter().zip(premultiplied) {
+      let expected = value as f32 * key as f32 / 255.0;
+      assert!(
+        (premultiplied as f32 - expected).abs() <= 1.0,
+        "{premultiplied} {expected}"
+      );
+    }
+  }
+
   // TODO: are these tests any good?
   // They assume lossess cb/cr values, which is not the case because of the 422
 
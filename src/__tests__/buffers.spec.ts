@@ -0,0 +1,119 @@
+import { decodeImageFromAtem, encodeImageForAtem } from '../index'
+
+const width = 16
+const height = 8
+const length = width * height * 4
+
+const rgba = Buffer.alloc(length)
+for (let i = 0; i < rgba.length; i++) {
+	rgba[i] = (i * 37) & 0xff
+}
+const expected = encodeImageForAtem(width, height, rgba, 'rgba').encodedData
+
+/**
+ * Copy the image into the middle of a larger buffer, with different bytes either side of it
+ */
+function embed(buffer: ArrayBuffer | SharedArrayBuffer, offset: number): void {
+	new Uint8Array(buffer).fill(0xff)
+	new Uint8Array(buffer, offset, length).set(rgba)
+}
+
+describe('Buffers', () => {
+	test('Uint8Array with a byte offset', () => {
+		const buffer = new ArrayBuffer(length + 24)
+		embed(buffer, 8)
+		const view = new Uint8Array(buffer, 8, length)
+		expect(encodeImageForAtem(width, height, view, 'rgba').encodedData.equals(expected)).toBe(true)
+
+		// Only the bytes of the view are read, so a view including the padding has the wrong length
+		expect(() => encodeImageForAtem(width, height, new Uint8Array(buffer, 8), 'rgba')).toThrow(
+			'Pixel buffer has incorrect length'
+		)
+	})
+
+	test('Float32Array with a byte offset', () => {
+		const buffer = new ArrayBuffer(length + 24)
+		embed(buffer, 8)
+		const view = new Float32Array(buffer, 8, length / 4)
+		expect(encodeImageForAtem(width, height, view, 'rgba').encodedData.equals(expected)).toBe(true)
+
+		// Float pixels are passed as they are, with 16 bytes per pixel
+		const pixels = new Float32Array(width * height * 4)
+		for (let i = 0; i < pixels.length; i++) {
+			pixels[i] = rgba[i] / 255
+		}
+		const encoded = encodeImageForAtem(width, height, pixels, 'rgbaf32').encodedData
+		expect(encoded.length).toBe(length)
+	})
+
+	test('DataView with a byte offset', () => {
+		const buffer = new ArrayBuffer(length + 24)
+		embed(buffer, 12)
+		const view = new DataView(buffer, 12, length)
+		expect(encodeImageForAtem(width, height, view, 'rgba').encodedData.equals(expected)).toBe(true)
+	})
+
+	test('ArrayBuffer', () => {
+		const buffer = new ArrayBuffer(length)
+		embed(buffer, 0)
+		expect(encodeImageForAtem(width, height, buffer, 'rgba').encodedData.equals(expected)).toBe(true)
+
+		const output = new ArrayBuffer(length)
+		const encoded = encodeImageForAtem(width, height, buffer, 'rgba', { output })
+		expect(encoded.encodedData.buffer).toBe(output)
+		expect(Buffer.from(output).equals(expected)).toBe(true)
+	})
+
+	test('SharedArrayBuffer', () => {
+		const buffer = new SharedArrayBuffer(length + 16)
+		embed(buffer, 16)
+		const view = new Uint8Array(buffer, 16, length)
+		expect(encodeImageForAtem(width, height, view, 'rgba').encodedData.equals(expected)).toBe(true)
+
+		// The output is written in place, around the bytes before and after it
+		const output = new SharedArrayBuffer(length + 16)
+		new Uint8Array(output).fill(0xaa)
+		encodeImageForAtem(width, height, view, 'rgba', { output: new Uint8Array(output, 8, length) })
+		expect(Buffer.from(output, 8, length).equals(expected)).toBe(true)
+		expect(new Uint8Array(output, 0, 8).every((value) => value === 0xaa)).toBe(true)
+		expect(new Uint8Array(output, length + 8, 8).every((value) => value === 0xaa)).toBe(true)
+
+		// A bare SharedArrayBuffer covers all of its memory
+		const bare = new SharedArrayBuffer(length)
+		embed(bare, 0)
+		expect(encodeImageForAtem(width, height, bare, 'rgba').encodedData.equals(expected)).toBe(true)
+	})
+
+	test('decoding into a view with a byte offset', () => {
+		const decoded = decodeImageFromAtem(width, height, expected)
+		const buffer = new ArrayBuffer(length + 16)
+		new Uint8Array(buffer).fill(0xaa)
+		const output = new Uint8Array(buffer, 8, length)
+		expect(decodeImageFromAtem(width, height, expected, { output }).equals(decoded)).toBe(true)
+		expect(new Uint8Array(buffer, 0, 8).every((value) => value === 0xaa)).toBe(true)
+		expect(new Uint8Array(buffer, length + 8, 8).every((value) => value === 0xaa)).toBe(true)
+	})
+
+	test('overlapping input and output', () => {
+		const buffer = new ArrayBuffer(length * 2)
+		embed(buffer, 0)
+		const input = new Uint8Array(buffer, 0, length)
+
+		expect(() => encodeImageForAtem(width, height, input, 'rgba', { output: input })).toThrow(
+			'Input and output buffers must not overlap'
+		)
+		expect(() =>
+			encodeImageForAtem(width, height, input, 'rgba', { output: new Uint8Array(buffer, length - 4, length) })
+		).toThrow('Input and output buffers must not overlap')
+		expect(() =>
+			encodeImageForAtem(width, height, new DataView(buffer, 0, length), 'rgba', {
+				output: new Float32Array(buffer, 4, length / 4),
+			})
+		).toThrow('Input and output buffers must not overlap')
+
+		// Adjacent views of the same memory don't overlap
+		const output = new Uint8Array(buffer, length, length)
+		encodeImageForAtem(width, height, input, 'rgba', { output })
+		expect(Buffer.from(output.buffer, length, length).equals(expected)).toBe(true)
+	})
+})
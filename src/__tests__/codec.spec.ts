@@ -0,0 +1,126 @@
+import * as path from 'path'
+import { Worker } from 'worker_threads'
+import { createImageCodec, decodeImageFromAtem, encodeImageForAtem } from '../index'
+
+const width = 64
+const height = 16
+
+function testImage(seed: number): Buffer {
+	const rgba = Buffer.alloc(width * height * 4)
+	for (let i = 0; i < rgba.length; i++) {
+		rgba[i] = (i * 7 + seed * 31) & 0xff
+	}
+	return rgba
+}
+
+describe('AtemImageCodec', () => {
+	test('encode matches encodeImageForAtem', () => {
+		const codec = createImageCodec({ width, height })
+		for (let seed = 0; seed < 3; seed++) {
+			const rgba = testImage(seed)
+			expect(codec.encode(rgba).equals(encodeImageForAtem(width, height, rgba, 'rgba').encodedData)).toBe(true)
+		}
+	})
+
+	test('decode matches decodeImageFromAtem', () => {
+		const codec = createImageCodec({ width, height, outputFormat: 'rgba16le' })
+		const { encodedData } = encodeImageForAtem(width, height, testImage(0), 'rgba')
+		const expected = decodeImageFromAtem(width, height, encodedData, { format: 'rgba16le' })
+		expect(codec.decode(encodedData).equals(expected)).toBe(true)
+	})
+
+	test('separate input and output strides', () => {
+		// 16-bit input has twice the bytes per pixel of 8-bit output, so each needs its own stride
+		const inputStride = width * 8 + 16
+		const outputStride = width * 4 + 8
+		const codec = createImageCodec({ width, height, inputFormat: 'rgba16le', inputStride, outputStride })
+
+		const rgba = testImage(0)
+		const input = Buffer.alloc(inputStride * height)
+		for (let i = 0; i < width * height * 4; i++) {
+			const pixel = Math.floor(i / 4)
+			const offset = Math.floor(pixel / width) * inputStride + (pixel % width) * 8 + (i % 4) * 2
+			input.writeUInt16LE(rgba[i] * 257, offset)
+		}
+		const encoded = codec.encode(input)
+		const expected = encodeImageForAtem(width, height, input, 'rgba16le', { stride: inputStride }).encodedData
+		expect(encoded.equals(expected)).toBe(true)
+
+		const decoded = codec.decode(encoded)
+		const expectedDecoded = decodeImageFromAtem(width, height, encoded)
+		expect(decoded.length).toBe(outputStride * (height - 1) + width * 4)
+		for (let y = 0; y < height; y++) {
+			const row = decoded.subarray(y * outputStride, y * outputStride + width * 4)
+			expect(row.equals(expectedDecoded.subarray(y * width * 4, (y + 1) * width * 4))).toBe(true)
+		}
+	})
+
+	test('encode into an output buffer', () => {
+		const codec = createImageCodec({ width, height })
+		const rgba = testImage(0)
+		const output = new Uint8Array(width * height * 4)
+		expect(codec.encode(rgba, output)).toBe(output)
+		expect(Buffer.from(output.buffer).equals(codec.encode(rgba))).toBe(true)
+	})
+
+	test('new buffers without reuseBuffers', () => {
+		const codec = createImageCodec({ width, height })
+		const first = codec.encode(testImage(0))
+		const firstCopy = Buffer.from(first)
+		const second = codec.encode(testImage(1))
+		expect(second).not.toBe(first)
+		expect(first.equals(firstCopy)).toBe(true)
+	})
+
+	test('reused buffers are overwritten by the next call', () => {
+		const codec = createImageCodec({ width, height, reuseBuffers: true })
+		const first = codec.encode(testImage(0))
+		const second = codec.encode(testImage(1))
+		expect(second).toBe(first)
+		expect(first.equals(encodeImageForAtem(width, height, testImage(1), 'rgba').encodedData)).toBe(true)
+
+		// Encoding and decoding each have their own buffer
+		const decoded = codec.decode(second)
+		expect(decoded).not.toBe(second)
+		const decodedAgain = codec.decode(encodeImageForAtem(width, height, testImage(2), 'rgba').encodedData)
+		expect(decodedAgain).toBe(decoded)
+		expect(second.equals(encodeImageForAtem(width, height, testImage(1), 'rgba').encodedData)).toBe(true)
+	})
+
+	test('an instance per worker thread', async () => {
+		// An instance can't be passed to another thread, but each worker can create its own and convert into memory
+		// shared with the main thread
+		const workers = 4
+		const inputs = new SharedArrayBuffer(width * height * 4 * workers)
+		const outputs = new SharedArrayBuffer(width * height * 4 * workers)
+		for (let i = 0; i < workers; i++) {
+			testImage(i).copy(Buffer.from(inputs, i * width * height * 4, width * height * 4))
+		}
+
+		const nativeLib = path.join(__dirname, '../../nativeLib')
+		const script = `
+			const { workerData } = require('worker_threads')
+			const { AtemImageCodec } = require(${JSON.stringify(nativeLib)})
+			const { index, width, height, inputs, outputs } = workerData
+			const length = width * height * 4
+			const codec = new AtemImageCodec({ width, height })
+			codec.encode(new Uint8Array(inputs, index * length, length), new Uint8Array(outputs, index * length, length))
+		`
+		await Promise.all(
+			Array.from(
+				{ length: workers },
+				async (_, index) =>
+					new Promise<void>((resolve, reject) => {
+						const worker = new Worker(script, { eval: true, workerData: { index, width, height, inputs, outputs } })
+						worker.on('error', reject)
+						worker.on('exit', (code) => (code === 0 ? resolve() : reject(new Error(`Worker exited with ${code}`))))
+					})
+			)
+		)
+
+		for (let i = 0; i < workers; i++) {
+			const expected = encodeImageForAtem(width, height, testImage(i), 'rgba').encodedData
+			expect(Buffer.from(outputs, i * width * height * 4, width * height * 4).equals(expected)).toBe(true)
+		}
+	})
+})
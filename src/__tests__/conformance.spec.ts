@@ -0,0 +1,140 @@
+import * as crypto from 'crypto'
+import * as fs from 'fs'
+import * as path from 'path'
+import * as zlib from 'zlib'
+import {
+	Arithmetic,
+	decodeImageFromAtem,
+	encodeImageForAtem,
+	hashEncodedImage,
+	stillsEqual,
+	VideoRange,
+} from '../index'
+
+/**
+ * The synthetic conformance corpus, which is generated and also checked by the rust tests in `native/src/conformance.rs`
+ */
+const corpusDirectory = path.join(__dirname, '../../fixtures/conformance')
+
+interface ConformanceStill {
+	name: string
+	width: number
+	height: number
+	range: VideoRange
+	arithmetic: Arithmetic
+	sourceMd5: string
+	yuvaMd5: string
+	rleMd5: string
+	decodedMd5: string
+	/** The RLE compressed still, which is only checked in for the smaller stills */
+	rle?: string
+	/** The decoded image, which is only checked in for the smaller stills */
+	decoded?: string
+}
+
+const manifest: { stills: ConformanceStill[] } = JSON.parse(
+	fs.readFileSync(path.join(corpusDirectory, 'manifest.json'), 'utf8')
+)
+
+function md5(data: Buffer): string {
+	return crypto.createHash('md5').update(data).digest('hex')
+}
+
+function readGz(file: string): Buffer {
+	return zlib.gunzipSync(fs.readFileSync(path.join(corpusDirectory, file)))
+}
+
+/**
+ * The test pattern the corpus is generated from, as in `native/src/conformance.rs`: colour bars over the top half, then
+ * luma and alpha ramps, then blocks of pseudo-random colour and alpha
+ */
+function testPattern(width: number, height: number): Buffer {
+	const bars = [
+		[191, 191, 191],
+		[191, 191, 0],
+		[0, 191, 191],
+		[0, 191, 0],
+		[191, 0, 191],
+		[191, 0, 0],
+		[0, 0, 191],
+		[0, 0, 0],
+	]
+
+	const image = Buffer.alloc(width * height * 4)
+	for (let y = 0; y < height; y++) {
+		for (let x = 0; x < width; x++) {
+			const offset = (y * width + x) * 4
+			const ramp = Math.floor((x * 255) / (width - 1))
+			if (y < Math.floor(height / 2)) {
+				const [r, g, b] = bars[Math.floor((x * 8) / width)]
+				image.set([r, g, b, 255], offset)
+			} else if (y < Math.floor((height * 5) / 8)) {
+				image.set([ramp, ramp, ramp, 255], offset)
+			} else if (y < Math.floor((height * 3) / 4)) {
+				image.set([255, 128, 0, ramp], offset)
+			} else {
+				const block = Math.imul(Math.floor(x / 16), 0x9e3779b9) ^ Math.imul(Math.floor(y / 16), 0x85ebca6b)
+				image.writeUInt32LE(Math.imul(block, 0xc2b2ae35) >>> 0, offset)
+			}
+		}
+	}
+	return image
+}
+
+/**
+ * Read the pixels of a PNG from the corpus, which are always 8-bit RGBA without any filtering
+ */
+function readPng(file: string, width: number, height: number): Buffer {
+	const data = fs.readFileSync(path.join(corpusDirectory, file))
+
+	const chunks: Buffer[] = []
+	for (let offset = 8; offset < data.length; ) {
+		const length = data.readUInt32BE(offset)
+		const type = data.toString('latin1', offset + 4, offset + 8)
+		if (type === 'IHDR') {
+			expect(data.readUInt32BE(offset + 8)).toBe(width)
+			expect(data.readUInt32BE(offset + 12)).toBe(height)
+		} else if (type === 'IDAT') {
+			chunks.push(data.subarray(offset + 8, offset + 8 + length))
+		}
+		offset += length + 12
+	}
+
+	// Each row starts with its filter type, which must be none
+	const rows = zlib.inflateSync(Buffer.concat(chunks))
+	const rowBytes = width * 4
+	const pixels = Buffer.alloc(rowBytes * height)
+	for (let y = 0; y < height; y++) {
+		const rowStart = y * (rowBytes + 1)
+		expect(rows[rowStart]).toBe(0)
+		rows.copy(pixels, y * rowBytes, rowStart + 1, rowStart + 1 + rowBytes)
+	}
+	return pixels
+}
+
+describe('Conformance', () => {
+	test.each(manifest.stills.map((still) => [still.name, still]))('%s', (_name, still) => {
+		const { width, height, range, arithmetic } = still
+		const source = testPattern(width, height)
+		expect(md5(source)).toBe(still.sourceMd5)
+
+		// Encoding the source gives the still, with the md5 as its hash
+		const encoded = encodeImageForAtem(width, height, source, 'rgba', { range, arithmetic })
+		expect(md5(encoded.encodedData)).toBe(still.yuvaMd5)
+		expect(encoded.hash).toBe(Buffer.from(still.yuvaMd5, 'hex').toString('base64'))
+		expect(md5(decodeImageFromAtem(width, height, encoded.encodedData, { range, arithmetic }))).toBe(still.decodedMd5)
+
+		if (still.rle) {
+			// The RLE compressed still holds the same samples, and decodes to the same image
+			const rle = readGz(still.rle)
+			expect(md5(rle)).toBe(still.rleMd5)
+			expect(rle.length).toBeLessThan(encoded.encodedData.length)
+			expect(stillsEqual(encoded.encodedData, rle)).toBe(true)
+			expect(hashEncodedImage(rle)).toBe(encoded.hash)
+			expect(md5(decodeImageFromAtem(width, height, rle, { range, arithmetic }))).toBe(still.decodedMd5)
+		}
+		if (still.decoded) {
+			expect(md5(readPng(still.decoded, width, height))).toBe(still.decodedMd5)
+		}
+	})
+})
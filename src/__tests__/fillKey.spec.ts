@@ -0,0 +1,130 @@
+import * as fs from 'fs'
+import * as path from 'path'
+import * as zlib from 'zlib'
+import {
+	Arithmetic,
+	decodeFillKeyFromAtem,
+	decodeImageFromAtem,
+	encodeFillKeyForAtem,
+	encodeImageForAtem,
+	setDefaultArithmetic,
+} from '../index'
+
+const still = zlib.gunzipSync(fs.readFileSync(path.join(__dirname, '../../fixtures/conformance/525i.rle.yuva.gz')))
+const width = 720
+const height = 486
+const arithmetics: Arithmetic[] = ['float', 'fixed', 'javascript']
+
+/**
+ * Pseudo-random samples, for which the float and fixed-point arithmetic decode a few pixels differently
+ */
+function noiseStill(): Buffer {
+	const data = Buffer.alloc(width * height * 4)
+	let state = 1
+	for (let i = 0; i < data.length; i++) {
+		state = (Math.imul(state, 1664525) + 1013904223) >>> 0
+		data[i] = state >>> 24
+	}
+	return data
+}
+
+function splitRgba(rgba: Buffer): { fill: Buffer; key: Buffer } {
+	const fill = Buffer.alloc(width * height * 3)
+	const key = Buffer.alloc(width * height)
+	for (let i = 0; i < width * height; i++) {
+		rgba.copy(fill, i * 3, i * 4, i * 4 + 3)
+		key[i] = rgba[i * 4 + 3]
+	}
+	return { fill, key }
+}
+
+describe('Fill and key', () => {
+	test.each(arithmetics)('matches RGBA with %s arithmetic', (arithmetic) => {
+		for (const data of [still, noiseStill()]) {
+			const expected = splitRgba(decodeImageFromAtem(width, height, data, { arithmetic }))
+			const { fill, key } = decodeFillKeyFromAtem(width, height, data, { arithmetic })
+			expect(fill.equals(expected.fill)).toBe(true)
+			expect(key.equals(expected.key)).toBe(true)
+		}
+	})
+
+	test('follows the default arithmetic', () => {
+		const data = noiseStill()
+		const fixed = decodeFillKeyFromAtem(width, height, data, { arithmetic: 'fixed' })
+		expect(fixed.fill.equals(decodeFillKeyFromAtem(width, height, data, { arithmetic: 'float' }).fill)).toBe(false)
+
+		setDefaultArithmetic('fixed')
+		try {
+			expect(decodeFillKeyFromAtem(width, height, data).fill.equals(fixed.fill)).toBe(true)
+		} finally {
+			setDefaultArithmetic('float')
+		}
+	})
+
+	test('premultiplied', () => {
+		const { fill, key } = decodeFillKeyFromAtem(width, height, still, { premultiply: true })
+
+		for (let i = 0; i < width * height; i++) {
+			for (let c = 0; c < 3; c++) {
+				expect(fill[i * 3 + c]).toBeLessThanOrEqual(key[i])
+			}
+		}
+	})
+
+	test('short input', () => {
+		expect(() => decodeFillKeyFromAtem(width, height, still.subarray(0, 1000))).toThrow(
+			'Input buffer has less data than expected'
+		)
+	})
+})
+
+describe('Encoding fill and key', () => {
+	const rgba = decodeImageFromAtem(width, height, still)
+	const { fill, key } = splitRgba(rgba)
+
+	test.each(arithmetics)('matches RGBA with %s arithmetic', (arithmetic) => {
+		const expected = encodeImageForAtem(width, height, rgba, 'rgba', { arithmetic })
+		expect(encodeFillKeyForAtem(width, height, fill, key, { arithmetic }).hash).toBe(expected.hash)
+		expect(encodeFillKeyForAtem(width, height, fill, rgba, { keyFormat: 'rgba', arithmetic }).hash).toBe(expected.hash)
+	})
+
+	test('rejects javascript arithmetic for full range', () => {
+		expect(() => encodeFillKeyForAtem(width, height, fill, key, { arithmetic: 'javascript', range: 'full' })).toThrow(
+			'The javascript arithmetic only encodes limited range rgba input'
+		)
+	})
+
+	test('follows the default arithmetic', () => {
+		const fixed = encodeImageForAtem(width, height, rgba, 'rgba', { arithmetic: 'fixed' })
+		expect(encodeFillKeyForAtem(width, height, fill, key, { arithmetic: 'float' }).hash).not.toBe(fixed.hash)
+
+		setDefaultArithmetic('fixed')
+		try {
+			expect(encodeFillKeyForAtem(width, height, fill, key).hash).toBe(fixed.hash)
+		} finally {
+			setDefaultArithmetic('float')
+		}
+	})
+
+	test('round trip', () => {
+		const encoded = encodeFillKeyForAtem(width, height, fill, key)
+		const decoded = decodeFillKeyFromAtem(width, height, encoded.encodedData)
+		expect(decoded.key.equals(key)).toBe(true)
+	})
+
+	test('inverted key', () => {
+		const inverted = Buffer.from(key.map((value) => 255 - value))
+		expect(encodeFillKeyForAtem(width, height, fill, inverted, { invertKey: true }).hash).toBe(
+			encodeFillKeyForAtem(width, height, fill, key).hash
+		)
+	})
+
+	test('invalid key', () => {
+		expect(() => encodeFillKeyForAtem(width, height, fill, key, { keyClip: 2 })).toThrow(
+			'Key clip must be from 0 to 1'
+		)
+		expect(() => encodeFillKeyForAtem(width, height, fill, key, { keyFormat: 'rgb' })).toThrow(
+			'Key buffer has incorrect length'
+		)
+	})
+})
@@ -0,0 +1,112 @@
+import * as crypto from 'crypto'
+import { encodeImageForAtem } from '../index'
+
+/**
+ * `convertRGBAToYUV422` of atem-connection, which the `javascript` arithmetic reproduces
+ */
+function convertRGBAToYUV422(width: number, height: number, data: Buffer): Buffer {
+	// BT.709 or BT.601
+	const KR = height >= 720 ? 0.2126 : 0.299
+	const KB = height >= 720 ? 0.0722 : 0.114
+	const KG = 1 - KR - KB
+
+	const KRi = 1 - KR
+	const KBi = 1 - KB
+
+	const YRange = 219
+	const CbCrRange = 224
+	const HalfCbCrRange = CbCrRange / 2
+
+	const YOffset = 16 << 8
+	const CbCrOffset = 128 << 8
+
+	const KRoKBi = (KR / KBi) * HalfCbCrRange
+	const KGoKBi = (KG / KBi) * HalfCbCrRange
+	const KBoKRi = (KB / KRi) * HalfCbCrRange
+	const KGoKRi = (KG / KRi) * HalfCbCrRange
+
+	const genColor = (rawA: number, uv16: number, y16: number): number => {
+		const a = ((rawA << 2) * 219) / 255 + (16 << 2)
+		const y = Math.round(y16) >> 6
+		const uv = Math.round(uv16) >> 6
+
+		return (a << 20) + (uv << 10) + y
+	}
+
+	const buffer = Buffer.alloc(width * height * 4)
+	let i = 0
+	while (i < width * height * 4) {
+		const r1 = data[i + 0]
+		const g1 = data[i + 1]
+		const b1 = data[i + 2]
+
+		const r2 = data[i + 4]
+		const g2 = data[i + 5]
+		const b2 = data[i + 6]
+
+		const a1 = data[i + 3]
+		const a2 = data[i + 7]
+
+		const y16a = YOffset + KR * YRange * r1 + KG * YRange * g1 + KB * YRange * b1
+		const cb16 = CbCrOffset + (-KRoKBi * r1 - KGoKBi * g1 + HalfCbCrRange * b1)
+		const y16b = YOffset + KR * YRange * r2 + KG * YRange * g2 + KB * YRange * b2
+		const cr16 = CbCrOffset + (HalfCbCrRange * r1 - KGoKRi * g1 - KBoKRi * b1)
+
+		buffer.writeUInt32BE(genColor(a1, cb16, y16a), i)
+		buffer.writeUInt32BE(genColor(a2, cr16, y16b), i + 4)
+		i += 8
+	}
+	return buffer
+}
+
+/**
+ * A random image, which is the same for each seed
+ */
+function randomImage(width: number, height: number, seed: number): Buffer {
+	const image = Buffer.alloc(width * height * 4)
+	let state = seed
+	for (let i = 0; i < image.length; i += 32) {
+		crypto
+			.createHash('sha256')
+			.update(String(state++))
+			.digest()
+			.copy(image, i)
+	}
+	return image
+}
+
+describe('JavaScript compatibility', () => {
+	test.each([
+		[8, 1],
+		[64, 16],
+		[720, 486],
+		[1280, 720],
+		[1920, 1080],
+	])('%ix%i random images', (width, height) => {
+		for (let seed = 0; seed < 4; seed++) {
+			const image = randomImage(width, height, seed)
+			const expected = convertRGBAToYUV422(width, height, image)
+
+			const encoded = encodeImageForAtem(width, height, image, 'rgba', { arithmetic: 'javascript' })
+			expect(encoded.encodedData.equals(expected)).toBe(true)
+			expect(encoded.hash).toBe(crypto.createHash('md5').update(expected).digest('base64'))
+		}
+	})
+
+	test('float arithmetic differs', () => {
+		const image = randomImage(1920, 1080, 0)
+		const expected = convertRGBAToYUV422(1920, 1080, image)
+
+		const encoded = encodeImageForAtem(1920, 1080, image, 'rgba', { arithmetic: 'float' })
+		expect(encoded.encodedData.equals(expected)).toBe(false)
+	})
+
+	test('rejects what atem-connection cannot encode', () => {
+		const message = 'The javascript arithmetic only encodes limited range rgba input'
+		const image = randomImage(8, 1, 0)
+		expect(() => encodeImageForAtem(8, 1, image, 'rgba', { arithmetic: 'javascript', range: 'full' })).toThrow(message)
+
+		const deep = new Uint16Array(8 * 4)
+		expect(() => encodeImageForAtem(8, 1, deep, 'rgba16le', { arithmetic: 'javascript' })).toThrow(message)
+	})
+})
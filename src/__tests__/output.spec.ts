@@ -0,0 +1,68 @@
+import { decodeImageFromAtem, encodeImageForAtem } from '../index'
+
+const width = 64
+const height = 16
+
+const rgba = Buffer.alloc(width * height * 4)
+for (let i = 0; i < rgba.length; i++) {
+	rgba[i] = (i * 7) & 0xff
+}
+
+describe('Output buffers', () => {
+	test('encoding allocates a buffer', () => {
+		const encoded = encodeImageForAtem(width, height, rgba, 'rgba')
+		expect(encoded.encodedData.length).toBe(width * height * 4)
+		expect(encoded.rawDataLength).toBe(width * height * 4)
+	})
+
+	test('encoding into an output buffer', () => {
+		const expected = encodeImageForAtem(width, height, rgba, 'rgba')
+
+		const output = new Uint8Array(width * height * 4).fill(0xaa)
+		const encoded = encodeImageForAtem(width, height, rgba, 'rgba', { output })
+		expect(encoded.encodedData.buffer).toBe(output.buffer)
+		expect(encoded.encodedData.equals(expected.encodedData)).toBe(true)
+		expect(encoded.hash).toBe(expected.hash)
+	})
+
+	test('encoding into a short output buffer', () => {
+		const output = Buffer.alloc(width * height * 4 - 8)
+		expect(() => encodeImageForAtem(width, height, rgba, 'rgba', { output })).toThrow(
+			'Output buffer has incorrect length'
+		)
+	})
+
+	test('decoding allocates a buffer', () => {
+		const { encodedData } = encodeImageForAtem(width, height, rgba, 'rgba')
+		expect(decodeImageFromAtem(width, height, encodedData).length).toBe(width * height * 4)
+		expect(decodeImageFromAtem(width, height, encodedData, { format: 'rgba16le' }).length).toBe(
+			width * height * 8
+		)
+	})
+
+	test('decoding into an output buffer', () => {
+		const { encodedData } = encodeImageForAtem(width, height, rgba, 'rgba')
+		const expected = decodeImageFromAtem(width, height, encodedData)
+
+		const output = Buffer.alloc(width * height * 4, 0xaa)
+		const decoded = decodeImageFromAtem(width, height, encodedData, { output })
+		expect(decoded).toBe(output)
+		expect(decoded.equals(expected)).toBe(true)
+	})
+
+	test('decoding with a stride zero-fills the padding', () => {
+		const { encodedData } = encodeImageForAtem(width, height, rgba, 'rgba')
+		const expected = decodeImageFromAtem(width, height, encodedData)
+
+		const stride = width * 4 + 16
+		const decoded = decodeImageFromAtem(width, height, encodedData, { stride })
+		expect(decoded.length).toBe(stride * (height - 1) + width * 4)
+		for (let y = 0; y < height; y++) {
+			const row = decoded.subarray(y * stride, y * stride + width * 4)
+			expect(row.equals(expected.subarray(y * width * 4, (y + 1) * width * 4))).toBe(true)
+			if (y < height - 1) {
+				expect(decoded.subarray(y * stride + width * 4, (y + 1) * stride).every((v) => v === 0)).toBe(true)
+			}
+		}
+	})
+})
@@ -0,0 +1,25 @@
+/**
+ * Pixel data in any ArrayBuffer-backed value.
+ * Views such as `Uint8Array` or `DataView` only cover their byte offset and length of the underlying memory.
+ * Shared memory is copied before it is read, and written back whole after a conversion into it, so other threads may
+ * keep using it, but any bytes they change in an output's range during the conversion are overwritten
+ */
+export type ImageBuffer = ArrayBufferView | ArrayBuffer | SharedArrayBuffer
+
+/**
+ * Prepare a buffer for passing to the native library.
+ * Node-API can't read a SharedArrayBuffer directly, but it can read a view of one
+ */
+export function toNativeBuffer(data: ImageBuffer): ArrayBufferView | ArrayBuffer {
+	if (typeof SharedArrayBuffer !== 'undefined' && data instanceof SharedArrayBuffer) return new Uint8Array(data)
+	return data
+}
+
+/**
+ * Get a `Buffer` covering the same memory as the provided value, without copying it
+ */
+export function toBuffer(data: ImageBuffer): Buffer {
+	if (Buffer.isBuffer(data)) return data
+	if (ArrayBuffer.isView(data)) return Buffer.from(data.buffer, data.byteOffset, data.byteLength)
+	return Buffer.from(data)
+}
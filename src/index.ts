@@ -1,8 +1,131 @@
 import * as crypto from 'crypto'
 import { UploadBufferInfo } from './copy'
 import { Native } from './nativeLoader'
+import { ImageBuffer, toBuffer, toNativeBuffer } from './buffers'
+import type {
+	AlphaStatistics,
+	AnalyseOptions,
+	AtemImageCodec,
+	AtemImageCodecOptions,
+	Capabilities,
+	CompareOptions,
+	CompareThresholds,
+	FillKeyEncodeOptions,
+	FillKeyOptions,
+	IllegalRegion,
+	LegaliseOptions,
+	LegaliseReport,
+	ScopeOptions,
+	StillAnalysis,
+	StillComparison,
+} from '../nativeLib'
 
 export { NativeError } from './nativeLoader'
+export type {
+	AlphaStatistics,
+	AnalyseOptions,
+	AtemImageCodec,
+	AtemImageCodecOptions,
+	Capabilities,
+	CompareOptions,
+	CompareThresholds,
+	FillKeyEncodeOptions,
+	FillKeyOptions,
+	IllegalRegion,
+	LegaliseOptions,
+	LegaliseReport,
+	ScopeOptions,
+	StillAnalysis,
+	StillComparison,
+}
+export type { ImageBuffer }
+
+/**
+ * The pixel layouts accepted for encoding.
+ * `rgba` has 8 bits per channel, `rgba16le` and `rgba16be` have 16 bits per channel in little or big endian order,
+ * and `rgbaf32` has a 32-bit float per channel in the range 0-1, in the platform byte order like a `Float32Array`.
+ * The deeper formats are converted with the full precision of the 10-bit output, so limited range white is luma 940,
+ * while `rgba` is converted as earlier versions did, with white at 936
+ */
+export type InputFormat = 'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32'
+
+/**
+ * The pixel layouts produced by decoding. Those shared with `InputFormat` have the same form.
+ * `r210` packs 10-bit RGB into a big-endian 32-bit word per pixel with the top 2 bits unused, and `a2r10g10b10`
+ * packs 10-bit RGB and 2-bit alpha into a little-endian 32-bit word per pixel with alpha in the top 2 bits.
+ * The 10-bit components are full range 0-1023.
+ * The deeper formats are converted with the full precision of the 10-bit input, so limited range luma 940 is white,
+ * while `rgba` is converted as earlier versions did
+ */
+export type OutputFormat = 'rgba' | 'rgba16le' | 'rgba16be' | 'rgbaf32' | 'r210' | 'a2r10g10b10'
+
+/**
+ * How the colour values of float pixels are encoded.
+ * `gamma` values are display-referred like 8-bit images, while `linear` values are linear light, and are converted
+ * with the BT.709 transfer function. Alpha is always linear
+ */
+export type TransferFunction = 'gamma' | 'linear'
+
+/**
+ * The pattern used to dither values when rounding them to fewer bits.
+ * `bayer` is an 8x8 ordered matrix, and `blueNoise` is a 32x32 blue noise matrix which is less visible.
+ * The pattern only depends on the position of each pixel, so the output is always the same for the same input
+ */
+export type Dither = 'bayer' | 'blueNoise'
+
+/**
+ * The range of 10-bit codes used for a signal.
+ * `limited` is studio range, with luma and alpha from 64 to 940 and chroma from 64 to 960, and `full` uses every code
+ */
+export type VideoRange = 'limited' | 'full'
+
+/**
+ * The arithmetic used for converting `rgba` pixels.
+ * `float` uses the widest SIMD the CPU supports, while `fixed` uses integer arithmetic which gives the same output on
+ * every architecture, so is suited to comparing hashes of images encoded on different machines.
+ * The two differ by at most 1 code, and in fewer than 1 in 50000 outputs over random images.
+ * `javascript` encodes limited range exactly as the JavaScript converter of atem-connection, so that stills have the
+ * same hash whether or not this library is loaded. Like atem-connection it only encodes limited range `rgba` pixels, so
+ * encoding full range video or deeper input with it is an error, while decoding with it is the same as `float`
+ */
+export type Arithmetic = 'float' | 'fixed' | 'javascript'
+
+/**
+ * The kernels used for converting `rgba` pixels with `float` arithmetic.
+ * `scalar` converts 1 pixel pair at a time, `portable` 4 with SIMD that works on any CPU, and `avx2` and `avx512` 8 and
+ * 16 with those x86 extensions
+ */
+export type SimdPath = 'scalar' | 'portable' | 'avx2' | 'avx512'
+
+function bytesPerPixelForFormat(format: InputFormat): number {
+	switch (format) {
+		case 'rgba':
+			return 4
+		case 'rgba16le':
+		case 'rgba16be':
+			return 8
+		case 'rgbaf32':
+			return 16
+		default:
+			throw new Error(`Unsupported input format "${format}"`)
+	}
+}
+
+/**
+ * Create a codec for converting a sequence of images of the same size, such as the frames of a clip.
+ * The colour constants are computed once, instead of for every frame
+ *
+ * An instance can't be shared between threads or passed to a worker. Each worker thread creates its own instead,
+ * and can convert into a `SharedArrayBuffer` owned by the main thread
+ *
+ * @param options - The dimensions and conversion options of the images
+ * @returns The codec
+ */
+export function createImageCodec(options: AtemImageCodecOptions): AtemImageCodec {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return new Native.AtemImageCodec(options)
+}
 
 /**
  * Convert a RGBA buffer to ATEM YUV422 packing in the correct colorspace
@@ -12,14 +135,35 @@ export { NativeError } from './nativeLoader'
  * @param width - The width of the image
  * @param height - The height of the image
  * @param data - The input RGBA pixel data
+ * @param format - The pixel layout of the input
+ * @param options - Optional output buffer, and layout of the input
  * @returns The output YUVA422 pixel data
  */
-function convertRGBAToYUV422(width: number, height: number, data: Buffer): Buffer {
+function convertRGBAToYUV422(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	format: InputFormat,
+	options: EncodingOptions | undefined
+): Buffer {
 	if (!Native) throw new Error('Library failed to initialise')
 
-	const output = Buffer.alloc(width * height * 4)
-	Native.convertRgbaToYuva422(width, height, data, output)
-	return output
+	const output = options?.output
+	const nativeOptions = {
+		format,
+		stride: options?.stride,
+		transfer: options?.transfer,
+		dither: options?.dither,
+		range: options?.range,
+		alphaRange: options?.alphaRange,
+		arithmetic: options?.arithmetic,
+		simdPath: options?.simdPath,
+		threads: options?.threads,
+	}
+	if (!output) return Native.convertRgbaToYuva422Alloc(width, height, toNativeBuffer(data), nativeOptions)
+
+	Native.convertRgbaToYuva422(width, height, toNativeBuffer(data), toNativeBuffer(output), nativeOptions)
+	return toBuffer(output)
 }
 
 /**
@@ -30,42 +174,224 @@ function convertRGBAToYUV422(width: number, height: number, data: Buffer): Buffe
  * @param width - The width of the image
  * @param height - The height of the image
  * @param data - The input YUVA422 pixel data
+ * @param options - Optional output buffer, and format and layout of the output
  * @returns The output RGBA pixel data
  */
-function convertYUV422ToRGBA(width: number, height: number, data: Buffer): Buffer {
+function convertYUV422ToRGBA(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	options: DecodingOptions | undefined
+): Buffer {
 	if (!Native) throw new Error('Library failed to initialise')
 
-	const output = Buffer.alloc(width * height * 4)
-	Native.convertYuva422ToRgba(width, height, data, output)
-	return output
+	const output = options?.output
+	const nativeOptions = {
+		format: options?.format,
+		stride: options?.stride,
+		transfer: options?.transfer,
+		dither: options?.dither,
+		range: options?.range,
+		alphaRange: options?.alphaRange,
+		arithmetic: options?.arithmetic,
+		simdPath: options?.simdPath,
+		threads: options?.threads,
+	}
+	if (!output) return Native.convertYuva422ToRgbaAlloc(width, height, toNativeBuffer(data), nativeOptions)
+
+	Native.convertYuva422ToRgba(width, height, toNativeBuffer(data), toNativeBuffer(output), nativeOptions)
+	return toBuffer(output)
 }
 
 function generateHashForBuffer(data: Buffer): string {
 	return data ? crypto.createHash('md5').update(data).digest('base64') : ''
 }
 
+/**
+ * Hash the samples of an encoded image, which may be RLE compressed.
+ * The hash only depends on the expanded samples, so an image has the same hash however it was compressed. For an image
+ * which is not RLE compressed it matches the `hash` returned by `encodeImageForAtem`.
+ * Images which expand to more than an 8K still are rejected
+ *
+ * @param data - The encoded YUVA422 image
+ * @returns The base64 encoded MD5 digest
+ */
+export function hashEncodedImage(data: ImageBuffer): string {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.hashYuva422(toNativeBuffer(data)).toString('base64')
+}
+
+/**
+ * Check whether two encoded images, which may be RLE compressed, hold the same picture.
+ * Neither image is expanded into memory. Images which expand to more than an 8K still are rejected
+ *
+ * @param a - The first encoded YUVA422 image
+ * @param b - The second encoded YUVA422 image
+ * @returns Whether the expanded samples are identical
+ */
+export function stillsEqual(a: ImageBuffer, b: ImageBuffer): boolean {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.stillsEqual(toNativeBuffer(a), toNativeBuffer(b))
+}
+
+/**
+ * Report the SIMD support of the CPU, and which kernels are used for converting 8-bit RGBA.
+ * The widest kernels the CPU supports are chosen when the library is first used, unless a narrower path is forced with
+ * the `ATEM_IMAGE_TOOLS_SIMD` environment variable. A forced path is also used for large images, which are otherwise
+ * decoded with lookup tables if those were faster when first timed
+ */
+export function getCapabilities(): Capabilities {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.getCapabilities()
+}
+
+/**
+ * Set the arithmetic used for converting `rgba` pixels when none is given in the options.
+ * `fixed` gives the same output on every architecture, so should be set when the hashes of encoded images are compared
+ * between machines. `javascript` should be set when they are compared with stills encoded by atem-connection, and then
+ * full range video and deeper input must be encoded with another arithmetic given in the options
+ *
+ * @param arithmetic - The arithmetic to use by default
+ */
+export function setDefaultArithmetic(arithmetic: Arithmetic): void {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	Native.setDefaultArithmetic(arithmetic)
+}
+
+export interface EncodedImageInfo extends UploadBufferInfo {
+	/**
+	 * The number of pixels modified to make the image broadcast-safe, when `legalise` was set
+	 */
+	legalisedPixels?: number
+}
+
 export interface EncodingOptions {
 	disableRLE?: boolean
+	/**
+	 * Clip the luma, and reduce the chroma, of the encoded image so that it lies within the EBU R103 broadcast-safe
+	 * limits. The number of modified pixels is returned as `legalisedPixels`
+	 */
+	legalise?: boolean
+	/**
+	 * Buffer to write the encoded image into, instead of allocating a new one.
+	 * It must be `width * height * 4` bytes long, and a `Buffer` of the same memory gets returned as the `encodedData`
+	 */
+	output?: ImageBuffer
+	/**
+	 * The number of bytes from the start of one row of the input to the start of the next, for when the rows are padded.
+	 * Defaults to `width` multiplied by the bytes per pixel of the format
+	 */
+	stride?: number
+	/**
+	 * How the colour values of `rgbaf32` input are encoded. Defaults to `gamma`
+	 */
+	transfer?: TransferFunction
+	/**
+	 * Dither `rgba16le`, `rgba16be` and `rgbaf32` input when rounding it to 10-bit. Defaults to no dithering
+	 */
+	dither?: Dither
+	/**
+	 * The range of the luma and chroma codes. Defaults to `limited`
+	 */
+	range?: VideoRange
+	/**
+	 * The range of the alpha codes. Defaults to `limited`
+	 */
+	alphaRange?: VideoRange
+	/**
+	 * The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic`, which is
+	 * initially `float`
+	 */
+	arithmetic?: Arithmetic
+	/**
+	 * The kernels used for converting `rgba` pixels with `float` arithmetic. Every path gives identical output.
+	 * Defaults to the widest the CPU supports, and a path wider than that is ignored
+	 */
+	simdPath?: SimdPath
+	/**
+	 * The number of threads the image is split between, which gives identical output. 0 uses one per CPU core, which is
+	 * also the most that are used. Defaults to 1, converting on the calling thread
+	 */
+	threads?: number
+}
+
+export interface DecodingOptions {
+	/**
+	 * The pixel layout of the output. Defaults to `rgba`
+	 */
+	format?: OutputFormat
+	/**
+	 * Buffer to write the decoded RGBA image into, instead of allocating a new one.
+	 * It must be `width * height` multiplied by the bytes per pixel of the format long, or long enough for the `stride`,
+	 * and a `Buffer` of the same memory gets returned
+	 */
+	output?: ImageBuffer
+	/**
+	 * The number of bytes from the start of one row of the output to the start of the next, for when the rows are padded.
+	 * Defaults to `width` multiplied by the bytes per pixel of the format
+	 */
+	stride?: number
+	/**
+	 * How the colour values of `rgbaf32` output are encoded. Defaults to `gamma`
+	 */
+	transfer?: TransferFunction
+	/**
+	 * Dither `rgba` output when rounding it to 8-bit. Defaults to no dithering
+	 */
+	dither?: Dither
+	/**
+	 * The range of the luma and chroma codes. Defaults to `limited`
+	 */
+	range?: VideoRange
+	/**
+	 * The range of the alpha codes. Defaults to `limited`
+	 */
+	alphaRange?: VideoRange
+	/**
+	 * The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic`, which is
+	 * initially `float`
+	 */
+	arithmetic?: Arithmetic
+	/**
+	 * The kernels used for converting `rgba` pixels with `float` arithmetic. Every path gives identical output.
+	 * Defaults to the widest the CPU supports, and a path wider than that is ignored
+	 */
+	simdPath?: SimdPath
+	/**
+	 * The number of threads the image is split between, which gives identical output. 0 uses one per CPU core, which is
+	 * also the most that are used. Defaults to 1, converting on the calling thread
+	 */
+	threads?: number
 }
 
 export function encodeImageForAtem(
 	width: number,
 	height: number,
-	data: Buffer,
-	format: 'rgba',
-	_options?: EncodingOptions
-): UploadBufferInfo {
-	const expectedLength = width * height * 4
-	if (data.length !== expectedLength)
-		throw new Error(`Pixel buffer has incorrect length. Received ${data.length} expected ${expectedLength}`)
+	data: ImageBuffer,
+	format: InputFormat,
+	options?: EncodingOptions
+): EncodedImageInfo {
+	const bytesPerPixel = bytesPerPixelForFormat(format)
+	if (options?.stride !== undefined) {
+		// The final row does not need to be padded
+		const minimumLength = options.stride * (height - 1) + width * bytesPerPixel
+		if (data.byteLength < minimumLength)
+			throw new Error(`Pixel buffer is too short. Received ${data.byteLength} expected ${minimumLength}`)
+	} else {
+		const expectedLength = width * height * bytesPerPixel
+		if (data.byteLength !== expectedLength)
+			throw new Error(`Pixel buffer has incorrect length. Received ${data.byteLength} expected ${expectedLength}`)
+	}
 
-	let encodedData: Buffer
-	switch (format) {
-		case 'rgba':
-			encodedData = convertRGBAToYUV422(width, height, data)
-			break
-		default:
-			throw new Error(`Unsupported input format "${format}"`)
+	const encodedData = convertRGBAToYUV422(width, height, data, format, options)
+
+	let legalisedPixels: number | undefined
+	if (options?.legalise) {
+		legalisedPixels = legaliseImage(width, height, encodedData, { range: options.range }).modifiedPixels
 	}
 
 	return {
@@ -73,11 +399,288 @@ export function encodeImageForAtem(
 		rawDataLength: encodedData.length,
 		isRleEncoded: false, // TODO: RLE
 		hash: generateHashForBuffer(encodedData),
+		legalisedPixels,
 	}
 }
 
-export function decodeImageFromAtem(width: number, height: number, data: Buffer): Buffer {
+/**
+ * Clip the luma, and reduce the chroma, of an encoded image, so that it lies within the EBU R103 broadcast-safe limits.
+ * This modifies the image in place
+ *
+ * The image must not be RLE compressed. Chroma is shared by each pair of pixels, so it gets reduced by the smallest
+ * amount which makes both pixels legal, keeping the hue
+ *
+ * @param width - The width of the image
+ * @param height - The height of the image
+ * @param data - The encoded YUVA422 image
+ * @param options - The range of the image, and whether to return a map of the modified pixels
+ * @returns The number of modified pixels, and the optional map
+ */
+export function legaliseImage(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	options?: LegaliseOptions
+): LegaliseReport {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.legaliseYuva422(width, height, toNativeBuffer(data), options)
+}
+
+/**
+ * Report the levels of a still image as it would be sent to the ATEM, for quality control.
+ * RGBA input is encoded with the same maths as `encodeImageForAtem` before being analysed
+ *
+ * The report contains histograms of the 10-bit luma and chroma codes, the pixels outside of the EBU R103
+ * broadcast-safe limits and the bounding boxes of the regions they form, and statistics about the alpha
+ *
+ * @param width - The width of the image
+ * @param height - The height of the image
+ * @param data - The image, either encoded YUVA422 (which may be RLE compressed) or RGBA
+ * @param options - The format and range of the image
+ * @returns The analysis of the image
+ */
+export function analyseStill(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	options?: AnalyseOptions
+): StillAnalysis {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.analyseStill(width, height, toNativeBuffer(data), options)
+}
+
+/**
+ * The scopes which can be rendered from an image
+ */
+export type ScopeKind = 'waveform' | 'parade' | 'vectorscope' | 'histogram' | 'rgbHistogram'
+
+/**
+ * Render a waveform, RGB parade, vectorscope or histogram of an image, as an RGBA image of the requested size.
+ * The samples are read directly, so the image does not need decoding to RGB first
+ *
+ * @param width - The width of the image
+ * @param height - The height of the image
+ * @param data - The encoded YUVA422 image, which may be RLE compressed
+ * @param kind - The scope to render
+ * @param scopeWidth - The width of the rendered scope
+ * @param scopeHeight - The height of the rendered scope
+ * @param options - The range of the image, and the saturation of the vectorscope targets
+ * @returns The RGBA pixel data of the scope
+ */
+export function renderScope(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	kind: ScopeKind,
+	scopeWidth: number,
+	scopeHeight: number,
+	options?: ScopeOptions
+): Buffer {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.renderScope(width, height, toNativeBuffer(data), kind, scopeWidth, scopeHeight, options)
+}
+
+/**
+ * Compare two stills of the same size, such as a source image and the same image downloaded from an ATEM.
+ * Encoded images are decoded first, so differences in RLE compression are ignored
+ *
+ * The comparison measures PSNR, SSIM and the CIEDE2000 colour difference, and passes if every measurement is within
+ * the thresholds
+ *
+ * @param width - The width of the images
+ * @param height - The height of the images
+ * @param a - The first image, either encoded YUVA422 or RGBA
+ * @param b - The second image, either encoded YUVA422 or RGBA
+ * @param options - The format of each image, and the thresholds
+ * @returns The measurements, the heat map, and whether the comparison passed
+ */
+export function compareStills(
+	width: number,
+	height: number,
+	a: ImageBuffer,
+	b: ImageBuffer,
+	options?: CompareOptions
+): StillComparison {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	return Native.compareStills(width, height, toNativeBuffer(a), toNativeBuffer(b), options)
+}
+
+export function decodeImageFromAtem(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	options?: DecodingOptions
+): Buffer {
 	// RLE means the input data could have any length, we can't check that here
 
-	return convertYUV422ToRGBA(width, height, data)
+	return convertYUV422ToRGBA(width, height, data, options)
+}
+
+/**
+ * Convert separate fill and key images to an ATEM YUVA422 image, with the key as the alpha.
+ * This is the same as combining them into RGBA for `encodeImageForAtem` with the same arithmetic, but without the
+ * intermediate image
+ *
+ * This is performed synchronously
+ *
+ * @param width - The width of the image
+ * @param height - The height of the image
+ * @param fill - The 8-bit RGB fill, of 3 bytes per pixel
+ * @param key - The key, either 8-bit greyscale, or RGB keyed by its luma, or RGBA keyed by its alpha
+ * @param options - The format of the key, how to adjust it, and the arithmetic
+ * @returns The encoded image
+ */
+export function encodeFillKeyForAtem(
+	width: number,
+	height: number,
+	fill: ImageBuffer,
+	key: ImageBuffer,
+	options?: FillKeyEncodeOptions
+): EncodedImageInfo {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	// Every byte gets written, so the buffer doesn't need to be zero-filled
+	const encodedData = Buffer.allocUnsafe(width * height * 4)
+	Native.convertFillKeyToYuva422(width, height, toNativeBuffer(fill), toNativeBuffer(key), encodedData, options)
+
+	return {
+		encodedData: encodedData, // TODO: RLE
+		rawDataLength: encodedData.length,
+		isRleEncoded: false, // TODO: RLE
+		hash: generateHashForBuffer(encodedData),
+	}
+}
+
+export interface DecodedFillKey {
+	/**
+	 * The 8-bit RGB fill, of 3 bytes per pixel
+	 */
+	fill: Buffer
+	/**
+	 * The 8-bit key, of 1 byte per pixel
+	 */
+	key: Buffer
+}
+
+/**
+ * Convert an ATEM YUVA422 buffer to separate fill and key images, as the ATEM media player outputs them for external
+ * keyers. The key is a luma-only matte decoded from the alpha
+ *
+ * This is performed synchronously
+ *
+ * @param width - The width of the image
+ * @param height - The height of the image
+ * @param data - The input YUVA422 pixel data, which may be RLE compressed
+ * @param options - The range of the image, whether to premultiply the fill, and the arithmetic
+ * @returns The fill and key images
+ */
+export function decodeFillKeyFromAtem(
+	width: number,
+	height: number,
+	data: ImageBuffer,
+	options?: FillKeyOptions
+): DecodedFillKey {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	// Every byte gets written, so the buffers don't need to be zero-filled
+	const fill = Buffer.allocUnsafe(width * height * 3)
+	const key = Buffer.allocUnsafe(width * height)
+	Native.convertYuva422ToFillKey(width, height, toNativeBuffer(data), fill, key, options)
+
+	return { fill, key }
+}
+
+export interface RegionEncodingOptions {
+	/**
+	 * The pixel layout of the region input. Defaults to `rgba`
+	 */
+	format?: InputFormat
+	/**
+	 * The number of bytes from the start of one row of the region input to the start of the next, for when the rows are padded.
+	 * Defaults to `width` multiplied by the bytes per pixel of the format
+	 */
+	stride?: number
+	/**
+	 * How the colour values of `rgbaf32` input are encoded. Defaults to `gamma`
+	 */
+	transfer?: TransferFunction
+	/**
+	 * Dither `rgba16le`, `rgba16be` and `rgbaf32` input when rounding it to 10-bit. Defaults to no dithering
+	 */
+	dither?: Dither
+	/**
+	 * The range of the luma and chroma codes. Defaults to `limited`
+	 */
+	range?: VideoRange
+	/**
+	 * The range of the alpha codes. Defaults to `limited`
+	 */
+	alphaRange?: VideoRange
+	/**
+	 * The arithmetic used for converting `rgba` pixels. Defaults to the one set with `setDefaultArithmetic`, which is
+	 * initially `float`
+	 */
+	arithmetic?: Arithmetic
+}
+
+/**
+ * Convert a RGBA image, and write it over a rectangle of an existing encoded image.
+ * This allows for updating part of a cached image, without converting the whole image again
+ *
+ * The existing image must not be RLE compressed, and gets modified in place
+ *
+ * @param target - The encoded YUVA422 image to write the region into
+ * @param targetWidth - The width of the target image
+ * @param targetHeight - The height of the target image
+ * @param x - The x position of the region in the target image
+ * @param y - The y position of the region in the target image
+ * @param data - The input RGBA pixel data of the region
+ * @param width - The width of the region
+ * @param height - The height of the region
+ * @returns The updated image, with a recomputed hash
+ */
+export function encodeRegion(
+	target: ImageBuffer,
+	targetWidth: number,
+	targetHeight: number,
+	x: number,
+	y: number,
+	data: ImageBuffer,
+	width: number,
+	height: number,
+	options?: RegionEncodingOptions
+): UploadBufferInfo {
+	if (!Native) throw new Error('Library failed to initialise')
+
+	Native.convertRgbaRegionToYuva422(
+		toNativeBuffer(target),
+		targetWidth,
+		targetHeight,
+		x,
+		y,
+		toNativeBuffer(data),
+		width,
+		height,
+		{
+			format: options?.format,
+			stride: options?.stride,
+			transfer: options?.transfer,
+			dither: options?.dither,
+			range: options?.range,
+			alphaRange: options?.alphaRange,
+			arithmetic: options?.arithmetic,
+		}
+	)
+
+	const encodedData = toBuffer(target)
+	return {
+		encodedData: encodedData,
+		rawDataLength: encodedData.length,
+		isRleEncoded: false,
+		hash: generateHashForBuffer(encodedData),
+	}
 }